@@ -31,13 +31,12 @@ impl Download {
     /// performed by `try_from`:
     ///
     /// ```no_run
-    /// # use color_eyre::{eyre::Report, Result};
-    /// use trauma::download::Download;
+    /// use startmc_downloader::Download;
     /// use reqwest::Url;
     ///
-    /// # fn main() -> Result<(), Report> {
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
     /// Download::try_from("https://example.com/file-0.1.2.zip")?;
-    /// Download::new(&Url::parse("https://example.com/file-0.1.2.zip")?, "file-0.1.2.zip");
+    /// Download::new(&Url::parse("https://example.com/file-0.1.2.zip")?, "file-0.1.2.zip", None);
     /// # Ok(())
     /// # }
     /// ```
@@ -98,7 +97,7 @@ impl TryFrom<&Url> for Download {
                     value
                 ))
             })?
-            .last()
+            .next_back()
             .map(String::from)
             .map(|filename| Download {
                 url: value.clone(),
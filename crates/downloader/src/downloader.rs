@@ -27,7 +27,7 @@ pub struct TimeTrace;
 ///
 /// ```rust
 /// # fn main()  {
-/// use trauma::downloader::DownloaderBuilder;
+/// use startmc_downloader::DownloaderBuilder;
 ///
 /// let d = DownloaderBuilder::new().build();
 /// # }
@@ -140,10 +140,10 @@ impl Downloader {
             can_resume,
         );
         let output = PathBuf::from(&download.filename);
-        if !output.exists() {
-            if let Err(e) = std::fs::create_dir_all(output.parent().unwrap()) {
-                return summary.fail(e);
-            }
+        if !output.exists()
+            && let Err(e) = std::fs::create_dir_all(output.parent().unwrap())
+        {
+            return summary.fail(e);
         }
 
         // If resumable is turned on...
@@ -201,12 +201,12 @@ impl Downloader {
         };
 
         // Check wether or not we need to download the file.
-        if let Some(content_length) = content_length {
-            if content_length == size_on_disk {
-                return summary.with_status(Status::Skipped(
-                    "the file was already fully downloaded".into(),
-                ));
-            }
+        if let Some(content_length) = content_length
+            && content_length == size_on_disk
+        {
+            return summary.with_status(Status::Skipped(
+                "the file was already fully downloaded".into(),
+            ));
         }
 
         // Check the status for errors.
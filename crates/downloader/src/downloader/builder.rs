@@ -4,9 +4,9 @@ use super::*;
 ///
 /// ```rust
 /// # fn main()  {
-/// use trauma::downloader::DownloaderBuilder;
+/// use startmc_downloader::DownloaderBuilder;
 ///
-/// let d = DownloaderBuilder::new().retries(5).directory("downloads".into()).build();
+/// let d = DownloaderBuilder::new().retries(5).concurrent_downloads(10).build();
 /// # }
 /// ```
 pub struct DownloaderBuilder(Downloader);
@@ -62,7 +62,7 @@ impl DownloaderBuilder {
     ///
     /// ```
     /// use reqwest::header::{self, HeaderValue, HeaderMap};
-    /// use trauma::downloader::DownloaderBuilder;
+    /// use startmc_downloader::DownloaderBuilder;
     ///
     /// let ua = HeaderValue::from_str("curl/7.87").expect("Invalid UA");
     ///
@@ -90,7 +90,7 @@ impl DownloaderBuilder {
     ///
     /// ```
     /// use reqwest::header::{self, HeaderValue};
-    /// use trauma::downloader::DownloaderBuilder;
+    /// use startmc_downloader::DownloaderBuilder;
     ///
     /// const FIREFOX_UA: &str =
     /// "Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/109.0";
@@ -9,6 +9,7 @@ pub struct FabricVersionGame {
     pub intermediary: FabricVersion,
 }
 
+/// Also what Quilt's meta has for a loader version of a game version, see [`QUILT_VERSIONS_GAME`]
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct FabricVerisonGameLoader {
@@ -29,6 +30,8 @@ pub struct LoaderFabricVersion {
 pub struct FabricVersion {
     pub maven: String,
     pub version: String,
+    /// Quilt doesn't say
+    #[serde(default)]
     pub stable: bool,
 }
 
@@ -52,8 +55,12 @@ pub struct FabricLibraries {
 #[derive(Deserialize, Debug)]
 pub struct FabricLibrary {
     pub name: String,
+    /// The Maven repository the library is in
     pub url: String,
+    // Quilt only has the name and repository
+    #[serde(default)]
     pub sha1: String,
+    #[serde(default)]
     pub size: u64,
 }
 
@@ -70,3 +77,8 @@ pub const FABRIC_VERSIONS_LOADER: &str = "https://meta.fabricmc.net/v2/versions/
 ///
 /// Or concat with `/GAMEVERSION` to get [`FabricVersionsGame`]
 pub const FABRIC_VERSIONS_GAME: &str = "https://meta.fabricmc.net/v2/versions/loader";
+
+pub const QUILT_MAVEN: &str = "https://maven.quiltmc.org/repository/release/";
+
+/// Concat with `/GAMEVERSION/LOADERVERSION` to get [`FabricVerisonGameLoader`] of Quilt
+pub const QUILT_VERSIONS_GAME: &str = "https://meta.quiltmc.org/v3/versions/loader";
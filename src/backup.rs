@@ -0,0 +1,365 @@
+//! Backups of the worlds of an instance, as zips in the user's data directory.
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    path::{Component, Path, PathBuf},
+};
+
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, Utc};
+use color_eyre::{
+    Result,
+    eyre::{Context, bail},
+};
+use zip::{ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+use crate::{config::BackupConfig, modpack::extract_dir, sync::instance_key, util::walk_files};
+
+const DATE_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// A backup of one world.
+#[derive(Debug, Clone)]
+pub struct Backup {
+    pub world: String,
+    /// The name of the backup, unique among the backups of its world
+    pub id: String,
+    pub date: DateTime<Utc>,
+    pub path: PathBuf,
+    /// Size of the archive in bytes
+    pub size: u64,
+}
+
+/// The backups of the instance in `minecraft_directory`.
+pub struct Backups {
+    /// Where the backups are kept, with a directory for each world
+    pub dir: PathBuf,
+    pub minecraft_directory: PathBuf,
+    pub policy: BackupConfig,
+}
+
+impl Backups {
+    pub fn new(minecraft_directory: &Path, policy: &BackupConfig) -> Self {
+        Self {
+            dir: dirs::data_dir()
+                .expect("data directory not found")
+                .join("startmc")
+                .join("backups")
+                .join(instance_key(minecraft_directory)),
+            minecraft_directory: minecraft_directory.to_path_buf(),
+            policy: policy.clone(),
+        }
+    }
+
+    fn saves(&self) -> PathBuf {
+        self.minecraft_directory.join("saves")
+    }
+
+    /// Names of the worlds in the instance, sorted.
+    pub fn worlds(&self) -> Result<Vec<String>> {
+        crate::world::folders(&self.minecraft_directory)
+    }
+
+    /// Back up the world named `world`, then delete backups of it that the retention policy
+    /// doesn't keep.
+    pub fn create(&self, world: &str) -> Result<Backup> {
+        let backup = self.write(world)?;
+        self.prune(world)?;
+        Ok(backup)
+    }
+
+    fn write(&self, world: &str) -> Result<Backup> {
+        check_world_name(world)?;
+        let source = self.saves().join(world);
+        if !source.is_dir() {
+            bail!("there is no world named {world} in the instance");
+        }
+        let dir = self.dir.join(world);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("create backup directory {}", dir.display()))?;
+
+        let date = Utc::now();
+        let base = date.format(DATE_FORMAT).to_string();
+        let mut id = base.clone();
+        let mut n = 1;
+        while dir.join(format!("{id}.zip")).exists() {
+            n += 1;
+            id = format!("{base}-{n}");
+        }
+        let path = dir.join(format!("{id}.zip"));
+        // written next to the backup first, so that a failed backup isn't listed
+        let temp = path.with_extension("zip.tmp");
+        write_archive(&source, world, File::create(&temp)?)
+            .with_context(|| format!("back up {world}"))?;
+        std::fs::rename(&temp, &path)?;
+
+        Ok(Backup {
+            world: world.to_string(),
+            id,
+            date,
+            size: std::fs::metadata(&path)?.len(),
+            path,
+        })
+    }
+
+    /// Backups of `world`, or of every world if `None`, newest first.
+    pub fn list(&self, world: Option<&str>) -> Result<Vec<Backup>> {
+        if !self.dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let worlds = match world {
+            Some(world) => {
+                check_world_name(world)?;
+                vec![world.to_string()]
+            }
+            None => std::fs::read_dir(&self.dir)?
+                .map(|it| Ok(it?.file_name().to_string_lossy().into_owned()))
+                .collect::<std::io::Result<Vec<_>>>()?,
+        };
+
+        let mut backups = vec![];
+        for world in worlds {
+            let dir = self.dir.join(&world);
+            if !dir.is_dir() {
+                continue;
+            }
+            for entry in std::fs::read_dir(&dir)? {
+                let path = entry?.path();
+                let Some(id) = path
+                    .file_name()
+                    .and_then(|it| it.to_str())
+                    .and_then(|it| it.strip_suffix(".zip"))
+                else {
+                    continue;
+                };
+                let stamp = id.get(..15).unwrap_or(id);
+                let Ok(date) = NaiveDateTime::parse_from_str(stamp, DATE_FORMAT) else {
+                    continue;
+                };
+                backups.push(Backup {
+                    world: world.clone(),
+                    id: id.to_string(),
+                    date: date.and_utc(),
+                    size: std::fs::metadata(&path)?.len(),
+                    path,
+                });
+            }
+        }
+        backups.sort_by(|a, b| (b.date, &b.id, &a.world).cmp(&(a.date, &a.id, &b.world)));
+        Ok(backups)
+    }
+
+    /// Find a backup of `world` by id, or its newest backup.
+    pub fn find(&self, world: &str, id: Option<&str>) -> Result<Backup> {
+        let backups = self.list(Some(world))?;
+        let backup = match id {
+            Some(id) => backups.into_iter().find(|it| it.id == id),
+            None => backups.into_iter().next(),
+        };
+        match (backup, id) {
+            (Some(backup), _) => Ok(backup),
+            (None, Some(id)) => bail!("there is no backup {id} of {world}"),
+            (None, None) => bail!("there are no backups of {world}"),
+        }
+    }
+
+    /// Replace the world with the contents of `backup`. The world is backed up first if it
+    /// exists, so restoring can be undone too. That backup isn't subject to the retention
+    /// policy until the next one, so that it can't delete `backup`.
+    pub fn restore(&self, backup: &Backup) -> Result<()> {
+        check_world_name(&backup.world)?;
+        let target = self.saves().join(&backup.world);
+        let mut archive = ZipArchive::new(File::open(&backup.path)?)
+            .with_context(|| format!("read backup {}", backup.path.display()))?;
+        if target.exists() {
+            self.write(&backup.world)?;
+            std::fs::remove_dir_all(&target)
+                .with_context(|| format!("remove {}", target.display()))?;
+        }
+        std::fs::create_dir_all(&target)?;
+        extract_dir(&mut archive, &backup.world, &target)?;
+        Ok(())
+    }
+
+    /// Delete backups of `world` that the retention policy doesn't keep. Returns the deleted
+    /// backups.
+    pub fn prune(&self, world: &str) -> Result<Vec<Backup>> {
+        let backups = self.list(Some(world))?;
+        let dates = backups
+            .iter()
+            .map(|it| it.date.with_timezone(&Local).naive_local())
+            .collect::<Vec<_>>();
+        let kept = retained(&dates, &self.policy);
+        let mut pruned = vec![];
+        for (backup, kept) in backups.into_iter().zip(kept) {
+            if !kept {
+                debug!("deleting backup {}", backup.path.display());
+                std::fs::remove_file(&backup.path)?;
+                pruned.push(backup);
+            }
+        }
+        Ok(pruned)
+    }
+}
+
+/// Refuse anything but a plain directory name as the name of a world, so that backing up or
+/// restoring can't reach outside of `saves` or the backup directory.
+fn check_world_name(world: &str) -> Result<()> {
+    let mut components = Path::new(world).components();
+    if !matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) {
+        bail!("{world:?} isn't the name of a world");
+    }
+    Ok(())
+}
+
+/// Zip everything in the world directory `source` into a directory named `world`.
+fn write_archive(source: &Path, world: &str, file: File) -> Result<()> {
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default();
+    let mut files = walk_files(source)?;
+    files.sort();
+    for path in files {
+        // the game holds this while the world is open, and recreates it anyway
+        if path.file_name().is_some_and(|it| it == "session.lock") {
+            continue;
+        }
+        let name = path
+            .strip_prefix(source)?
+            .components()
+            .map(|it| it.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        zip.start_file(format!("{world}/{name}"), options)?;
+        let mut file = File::open(&path).with_context(|| format!("open {}", path.display()))?;
+        std::io::copy(&mut file, &mut zip)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// Which of the backups made at `dates`, newest first, `policy` keeps.
+fn retained(dates: &[NaiveDateTime], policy: &BackupConfig) -> Vec<bool> {
+    if policy.keep_last.is_none() && policy.keep_daily.is_none() && policy.keep_weekly.is_none() {
+        return vec![true; dates.len()];
+    }
+    let mut kept = vec![false; dates.len()];
+    for it in kept.iter_mut().take(policy.keep_last.unwrap_or(0)) {
+        *it = true;
+    }
+    // the newest backup of each of the last `n` periods
+    let mut keep_periods = |n: usize, period: &dyn Fn(&NaiveDateTime) -> (i32, u32)| {
+        let mut seen = HashSet::new();
+        for (i, date) in dates.iter().enumerate() {
+            if seen.len() == n && !seen.contains(&period(date)) {
+                break;
+            }
+            if seen.insert(period(date)) {
+                kept[i] = true;
+            }
+        }
+    };
+    if let Some(n) = policy.keep_daily {
+        keep_periods(n, &|it| (it.year(), it.ordinal()));
+    }
+    if let Some(n) = policy.keep_weekly {
+        keep_periods(n, &|it| (it.iso_week().year(), it.iso_week().week()));
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backups(root: &Path) -> Backups {
+        Backups {
+            dir: root.join("backups"),
+            minecraft_directory: root.join("instance"),
+            policy: BackupConfig::default(),
+        }
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let root = tempfile::tempdir().unwrap();
+        let backups = backups(root.path());
+        let world = root.path().join("instance/saves/New World");
+        std::fs::create_dir_all(world.join("region")).unwrap();
+        std::fs::write(world.join("level.dat"), "level").unwrap();
+        std::fs::write(world.join("region/r.0.0.mca"), "region").unwrap();
+        std::fs::write(world.join("session.lock"), "").unwrap();
+
+        assert_eq!(backups.worlds().unwrap(), vec!["New World"]);
+        let backup = backups.create("New World").unwrap();
+        assert!(backup.size > 0);
+        assert!(backups.create("Old World").is_err());
+
+        std::fs::write(world.join("level.dat"), "broken").unwrap();
+        std::fs::remove_file(world.join("region/r.0.0.mca")).unwrap();
+        let found = backups.find("New World", Some(&backup.id)).unwrap();
+        backups.restore(&found).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(world.join("level.dat")).unwrap(),
+            "level"
+        );
+        assert!(world.join("region/r.0.0.mca").is_file());
+        assert!(!world.join("session.lock").exists());
+
+        // the broken world was backed up before restoring
+        let listed = backups.list(None).unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(backups.find("New World", None).unwrap().id, listed[0].id);
+        assert!(backups.find("New World", Some("19700101-000000")).is_err());
+
+        for name in ["..", "../..", "New World/../..", "/tmp", "."] {
+            assert!(backups.create(name).is_err(), "{name}");
+            assert!(backups.list(Some(name)).is_err(), "{name}");
+        }
+        let outside = Backup {
+            world: "..".to_string(),
+            ..found
+        };
+        assert!(backups.restore(&outside).is_err());
+        assert!(root.path().join("instance/saves").is_dir());
+    }
+
+    #[test]
+    fn test_retained() {
+        let dates = [
+            "2025-03-10 18:00",
+            "2025-03-10 12:00",
+            "2025-03-09 12:00",
+            "2025-03-08 12:00",
+            "2025-03-01 12:00",
+            "2025-02-20 12:00",
+        ]
+        .map(|it| NaiveDateTime::parse_from_str(it, "%Y-%m-%d %H:%M").unwrap());
+
+        assert!(
+            retained(&dates, &BackupConfig::default())
+                .iter()
+                .all(|it| *it)
+        );
+        let policy = BackupConfig {
+            keep_last: Some(1),
+            keep_daily: Some(2),
+            keep_weekly: Some(3),
+            ..Default::default()
+        };
+        // the newest of each day and week, and 03-08 is in the same week as 03-09
+        assert_eq!(
+            retained(&dates, &policy),
+            vec![true, false, true, false, true, false]
+        );
+        let policy = BackupConfig {
+            keep_weekly: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            retained(&dates, &policy),
+            vec![true, false, true, false, false, false]
+        );
+    }
+}
@@ -1,8 +1,7 @@
 use std::ffi::OsString;
 
-use crate::sync::SyncFilter;
+use crate::sync::{IndexEntryKind, SearchSort, SyncFilter};
 use clap::*;
-use ferinth::structures::search::Sort;
 
 /// The main CLI struct.
 ///
@@ -15,11 +14,27 @@ pub struct Cli {
 
 #[derive(Debug)]
 pub enum CliCommand {
-    Run,
+    Run(CliRun),
     Init(CliInit),
     Sync(CliSync),
     Upgrade(CliUpgrade),
     Remove(CliRemove),
+    Query(CliQuery),
+    Database(CliDatabase),
+    Export(CliExport),
+    History(CliHistory),
+    Backup(CliBackup),
+    Worlds(CliWorlds),
+    Servers(CliServers),
+    Instance(CliInstance),
+    Config(CliConfig),
+}
+
+#[derive(Debug)]
+pub struct CliRun {
+    /// Check that the dependencies of every mod are installed, and that no world was saved by
+    /// a newer version of the game, before launching
+    pub check: bool,
 }
 
 #[derive(Debug)]
@@ -31,21 +46,35 @@ pub struct CliInit {
     pub directory: Option<String>,
     pub vanilla: bool,
     pub username: Option<String>,
+    /// Modpack to create the instance from
+    pub pack: Option<String>,
+    pub curseforge_key: Option<String>,
+    /// Stand-in for the CurseForge API, see [`crate::sync::curseforge::CurseForgeConfig`]
+    pub curseforge_url: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct CliSync {
     pub refresh: bool,
+    /// `-yy`: also look up files that no provider knew at the last refresh
+    pub force_refresh: bool,
     pub upgrade: bool,
+    pub no_ignore: bool,
+    /// Rebuild the content directories exactly from the lock file
+    pub locked: bool,
     pub operand: SyncOperand,
     pub loader: Option<String>,
+    /// Only search for, or install projects as, this kind of content
+    pub kind: Option<IndexEntryKind>,
+    /// World to install datapacks into
+    pub world: Option<String>,
 }
 
 #[derive(Debug)]
 pub enum SyncOperand {
     Search {
         filter: SyncFilter,
-        sort: Option<Sort>,
+        sort: Option<SearchSort>,
     },
     Install(Vec<SyncFilter>),
     Nothing,
@@ -62,6 +91,8 @@ pub enum UpgradeKind {
     #[default]
     Mod,
     Resourcepack,
+    Shader,
+    Datapack,
 }
 
 #[derive(Debug)]
@@ -70,6 +101,120 @@ pub struct CliRemove {
     pub packages: Vec<SyncFilter>,
 }
 
+#[derive(Debug)]
+pub struct CliQuery {
+    pub info: bool,
+    pub foreign: bool,
+    pub operand: QueryOperand,
+}
+
+#[derive(Debug)]
+pub struct CliDatabase {
+    /// Whether to hold or unhold the packages
+    pub held: bool,
+    pub packages: Vec<SyncFilter>,
+}
+
+#[derive(Debug)]
+pub struct CliHistory {
+    /// Roll back a transaction and every one after it, instead of listing them
+    pub rollback: bool,
+    /// The transaction to roll back, the latest one if `None`
+    pub transaction: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct CliBackup {
+    pub operand: BackupOperand,
+}
+
+#[derive(Debug)]
+pub enum BackupOperand {
+    /// Back up a world, or every world if `None`
+    Create(Option<String>),
+    /// List backups of a world, or of every world if `None`
+    List(Option<String>),
+    /// Restore a backup of a world, the newest one if `id` is `None`
+    Restore { world: String, id: Option<String> },
+}
+
+#[derive(Debug)]
+pub struct CliWorlds;
+
+#[derive(Debug)]
+pub struct CliServers {
+    pub operand: ServersOperand,
+}
+
+#[derive(Debug)]
+pub enum ServersOperand {
+    List,
+    Add {
+        name: String,
+        ip: String,
+    },
+    /// Remove a server by name or address
+    Remove(String),
+    /// Move a server to a position in the list, counted from 1
+    Move {
+        server: String,
+        /// As given, it's checked when the server is moved
+        position: String,
+    },
+}
+
+#[derive(Debug)]
+pub struct CliInstance {
+    pub operand: InstanceOperand,
+}
+
+#[derive(Debug)]
+pub enum InstanceOperand {
+    /// Copy the instance into a new one named `name`, in `directory` or the default directory
+    /// for `name`
+    Clone {
+        name: String,
+        directory: Option<String>,
+        share_saves: bool,
+    },
+    /// Rename the instance to `name`, moving its directory to `directory` if it's given
+    Rename {
+        name: String,
+        directory: Option<String>,
+    },
+    Delete {
+        keep_worlds: bool,
+    },
+}
+
+#[derive(Debug)]
+pub struct CliConfig {
+    /// Check the config for problems instead of showing the effective config, with where each
+    /// value comes from
+    pub check: bool,
+}
+
+#[derive(Debug)]
+pub struct CliExport {
+    /// Where to write the `.mrpack`
+    pub output: String,
+    /// Name of the modpack, defaults to the instance name
+    pub name: Option<String>,
+    pub version: String,
+    /// Instance directories that go into the overrides
+    pub include: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum QueryOperand {
+    /// List installed packages, optionally only the ones matching the filters
+    Packages(Vec<SyncFilter>),
+    /// Search installed packages with a regex
+    Search(String),
+    /// Find the package that owns a file
+    Owns(String),
+}
+
 impl Cli {
     /// Build the clap command.
     pub fn command() -> clap::Command {
@@ -88,6 +233,12 @@ impl Cli {
                     .default_value("default")
                     .action(ArgAction::Set),
             )
+            .arg(
+                Arg::new("no-check")
+                    .long("no-check")
+                    .help("Launch without checking mod dependencies and world versions first")
+                    .action(ArgAction::SetTrue),
+            )
             .subcommand(
                 Command::new("init")
                     .short_flag('I')
@@ -142,6 +293,27 @@ impl Cli {
                             .long("username")
                             .help("The username to use")
                             .action(ArgAction::Set),
+                    )
+                    .arg(
+                        Arg::new("pack")
+                            .short('p')
+                            .long("pack")
+                            .help("Create the instance from a Modrinth (.mrpack) or CurseForge modpack")
+                            .conflicts_with_all(["version", "fabric", "vanilla"])
+                            .action(ArgAction::Set),
+                    )
+                    .arg(
+                        Arg::new("curseforge-key")
+                            .long("curseforge-key")
+                            .help("CurseForge API key, needed for CurseForge modpacks")
+                            .action(ArgAction::Set),
+                    )
+                    .arg(
+                        Arg::new("curseforge-url")
+                            .long("curseforge-url")
+                            .help("Use another server in place of the CurseForge API")
+                            .requires("curseforge-key")
+                            .action(ArgAction::Set),
                     ),
             )
             .subcommand(
@@ -153,8 +325,8 @@ impl Cli {
                         Arg::new("refresh")
                             .short('y')
                             .long("refresh")
-                            .help("Refresh the content index")
-                            .action(ArgAction::SetTrue),
+                            .help("Refresh the content index (twice to look up unknown files again)")
+                            .action(ArgAction::Count),
                     )
                     .arg(
                         Arg::new("upgrade")
@@ -191,6 +363,34 @@ impl Cli {
                             .long("loader")
                             .action(ArgAction::Set)
                             .help("set the needed mod loader"),
+                    )
+                    .arg(
+                        Arg::new("kind")
+                            .short('k')
+                            .long("kind")
+                            .action(ArgAction::Set)
+                            .value_parser(["mod", "resourcepack", "shader", "datapack"])
+                            .help("only search for, or install packages as, this kind of content"),
+                    )
+                    .arg(
+                        Arg::new("world")
+                            .short('w')
+                            .long("world")
+                            .action(ArgAction::Set)
+                            .help("install datapacks into this world"),
+                    )
+                    .arg(
+                        Arg::new("no-ignore")
+                            .long("no-ignore")
+                            .action(ArgAction::SetTrue)
+                            .help("upgrade held and ignored packages too, just this once"),
+                    )
+                    .arg(
+                        Arg::new("locked")
+                            .long("locked")
+                            .action(ArgAction::SetTrue)
+                            .conflicts_with_all(["refresh", "upgrade", "search", "package"])
+                            .help("rebuild content exactly from the lock file, failing on hash mismatches"),
                     ),
             )
             .subcommand(
@@ -211,6 +411,22 @@ impl Cli {
                             .long("resourcepack")
                             .action(ArgAction::SetTrue)
                             .help("Packages are resourcepacks, not mods"),
+                    )
+                    .arg(
+                        Arg::new("shader")
+                            .short('s')
+                            .long("shader")
+                            .action(ArgAction::SetTrue)
+                            .conflicts_with("resourcepack")
+                            .help("Packages are shader packs, not mods"),
+                    )
+                    .arg(
+                        Arg::new("datapack")
+                            .short('d')
+                            .long("datapack")
+                            .action(ArgAction::SetTrue)
+                            .conflicts_with_all(["resourcepack", "shader"])
+                            .help("Packages are datapacks, not mods"),
                     ),
             )
             .subcommand(
@@ -233,6 +449,268 @@ impl Cli {
                             .required(true),
                     ),
             )
+            .subcommand(
+                Command::new("query")
+                    .short_flag('Q')
+                    .long_flag("query")
+                    .about("Query the content installed on a Minecraft instance")
+                    .arg(
+                        Arg::new("info")
+                            .short('i')
+                            .long("info")
+                            .action(ArgAction::SetTrue)
+                            .help("view package information"),
+                    )
+                    .arg(
+                        Arg::new("search")
+                            .short('s')
+                            .long("search")
+                            .action(ArgAction::Set)
+                            .conflicts_with("owns")
+                            .help("search locally-installed packages for matching strings"),
+                    )
+                    .arg(
+                        Arg::new("owns")
+                            .short('o')
+                            .long("owns")
+                            .action(ArgAction::Set)
+                            .help("query the package that owns <file>"),
+                    )
+                    .arg(
+                        Arg::new("foreign")
+                            .short('m')
+                            .long("foreign")
+                            .action(ArgAction::SetTrue)
+                            .help("list packages not found on Modrinth"),
+                    )
+                    .arg(
+                        Arg::new("packages")
+                            .help("packages")
+                            .action(ArgAction::Append)
+                            .num_args(1..)
+                            .conflicts_with_all(["search", "owns"]),
+                    ),
+            )
+            .subcommand(
+                Command::new("database")
+                    .short_flag('D')
+                    .long_flag("database")
+                    .about("Modify the content index of a Minecraft instance")
+                    .arg(
+                        Arg::new("hold")
+                            .long("hold")
+                            .action(ArgAction::SetTrue)
+                            .help("hold packages at their installed version"),
+                    )
+                    .arg(
+                        Arg::new("unhold")
+                            .long("unhold")
+                            .action(ArgAction::SetTrue)
+                            .help("let packages be upgraded again"),
+                    )
+                    .group(
+                        ArgGroup::new("operation")
+                            .args(["hold", "unhold"])
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::new("packages")
+                            .help("packages")
+                            .action(ArgAction::Append)
+                            .num_args(1..)
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                Command::new("export")
+                    .short_flag('E')
+                    .long_flag("export")
+                    .about("Export a Minecraft instance as a Modrinth modpack (.mrpack)")
+                    .arg(
+                        Arg::new("output")
+                            .help("the .mrpack file to write")
+                            .action(ArgAction::Set)
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::new("name")
+                            .short('n')
+                            .long("name")
+                            .action(ArgAction::Set)
+                            .help("name of the modpack, defaults to the instance name"),
+                    )
+                    .arg(
+                        Arg::new("version")
+                            .short('v')
+                            .long("version")
+                            .action(ArgAction::Set)
+                            .default_value("1.0.0")
+                            .help("version of the modpack"),
+                    )
+                    .arg(
+                        Arg::new("include")
+                            .short('i')
+                            .long("include")
+                            .action(ArgAction::Append)
+                            .default_value("config")
+                            .help("instance directories to include as overrides"),
+                    ),
+            )
+            .subcommand(
+                Command::new("history")
+                    .short_flag('H')
+                    .long_flag("history")
+                    .about("List recent content changes of a Minecraft instance, or roll them back")
+                    .arg(
+                        Arg::new("rollback")
+                            .short('r')
+                            .long("rollback")
+                            .action(ArgAction::SetTrue)
+                            .help("undo a transaction and every one after it"),
+                    )
+                    .arg(
+                        Arg::new("transaction")
+                            .help("the transaction to roll back, the latest one by default")
+                            .action(ArgAction::Set)
+                            .requires("rollback"),
+                    ),
+            )
+            .subcommand(
+                Command::new("backup")
+                    .short_flag('B')
+                    .long_flag("backup")
+                    .about("Back up the worlds of a Minecraft instance, or restore them")
+                    .arg(
+                        Arg::new("list")
+                            .short('l')
+                            .long("list")
+                            .action(ArgAction::SetTrue)
+                            .help("list backups with their size and date")
+                            .conflicts_with("restore"),
+                    )
+                    .arg(
+                        Arg::new("restore")
+                            .short('r')
+                            .long("restore")
+                            .action(ArgAction::SetTrue)
+                            .help("replace a world with one of its backups")
+                            .requires("world"),
+                    )
+                    .arg(
+                        Arg::new("world")
+                            .help("the world, every world by default")
+                            .action(ArgAction::Set),
+                    )
+                    .arg(
+                        Arg::new("backup")
+                            .help("the backup to restore, the newest one by default")
+                            .action(ArgAction::Set)
+                            .requires("restore"),
+                    ),
+            )
+            .subcommand(
+                Command::new("worlds")
+                    .short_flag('W')
+                    .long_flag("worlds")
+                    .about("List the worlds of a Minecraft instance"),
+            )
+            .subcommand(
+                Command::new("servers")
+                    .short_flag('M')
+                    .long_flag("servers")
+                    .about("Manage the multiplayer server list of a Minecraft instance")
+                    .arg(
+                        Arg::new("add")
+                            .short('a')
+                            .long("add")
+                            .num_args(2)
+                            .value_names(["NAME", "ADDRESS"])
+                            .help("add a server at the end of the list"),
+                    )
+                    .arg(
+                        Arg::new("remove")
+                            .short('r')
+                            .long("remove")
+                            .value_name("SERVER")
+                            .help("remove a server by name or address"),
+                    )
+                    .arg(
+                        Arg::new("move")
+                            .short('m')
+                            .long("move")
+                            .num_args(2)
+                            .value_names(["SERVER", "POSITION"])
+                            .help("move a server to a position in the list, counted from 1"),
+                    )
+                    .group(ArgGroup::new("operation").args(["add", "remove", "move"])),
+            )
+            .subcommand(
+                Command::new("instance")
+                    .short_flag('N')
+                    .long_flag("instance")
+                    .about("Clone, rename or delete a Minecraft instance")
+                    .arg(
+                        Arg::new("clone")
+                            .short('c')
+                            .long("clone")
+                            .value_name("NAME")
+                            .help("copy the instance into a new instance"),
+                    )
+                    .arg(
+                        Arg::new("rename")
+                            .short('m')
+                            .long("rename")
+                            .value_name("NAME")
+                            .help("rename the instance"),
+                    )
+                    .arg(
+                        Arg::new("delete")
+                            .short('x')
+                            .long("delete")
+                            .action(ArgAction::SetTrue)
+                            .help("delete the instance, its config and its directory"),
+                    )
+                    .arg(
+                        Arg::new("directory")
+                            .short('d')
+                            .long("directory")
+                            .help("directory of the new instance, or where to move the renamed one")
+                            .conflicts_with("delete")
+                            .action(ArgAction::Set),
+                    )
+                    .arg(
+                        Arg::new("share-saves")
+                            .long("share-saves")
+                            .action(ArgAction::SetTrue)
+                            .help("share the worlds with the clone instead of copying them")
+                            .requires("clone"),
+                    )
+                    .arg(
+                        Arg::new("keep-worlds")
+                            .long("keep-worlds")
+                            .action(ArgAction::SetTrue)
+                            .help("leave the worlds in the directory of the deleted instance")
+                            .requires("delete"),
+                    )
+                    .group(
+                        ArgGroup::new("operation")
+                            .args(["clone", "rename", "delete"])
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                Command::new("config")
+                    .short_flag('C')
+                    .long_flag("config")
+                    .about("Show the effective config of a Minecraft instance and where each value comes from")
+                    .arg(
+                        Arg::new("check")
+                            .short('c')
+                            .long("check")
+                            .action(ArgAction::SetTrue)
+                            .help("check the config, the versions, Java, memory sizes and directories for problems"),
+                    ),
+            )
     }
 
     /// Parse a [`Cli`] from the command line arguments, i.e. [`std::env::args_os()`].
@@ -256,7 +734,9 @@ impl Cli {
         Cli {
             instance,
             command: match clap.subcommand() {
-                None => CliCommand::Run,
+                None => CliCommand::Run(CliRun {
+                    check: !clap.get_flag("no-check"),
+                }),
                 Some(("init", matches)) => {
                     let version = matches.get_one::<String>("version").map(|s| s.to_string());
                     let fabric = matches.get_one::<String>("fabric").map(|s| s.to_string());
@@ -269,6 +749,13 @@ impl Cli {
                         .map(|s| s.to_string());
                     let username = matches.get_one::<String>("username").map(|s| s.to_string());
                     let vanilla = matches.get_flag("vanilla");
+                    let pack = matches.get_one::<String>("pack").map(|s| s.to_string());
+                    let curseforge_key = matches
+                        .get_one::<String>("curseforge-key")
+                        .map(|s| s.to_string());
+                    let curseforge_url = matches
+                        .get_one::<String>("curseforge-url")
+                        .map(|s| s.to_string());
 
                     CliCommand::Init(CliInit {
                         version,
@@ -278,22 +765,35 @@ impl Cli {
                         directory,
                         username,
                         vanilla,
+                        pack,
+                        curseforge_key,
+                        curseforge_url,
                     })
                 }
                 Some(("sync", matches)) => {
-                    let refresh = matches.get_flag("refresh");
+                    let refresh = matches.get_count("refresh");
                     let upgrade = matches.get_flag("upgrade");
                     let search = matches.get_one::<String>("search");
                     let package = matches.get_many::<String>("package");
                     let loader = matches.get_one::<String>("loader").map(|s| s.to_string());
+                    let no_ignore = matches.get_flag("no-ignore");
+                    let locked = matches.get_flag("locked");
+                    let kind = matches.get_one::<String>("kind").map(|s| match s.as_str() {
+                        "mod" => IndexEntryKind::Mod,
+                        "resourcepack" => IndexEntryKind::Resourcepack,
+                        "shader" => IndexEntryKind::Shader,
+                        "datapack" => IndexEntryKind::Datapack,
+                        _ => unreachable!(),
+                    });
+                    let world = matches.get_one::<String>("world").cloned();
                     let sort = matches.get_one::<String>("sort").map(|s| {
                         let s = s.to_lowercase();
                         match s.as_str() {
-                            "relevance" => Sort::Relevance,
-                            "downloads" => Sort::Downloads,
-                            "follows" => Sort::Follows,
-                            "newest" => Sort::Newest,
-                            "updated" => Sort::Updated,
+                            "relevance" => SearchSort::Relevance,
+                            "downloads" => SearchSort::Downloads,
+                            "follows" => SearchSort::Follows,
+                            "newest" => SearchSort::Newest,
+                            "updated" => SearchSort::Updated,
                             _ => panic!("invalid sort: {s}"),
                         }
                     });
@@ -312,8 +812,13 @@ impl Cli {
                             _ => SyncOperand::Nothing,
                         },
                         loader,
-                        refresh,
+                        kind,
+                        world,
+                        refresh: refresh > 0,
+                        force_refresh: refresh > 1,
                         upgrade,
+                        no_ignore,
+                        locked,
                     })
                 }
                 Some(("upgrade", matches)) => {
@@ -324,6 +829,10 @@ impl Cli {
                         .collect();
                     let kind = if matches.get_flag("resourcepack") {
                         UpgradeKind::Resourcepack
+                    } else if matches.get_flag("shader") {
+                        UpgradeKind::Shader
+                    } else if matches.get_flag("datapack") {
+                        UpgradeKind::Datapack
                     } else {
                         UpgradeKind::Mod
                     };
@@ -338,6 +847,125 @@ impl Cli {
                         .collect();
                     CliCommand::Remove(CliRemove { disable, packages })
                 }
+                Some(("query", matches)) => {
+                    let info = matches.get_flag("info");
+                    let foreign = matches.get_flag("foreign");
+                    let operand = match (
+                        matches.get_one::<String>("search"),
+                        matches.get_one::<String>("owns"),
+                    ) {
+                        (Some(search), _) => QueryOperand::Search(search.to_string()),
+                        (None, Some(owns)) => QueryOperand::Owns(owns.to_string()),
+                        (None, None) => QueryOperand::Packages(
+                            matches
+                                .get_many::<String>("packages")
+                                .map(|packages| {
+                                    packages
+                                        .map(|p| p.parse().expect("invalid package"))
+                                        .collect()
+                                })
+                                .unwrap_or_default(),
+                        ),
+                    };
+                    CliCommand::Query(CliQuery {
+                        info,
+                        foreign,
+                        operand,
+                    })
+                }
+                Some(("database", matches)) => {
+                    let held = matches.get_flag("hold");
+                    let packages = matches
+                        .get_many::<String>("packages")
+                        .unwrap()
+                        .map(|p| p.parse().expect("invalid package"))
+                        .collect();
+                    CliCommand::Database(CliDatabase { held, packages })
+                }
+                Some(("export", matches)) => {
+                    let output = matches.get_one::<String>("output").unwrap().to_string();
+                    let name = matches.get_one::<String>("name").map(|s| s.to_string());
+                    let version = matches.get_one::<String>("version").unwrap().to_string();
+                    let include = matches
+                        .get_many::<String>("include")
+                        .unwrap()
+                        .map(|s| s.to_string())
+                        .collect();
+                    CliCommand::Export(CliExport {
+                        output,
+                        name,
+                        version,
+                        include,
+                    })
+                }
+                Some(("history", matches)) => CliCommand::History(CliHistory {
+                    rollback: matches.get_flag("rollback"),
+                    transaction: matches
+                        .get_one::<String>("transaction")
+                        .map(|s| s.to_string()),
+                }),
+                Some(("backup", matches)) => {
+                    let world = matches.get_one::<String>("world").map(|s| s.to_string());
+                    let operand = if matches.get_flag("list") {
+                        BackupOperand::List(world)
+                    } else if matches.get_flag("restore") {
+                        BackupOperand::Restore {
+                            world: world.unwrap(),
+                            id: matches.get_one::<String>("backup").map(|s| s.to_string()),
+                        }
+                    } else {
+                        BackupOperand::Create(world)
+                    };
+                    CliCommand::Backup(CliBackup { operand })
+                }
+                Some(("worlds", _)) => CliCommand::Worlds(CliWorlds),
+                Some(("servers", matches)) => {
+                    let values = |id: &str| {
+                        matches
+                            .get_many::<String>(id)
+                            .map(|it| it.map(|s| s.to_string()).collect::<Vec<_>>())
+                    };
+                    let operand = if let Some([name, ip]) = values("add").as_deref() {
+                        ServersOperand::Add {
+                            name: name.clone(),
+                            ip: ip.clone(),
+                        }
+                    } else if let Some(server) = matches.get_one::<String>("remove") {
+                        ServersOperand::Remove(server.to_string())
+                    } else if let Some([server, position]) = values("move").as_deref() {
+                        ServersOperand::Move {
+                            server: server.clone(),
+                            position: position.clone(),
+                        }
+                    } else {
+                        ServersOperand::List
+                    };
+                    CliCommand::Servers(CliServers { operand })
+                }
+                Some(("instance", matches)) => {
+                    let directory = matches.get_one::<String>("directory").cloned();
+                    let operand = match (
+                        matches.get_one::<String>("clone"),
+                        matches.get_one::<String>("rename"),
+                    ) {
+                        (Some(name), _) => InstanceOperand::Clone {
+                            name: name.to_string(),
+                            directory,
+                            share_saves: matches.get_flag("share-saves"),
+                        },
+                        (None, Some(name)) => InstanceOperand::Rename {
+                            name: name.to_string(),
+                            directory,
+                        },
+                        (None, None) => InstanceOperand::Delete {
+                            keep_worlds: matches.get_flag("keep-worlds"),
+                        },
+                    };
+                    CliCommand::Instance(CliInstance { operand })
+                }
+                Some(("config", matches)) => CliCommand::Config(CliConfig {
+                    check: matches.get_flag("check"),
+                }),
                 _ => unreachable!(),
             },
         }
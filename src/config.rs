@@ -11,32 +11,29 @@ use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use startmc_downloader::Download;
 use startmc_mojapi::{
-    model::{AssetIndex, FABRIC_MAVEN, FabricVerisonGameLoader, VersionManifestV2, VersionPackage},
+    model::{
+        AssetIndex, FABRIC_MAVEN, FABRIC_VERSIONS_GAME, FabricVerisonGameLoader, QUILT_MAVEN,
+        QUILT_VERSIONS_GAME, VersionManifestV2, VersionPackage,
+    },
     util::maven::MavenVersion,
 };
 
-use crate::cache::{use_cache_custom_path, use_cached, use_cached_json};
+pub mod check;
+pub mod layers;
+pub use layers::{LayeredConfig, Origin};
+
+use crate::{
+    cache::{use_cache_custom_path, use_cached, use_cached_json},
+    migrate,
+    options::OptionsConfig,
+    servers::ServerConfig,
+    sync::{SyncFilter, curseforge::CurseForgeConfig},
+};
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct MinecraftConfig {
     pub version: String,
     pub directory: String,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub fabric: Option<FabricConfig>,
-}
-
-impl MinecraftConfig {
-    pub fn get_loader_type(&self) -> Option<String> {
-        match self.fabric {
-            Some(FabricConfig { .. }) => Some(format!("fabric")),
-            None => None,
-        }
-    }
-}
-
-#[derive(Deserialize, Serialize, Debug)]
-pub struct FabricConfig {
-    pub version: String,
 }
 
 #[derive(Deserialize, Serialize, Debug, Default)]
@@ -70,9 +67,78 @@ pub enum Log4jConfig {
     Custom(String),
 }
 
+/// Per-instance content settings.
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct SyncConfig {
+    /// Packages that are never upgraded by `-Syu`, in [`SyncFilter`] syntax, like pacman's `IgnorePkg`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub curseforge: Option<CurseForgeConfig>,
+    /// Where datapacks are installed relative to the instance directory, like the folder of a
+    /// global datapack loader such as Paxi. Defaults to `datapacks`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datapacks: Option<String>,
+}
+
+impl SyncConfig {
+    pub fn is_empty(&self) -> bool {
+        self.ignore.is_empty() && self.curseforge.is_none() && self.datapacks.is_none()
+    }
+
+    pub fn ignore_filters(&self) -> Result<Vec<SyncFilter>> {
+        self.ignore
+            .iter()
+            .map(|it| {
+                it.parse()
+                    .map_err(|err| eyre!("invalid package in sync.ignore: {it}: {err}"))
+            })
+            .collect()
+    }
+}
+
+/// Per-instance world backup settings. Backups are kept by any of the `keep_*` rules, and if
+/// there are none, every backup is kept.
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+pub struct BackupConfig {
+    /// Keep the newest backups of each world
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_last: Option<usize>,
+    /// Keep the newest backup of each world for this many of the last days with backups
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_daily: Option<usize>,
+    /// Keep the newest backup of each world for this many of the last weeks with backups
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_weekly: Option<usize>,
+    /// Back up every world before launching
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub before_launch: bool,
+    /// Back up every world before `-Syu` upgrades anything
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub before_upgrade: bool,
+}
+
+impl BackupConfig {
+    pub fn is_empty(&self) -> bool {
+        self.keep_last.is_none()
+            && self.keep_daily.is_none()
+            && self.keep_weekly.is_none()
+            && !self.before_launch
+            && !self.before_upgrade
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct UnresolvedConfig {
+    /// Version of the layout of the config, see [`crate::migrate`]
+    #[serde(default = "config_schema")]
+    pub schema: u32,
+    /// Name of the instance config that this one is layered over, see [`LayeredConfig`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
     pub minecraft: MinecraftConfig,
+    #[serde(default, skip_serializing_if = "ModLoader::is_vanilla")]
+    pub loader: ModLoader,
     #[serde(default)]
     pub paths: PathsConfig,
     #[serde(default)]
@@ -83,6 +149,15 @@ pub struct UnresolvedConfig {
     pub username: Option<String>,
     #[serde(default)]
     pub uuid: Option<String>,
+    #[serde(default, skip_serializing_if = "SyncConfig::is_empty")]
+    pub sync: SyncConfig,
+    #[serde(default, skip_serializing_if = "BackupConfig::is_empty")]
+    pub backup: BackupConfig,
+    /// Servers that are added to the server list on launch if they aren't in it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub servers: Vec<ServerConfig>,
+    #[serde(default, skip_serializing_if = "OptionsConfig::is_empty")]
+    pub options: OptionsConfig,
 }
 
 impl Log4jConfig {
@@ -109,68 +184,153 @@ impl Log4jConfig {
     }
 }
 
-#[derive(Debug)]
+/// The mod loader of an instance, `[loader]` in the instance config. Instances without one are
+/// vanilla.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum ModLoader {
+    #[default]
     Vanilla,
-    Fabric { version: String },
+    Fabric {
+        version: String,
+    },
+    /// Quilt, which runs Fabric mods too
+    Quilt {
+        version: String,
+    },
 }
 
-async fn use_fabric_launcher_meta(game: &str, loader: &str) -> Result<FabricVerisonGameLoader> {
-    let manifest = use_cached(&format!(
-        "https://meta.fabricmc.net/v2/versions/loader/{game}/{loader}"
-    ))
-    .await?;
+impl ModLoader {
+    pub fn is_vanilla(&self) -> bool {
+        *self == ModLoader::Vanilla
+    }
+
+    /// The loader's name in Modrinth's API, `None` for vanilla.
+    pub fn get_loader_type(&self) -> Option<String> {
+        match self {
+            ModLoader::Vanilla => None,
+            ModLoader::Fabric { .. } => Some("fabric".to_string()),
+            ModLoader::Quilt { .. } => Some("quilt".to_string()),
+        }
+    }
+}
+
+/// The launcher meta of a Fabric or Quilt loader for the game version `game`, which have the
+/// same layout. `None` for vanilla.
+async fn use_launcher_meta(
+    loader: &ModLoader,
+    game: &str,
+) -> Result<Option<FabricVerisonGameLoader>> {
+    let (base, version) = match loader {
+        ModLoader::Vanilla => return Ok(None),
+        ModLoader::Fabric { version } => (FABRIC_VERSIONS_GAME, version),
+        ModLoader::Quilt { version } => (QUILT_VERSIONS_GAME, version),
+    };
+    let manifest = use_cached(&format!("{base}/{game}/{version}")).await?;
     let manifest: FabricVerisonGameLoader = serde_json::from_str(&manifest)?;
-    Ok(manifest)
+    Ok(Some(manifest))
 }
 
 impl ModLoader {
     pub async fn get_main_class(&self, game_version: &VersionPackage) -> Result<String> {
-        Ok(match self {
-            ModLoader::Vanilla => game_version.main_class.to_string(),
-            ModLoader::Fabric { version } => {
-                use_fabric_launcher_meta(&game_version.id, version.as_str())
-                    .await?
-                    .launcher_meta
-                    .main_class
-                    .client
-            }
+        Ok(match use_launcher_meta(self, &game_version.id).await? {
+            None => game_version.main_class.to_string(),
+            Some(manifest) => manifest.launcher_meta.main_class.client,
         })
     }
 
+    /// The libraries the loader needs for the game version `game`, with the Maven repository
+    /// each one is in.
+    pub async fn libraries(&self, game: &str) -> Result<Vec<(MavenVersion, String)>> {
+        let Some(manifest) = use_launcher_meta(self, game).await? else {
+            return Ok(vec![]);
+        };
+        let loader_maven = match self {
+            ModLoader::Quilt { .. } => QUILT_MAVEN,
+            _ => FABRIC_MAVEN,
+        };
+        Ok(manifest
+            .launcher_meta
+            .libraries
+            .client
+            .iter()
+            .chain(manifest.launcher_meta.libraries.common.iter())
+            .map(|it| {
+                (
+                    MavenVersion::parse(&it.name).expect("invalid library name"),
+                    it.url.clone(),
+                )
+            })
+            .chain([
+                (
+                    MavenVersion::parse(&manifest.loader.maven).expect("invalid loader name"),
+                    loader_maven.to_string(),
+                ),
+                (
+                    MavenVersion::parse(&manifest.intermediary.maven)
+                        .expect("invalid intermediary name"),
+                    FABRIC_MAVEN.to_string(),
+                ),
+                (
+                    MavenVersion {
+                        group: "net.minecrell".to_string(),
+                        artifact: "terminalconsoleappender".to_string(),
+                        version: "1.3.0".to_string(),
+                    },
+                    "https://repo1.maven.org/maven2".to_string(),
+                ),
+            ])
+            .collect())
+    }
+
     pub async fn build_classpath(&self, libraries_path: &str, game: &str) -> Result<Vec<String>> {
-        Ok(match self {
-            ModLoader::Vanilla => vec![],
-            ModLoader::Fabric { version } => {
-                let manifest = use_fabric_launcher_meta(game, version.as_str()).await?;
-                manifest
-                    .launcher_meta
-                    .libraries
-                    .client
-                    .iter()
-                    .chain(manifest.launcher_meta.libraries.common.iter())
-                    .map(|it| MavenVersion::parse(&it.name).expect("invalid library name"))
-                    .chain([
-                        MavenVersion::parse(&manifest.loader.maven).expect("invalid loader name"),
-                        MavenVersion::parse(&manifest.intermediary.maven)
-                            .expect("invalid intermediary name"),
-                        MavenVersion {
-                            group: "net.minecrell".to_string(),
-                            artifact: "terminalconsoleappender".to_string(),
-                            version: "1.3.0".to_string(),
-                        },
-                    ])
-                    .map(|l| format!("{}/{}", libraries_path, l.get_path()))
-                    .collect()
-            }
-        })
+        Ok(self
+            .libraries(game)
+            .await?
+            .into_iter()
+            .map(|(l, _)| format!("{}/{}", libraries_path, l.get_path()))
+            .collect())
     }
 }
 
+fn config_schema() -> u32 {
+    migrate::CONFIG.current()
+}
+
+/// Minimum memory of the game if the config doesn't say otherwise.
+pub const DEFAULT_MEM_MIN: &str = "512M";
+/// Maximum memory of the game if the config doesn't say otherwise.
+pub const DEFAULT_MEM_MAX: &str = "4G";
+
+/// Where libraries are downloaded if the config doesn't say otherwise.
+pub fn default_libraries_path() -> PathBuf {
+    dirs::data_dir()
+        .expect("data directory not found")
+        .join("startmc/libraries")
+}
+
+/// Where assets are downloaded if the config doesn't say otherwise.
+pub fn default_assets_path() -> PathBuf {
+    dirs::data_dir()
+        .expect("data directory not found")
+        .join("startmc/assets")
+}
+
+/// Where the config of an instance named `instance` can be, in the order they're looked up.
+pub(crate) fn config_paths(instance: &str) -> Result<Vec<PathBuf>> {
+    Ok(vec![
+        dirs::config_dir()
+            .context("config_dir not found")?
+            .join(format!("startmc/{instance}.toml")),
+        PathBuf::from(format!("./{instance}.startmc.toml")),
+    ])
+}
+
 impl UnresolvedConfig {
+    /// Read the instance config at `path`, layered over the configs it extends and the global
+    /// defaults.
     pub fn read(path: &Path) -> Result<Self> {
-        let contents = std::fs::read_to_string(path)?;
-        Ok(toml::from_str(&contents)?)
+        LayeredConfig::read(path)?.config()
     }
 
     pub fn write(&self, path: &Path) -> Result<()> {
@@ -184,25 +344,21 @@ impl UnresolvedConfig {
     }
 
     pub fn find_with_path(instance: &str) -> Result<(PathBuf, Self)> {
-        if instance.ends_with(".toml") {
-            return Ok((PathBuf::from(instance), Self::read(Path::new(instance))?));
-        }
+        let path = Self::find_path(instance)?;
+        let me = Self::read(&path)?;
+        Ok((path, me))
+    }
 
-        let paths = vec![
-            dirs::config_dir()
-                .context("config_dir not found")?
-                .join(format!("startmc/{instance}.toml")),
-            PathBuf::from(format!("./{instance}.startmc.toml")),
-        ];
-
-        for path in paths {
-            if path.exists() {
-                let me = Self::read(&path)?;
-                return Ok((path, me));
-            }
+    /// Find the config file of `instance` without reading it.
+    pub fn find_path(instance: &str) -> Result<PathBuf> {
+        if instance.ends_with(".toml") {
+            return Ok(PathBuf::from(instance));
         }
 
-        Err(eyre!("Config not found"))
+        config_paths(instance)?
+            .into_iter()
+            .find(|path| path.exists())
+            .ok_or_else(|| eyre!("Config not found"))
     }
 
     pub async fn resolve(self) -> Result<Config> {
@@ -215,42 +371,44 @@ impl UnresolvedConfig {
                 .versions
                 .into_iter()
                 .find(|v| v.id == self.minecraft.version)
-                .unwrap()
+                .with_context(|| {
+                    format!(
+                        "there is no Minecraft version {}, see startmc -Cc",
+                        self.minecraft.version
+                    )
+                })?
                 .url,
             )
             .await?,
-            java_path: self.paths.java.unwrap_or_else(|| {
-                std::env::var("JAVA_HOME")
-                    .expect("JAVA_HOME not set, and java_path is not specified in config")
-            }),
+            java_path: match self.paths.java {
+                Some(java) => java,
+                None => std::env::var("JAVA_HOME")
+                    .context("JAVA_HOME not set, and paths.java is not specified in config")?,
+            },
             libraries_path: self.paths.libraries.unwrap_or_else(|| {
-                let path = dirs::data_dir()
-                    .expect("data directory not found")
-                    .join("startmc/libraries");
+                let path = default_libraries_path();
                 std::fs::create_dir_all(&path).expect("failed to create libraries directory");
                 path.to_str()
                     .expect("libraries path is not utf-8")
                     .to_string()
             }),
             assets_path: self.paths.assets.unwrap_or_else(|| {
-                let path = dirs::data_dir()
-                    .expect("data directory not found")
-                    .join("startmc/assets");
+                let path = default_assets_path();
                 std::fs::create_dir_all(&path).expect("failed to create assets directory");
                 path.to_str().expect("assets path is not utf-8").to_string()
             }),
             minecraft_dir: self.minecraft.directory,
             jvm_args: self.args.jvm.split(' ').map(|s| s.to_string()).collect(),
             game_args: self.args.game.split(' ').map(|s| s.to_string()).collect(),
-            mem_min: self.args.mem_min.unwrap_or_else(|| "512M".to_string()),
-            mem_max: self.args.mem_max.unwrap_or_else(|| "4G".to_string()),
-            modloader: if let Some(fabric) = self.minecraft.fabric {
-                ModLoader::Fabric {
-                    version: fabric.version,
-                }
-            } else {
-                ModLoader::Vanilla
-            },
+            mem_min: self
+                .args
+                .mem_min
+                .unwrap_or_else(|| DEFAULT_MEM_MIN.to_string()),
+            mem_max: self
+                .args
+                .mem_max
+                .unwrap_or_else(|| DEFAULT_MEM_MAX.to_string()),
+            modloader: self.loader,
             log4j: self.log4j,
             username: self.username,
             uuid: self.uuid,
@@ -318,7 +476,7 @@ impl Config {
                         .artifact
                         .path
                         .split('/')
-                        .last()
+                        .next_back()
                         .unwrap()
                         .to_string(),
                 ),
@@ -333,47 +491,18 @@ impl Config {
         self.log4j
             .download(&self.libraries_path, &self.version, queue);
 
-        match &self.modloader {
-            ModLoader::Vanilla => {}
-            ModLoader::Fabric { version } => {
-                let manifest = use_fabric_launcher_meta(&self.version.id, &version).await?;
-                for lib in manifest
-                    .launcher_meta
-                    .libraries
-                    .client
-                    .iter()
-                    .chain(manifest.launcher_meta.libraries.common.iter())
-                    .map(|it| MavenVersion::parse(&it.name).expect("invalid library name"))
-                    .chain([
-                        MavenVersion::parse(&manifest.loader.maven).expect("invalid loader name"),
-                        MavenVersion::parse(&manifest.intermediary.maven)
-                            .expect("invalid intermediary name"),
-                        MavenVersion {
-                            group: "net.minecrell".to_string(),
-                            artifact: "terminalconsoleappender".to_string(),
-                            version: "1.3.0".to_string(),
-                        },
-                    ])
-                {
-                    let path = libs_path.join(&lib.get_path());
-                    if path.try_exists().unwrap_or(false) {
-                        trace!("library {} already downloaded", lib);
-                        continue;
-                    }
-                    std::fs::create_dir_all(path.parent().unwrap())
-                        .expect("failed to create directory");
-                    queue.push(Download::new(
-                        &Url::parse(&lib.get_url(if lib.group == "net.minecrell" {
-                            "https://repo1.maven.org/maven2"
-                        } else {
-                            FABRIC_MAVEN
-                        }))
-                        .unwrap(),
-                        path.to_str().unwrap(),
-                        Some(lib.to_string()),
-                    ));
-                }
+        for (lib, repo) in self.modloader.libraries(&self.version.id).await? {
+            let path = libs_path.join(lib.get_path());
+            if path.try_exists().unwrap_or(false) {
+                trace!("library {} already downloaded", lib);
+                continue;
             }
+            std::fs::create_dir_all(path.parent().unwrap()).expect("failed to create directory");
+            queue.push(Download::new(
+                &Url::parse(&lib.get_url(repo.trim_end_matches('/'))).unwrap(),
+                path.to_str().unwrap(),
+                Some(lib.to_string()),
+            ));
         }
 
         Ok(())
@@ -428,7 +557,7 @@ impl Config {
                             .iter()
                             .filter(|l| {
                                 l.check()
-                                    && if matches!(self.modloader, ModLoader::Fabric { .. }) {
+                                    && if !self.modloader.is_vanilla() {
                                         !l.name.contains("ow2.asm:asm")
                                     } else {
                                         true
@@ -469,7 +598,7 @@ impl Config {
             ],
         };
 
-        if self.jvm_args.len() > 0 {
+        if !self.jvm_args.is_empty() {
             args.jvm.extend(
                 self.jvm_args
                     .iter()
@@ -487,7 +616,7 @@ impl Config {
             args.game.push(username.to_string());
         }
 
-        if self.game_args.len() > 0 {
+        if !self.game_args.is_empty() {
             args.game.extend(
                 self.game_args
                     .iter()
@@ -510,12 +639,12 @@ impl Config {
         cmd.arg(args.main);
         cmd.args(args.game);
 
-        Ok(tokio::task::spawn_blocking(move || {
+        tokio::task::spawn_blocking(move || {
             let mut child = cmd.spawn()?;
             Ok::<_, color_eyre::Report>(child.wait()?)
         })
         .await
-        .context("cmd panic")??)
+        .context("cmd panic")?
     }
 }
 
@@ -0,0 +1,371 @@
+//! Checks of an instance config that find everything wrong with it at once, instead of failing
+//! on the first problem when the instance launches.
+
+use std::path::{Path, PathBuf};
+
+use startmc_mojapi::model::{
+    FABRIC_VERSIONS_GAME, FabricVersionsGame, QUILT_VERSIONS_GAME, VERSION_MANIFEST_V2,
+    VersionManifestV2, VersionPackage,
+};
+
+use super::{
+    DEFAULT_MEM_MAX, DEFAULT_MEM_MIN, LayeredConfig, ModLoader, UnresolvedConfig,
+    default_assets_path, default_libraries_path,
+};
+use crate::cache::use_cached_json;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The instance won't launch, or not as configured
+    Error,
+    /// The instance may launch, but something looks off, or couldn't be checked
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Problem {
+    fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+        }
+    }
+
+    fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.into(),
+        }
+    }
+}
+
+/// Check the instance config at `path`, with the configs it extends and the global defaults.
+pub async fn check(path: &Path) -> Vec<Problem> {
+    let (config, mut problems) = check_files(path);
+    let Some(config) = config else {
+        return problems;
+    };
+    let java_version = check_versions(&config, &mut problems).await;
+    check_java(&config, java_version, &mut problems);
+    problems.extend(check_memory(
+        config.args.mem_min.as_deref().unwrap_or(DEFAULT_MEM_MIN),
+        config.args.mem_max.as_deref().unwrap_or(DEFAULT_MEM_MAX),
+    ));
+    let paths = [
+        (
+            "minecraft.directory",
+            PathBuf::from(&config.minecraft.directory),
+        ),
+        (
+            "paths.libraries",
+            config
+                .paths
+                .libraries
+                .as_ref()
+                .map(PathBuf::from)
+                .unwrap_or_else(default_libraries_path),
+        ),
+        (
+            "paths.assets",
+            config
+                .paths
+                .assets
+                .as_ref()
+                .map(PathBuf::from)
+                .unwrap_or_else(default_assets_path),
+        ),
+    ];
+    for (key, path) in paths {
+        if let Err(err) = check_writable(&path) {
+            problems.push(Problem::error(format!(
+                "{key}: can't write to {}: {err}",
+                path.display()
+            )));
+        }
+    }
+    problems
+}
+
+/// Parse every file of the config. Type errors are reported with where they are in the file
+/// they're in, but a file can leave out fields that a config it extends has.
+fn check_files(path: &Path) -> (Option<UnresolvedConfig>, Vec<Problem>) {
+    let layers = match LayeredConfig::read(path) {
+        Ok(layers) => layers,
+        Err(err) => return (None, vec![Problem::error(err.to_string())]),
+    };
+    let mut problems = vec![];
+    for layer in &layers.layers {
+        let (super::Origin::Instance(path)
+        | super::Origin::Parent(path)
+        | super::Origin::Global(path)) = &layer.origin
+        else {
+            continue;
+        };
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            continue;
+        };
+        if let Err(err) = toml::from_str::<UnresolvedConfig>(&contents)
+            && !err.message().starts_with("missing field")
+        {
+            problems.push(Problem::error(format!("{}: {err}", path.display())));
+        }
+    }
+    if !problems.is_empty() {
+        return (None, problems);
+    }
+    match layers.config() {
+        Ok(config) => (Some(config), problems),
+        Err(err) => (None, vec![Problem::error(err.to_string())]),
+    }
+}
+
+/// Check the game and loader versions against their metadata. Returns the major Java version
+/// the game version needs.
+async fn check_versions(config: &UnresolvedConfig, problems: &mut Vec<Problem>) -> Option<u64> {
+    let game = &config.minecraft.version;
+    let manifest = match use_cached_json::<VersionManifestV2>(VERSION_MANIFEST_V2).await {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            problems.push(Problem::warning(format!(
+                "can't check the Minecraft version: {err}"
+            )));
+            return None;
+        }
+    };
+    let Some(version) = manifest.versions.iter().find(|it| it.id == *game) else {
+        problems.push(Problem::error(format!(
+            "minecraft.version: there is no Minecraft version {game}"
+        )));
+        return None;
+    };
+
+    // Quilt's meta lists its loaders like Fabric's
+    let loader = match &config.loader {
+        ModLoader::Vanilla => None,
+        ModLoader::Fabric { version } => Some(("Fabric", FABRIC_VERSIONS_GAME, version)),
+        ModLoader::Quilt { version } => Some(("Quilt", QUILT_VERSIONS_GAME, version)),
+    };
+    if let Some((name, meta, loader)) = loader {
+        match use_cached_json::<FabricVersionsGame>(&format!("{meta}/{game}")).await {
+            Ok(loaders) if loaders.is_empty() => problems.push(Problem::error(format!(
+                "loader: {name} doesn't support Minecraft {game}"
+            ))),
+            Ok(loaders) => {
+                if !loaders
+                    .iter()
+                    .any(|it| it.loader.version.version == *loader)
+                {
+                    problems.push(Problem::error(format!(
+                        "loader.version: there is no {name} loader {loader} for Minecraft {game}"
+                    )));
+                }
+            }
+            Err(err) => problems.push(Problem::warning(format!(
+                "can't check the {name} loader version: {err}"
+            ))),
+        }
+    }
+
+    match use_cached_json::<VersionPackage>(&version.url).await {
+        Ok(package) => Some(package.java_version.major_version),
+        Err(err) => {
+            problems.push(Problem::warning(format!(
+                "can't check which Java version Minecraft {game} needs: {err}"
+            )));
+            None
+        }
+    }
+}
+
+fn check_java(config: &UnresolvedConfig, required: Option<u64>, problems: &mut Vec<Problem>) {
+    let Some(java) = config
+        .paths
+        .java
+        .clone()
+        .or_else(|| std::env::var("JAVA_HOME").ok())
+    else {
+        problems.push(Problem::error(
+            "paths.java: no Java path, and JAVA_HOME isn't set",
+        ));
+        return;
+    };
+    let java = Path::new(&java);
+    let binary = java
+        .join("bin")
+        .join(if cfg!(windows) { "java.exe" } else { "java" });
+    if !binary.is_file() {
+        problems.push(Problem::error(format!(
+            "paths.java: there is no Java at {}",
+            binary.display()
+        )));
+        return;
+    }
+    let Some(required) = required else {
+        return;
+    };
+    match java_major_version(java) {
+        Some(found) if found < required => problems.push(Problem::error(format!(
+            "paths.java: Minecraft {} needs Java {required}, but {} is Java {found}",
+            config.minecraft.version,
+            java.display()
+        ))),
+        Some(found) if found > required => problems.push(Problem::warning(format!(
+            "paths.java: Minecraft {} is made for Java {required}, and {} is Java {found}",
+            config.minecraft.version,
+            java.display()
+        ))),
+        Some(_) => {}
+        None => problems.push(Problem::warning(format!(
+            "paths.java: can't tell which version of Java {} is",
+            java.display()
+        ))),
+    }
+}
+
+/// The major version of the Java installation in `java_home`, from its `release` file, or
+/// from `java -version` if it doesn't have one.
+fn java_major_version(java_home: &Path) -> Option<u64> {
+    if let Ok(release) = std::fs::read_to_string(java_home.join("release"))
+        && let Some(line) = release
+            .lines()
+            .find_map(|it| it.strip_prefix("JAVA_VERSION="))
+    {
+        return parse_java_version(line.trim_matches('"'));
+    }
+    let output = std::process::Command::new(java_home.join("bin").join("java"))
+        .arg("-version")
+        .output()
+        .ok()?;
+    // openjdk version "21.0.2" 2024-01-16
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let version = stderr.lines().next()?.split('"').nth(1)?;
+    parse_java_version(version)
+}
+
+/// The major version of a Java version like `21.0.2`, or `1.8.0_402` from before Java 9.
+fn parse_java_version(version: &str) -> Option<u64> {
+    let mut parts = version.split(['.', '_', '-', '+']);
+    match parts.next()?.parse().ok()? {
+        1 => parts.next()?.parse().ok(),
+        major => Some(major),
+    }
+}
+
+/// Check JVM memory sizes like `512M` or `4G`.
+fn check_memory(mem_min: &str, mem_max: &str) -> Vec<Problem> {
+    let mut problems = vec![];
+    let min = parse_memory(mem_min);
+    let max = parse_memory(mem_max);
+    for (key, value, parsed) in [("mem_min", mem_min, min), ("mem_max", mem_max, max)] {
+        if parsed.is_none() {
+            problems.push(Problem::error(format!(
+                "args.{key}: {value:?} isn't a memory size like 512M or 4G"
+            )));
+        }
+    }
+    if let (Some(min), Some(max)) = (min, max)
+        && min > max
+    {
+        problems.push(Problem::error(format!(
+            "args.mem_min: {mem_min} is more than args.mem_max, {mem_max}"
+        )));
+    }
+    problems
+}
+
+/// Bytes in a JVM memory size: a number of bytes, or of kibi-, mebi-, gibi- or tebibytes.
+fn parse_memory(size: &str) -> Option<u64> {
+    let (number, unit) = match size.char_indices().last()? {
+        (i, unit) if unit.is_ascii_alphabetic() => (&size[..i], unit.to_ascii_lowercase()),
+        _ => (size, 'b'),
+    };
+    let shift = match unit {
+        'b' => 0,
+        'k' => 10,
+        'm' => 20,
+        'g' => 30,
+        't' => 40,
+        _ => return None,
+    };
+    let number: u64 = number.parse().ok()?;
+    (number > 0).then(|| number << shift)
+}
+
+/// Check that files can be created in `dir`, or in the closest directory above it that exists,
+/// where startmc would create it.
+fn check_writable(dir: &Path) -> std::io::Result<()> {
+    let Some(existing) = dir.ancestors().find(|it| it.exists()) else {
+        return Ok(());
+    };
+    if !existing.is_dir() {
+        return Err(std::io::Error::other(format!(
+            "{} isn't a directory",
+            existing.display()
+        )));
+    }
+    let probe = existing.join(".startmc-write-check");
+    std::fs::write(&probe, "")?;
+    std::fs::remove_file(probe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_memory() {
+        assert_eq!(parse_memory("512M"), Some(512 << 20));
+        assert_eq!(parse_memory("4g"), Some(4 << 30));
+        assert_eq!(parse_memory("1048576"), Some(1 << 20));
+        assert_eq!(parse_memory("4 GB"), None);
+        assert_eq!(parse_memory("0M"), None);
+        assert!(check_memory("512M", "4G").is_empty());
+        assert_eq!(check_memory("8G", "4G").len(), 1);
+        assert_eq!(check_memory("lots", "4GB").len(), 2);
+    }
+
+    #[test]
+    fn test_parse_java_version() {
+        assert_eq!(parse_java_version("21.0.2"), Some(21));
+        assert_eq!(parse_java_version("1.8.0_402"), Some(8));
+        assert_eq!(parse_java_version("17"), Some(17));
+        assert_eq!(parse_java_version("23-ea"), Some(23));
+        assert_eq!(parse_java_version("openjdk"), None);
+    }
+
+    #[test]
+    fn test_check_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.toml");
+        std::fs::write(&path, "schema = 2\n[minecraft]\nversion = 1.21\n").unwrap();
+        let (config, problems) = check_files(&path);
+        assert!(config.is_none());
+        assert!(problems[0].message.contains("line 3"));
+
+        std::fs::write(
+            &path,
+            "schema = 2\n[minecraft]\nversion = \"1.21\"\ndirectory = 5\n",
+        )
+        .unwrap();
+        let (_, problems) = check_files(&path);
+        assert!(problems[0].message.contains("line 4"));
+
+        std::fs::write(&path, "schema = 2\n[minecraft]\nversion = \"1.21\"\n").unwrap();
+        let (_, problems) = check_files(&path);
+        assert!(problems[0].message.contains("missing field `directory`"));
+
+        std::fs::write(
+            &path,
+            "schema = 2\n[minecraft]\nversion = \"1.21\"\ndirectory = \"/a\"\n",
+        )
+        .unwrap();
+        let (config, problems) = check_files(&path);
+        assert!(problems.is_empty());
+        assert_eq!(config.unwrap().minecraft.directory, "/a");
+    }
+}
@@ -0,0 +1,320 @@
+//! Instance configs layered over the configs they extend and the global defaults file.
+
+use std::path::{Path, PathBuf};
+
+use color_eyre::{
+    Result,
+    eyre::{bail, eyre},
+};
+use toml::{Table, Value};
+
+use super::UnresolvedConfig;
+use crate::migrate;
+
+/// Where a value of the effective config comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    /// The instance's own config
+    Instance(PathBuf),
+    /// A config the instance extends, directly or through another one
+    Parent(PathBuf),
+    /// The global defaults file
+    Global(PathBuf),
+    /// The environment variable with this name
+    Environment(String),
+    /// startmc's own default
+    BuiltIn,
+}
+
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::Instance(path) => write!(f, "instance ({})", path.display()),
+            Origin::Parent(path) => write!(f, "extends ({})", path.display()),
+            Origin::Global(path) => write!(f, "global ({})", path.display()),
+            Origin::Environment(name) => write!(f, "${name}"),
+            Origin::BuiltIn => f.write_str("built-in"),
+        }
+    }
+}
+
+/// One config file of an instance's config.
+#[derive(Debug)]
+pub struct Layer {
+    pub origin: Origin,
+    pub table: Table,
+}
+
+/// The config files that make up the config of an instance, the instance's own first, then
+/// the configs it extends, then the global defaults.
+#[derive(Debug)]
+pub struct LayeredConfig {
+    pub layers: Vec<Layer>,
+}
+
+/// Where the global defaults that every instance config is layered over are.
+pub fn global_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("startmc").join("startmc.toml"))
+}
+
+/// Parse the config file at `path`, upgrading it first if it's from an older version of
+/// startmc. Syntax errors point at the line and column they're on.
+pub fn read_table(path: &Path) -> Result<Table> {
+    let contents = migrate::read(path, &migrate::CONFIG)?;
+    contents
+        .parse::<Table>()
+        .map_err(|err| eyre!("{}: {err}", path.display()))
+}
+
+impl LayeredConfig {
+    /// Read the instance config at `path` with everything it extends and the global defaults.
+    pub fn read(path: &Path) -> Result<Self> {
+        Self::read_with_global(path, global_path().as_deref())
+    }
+
+    pub fn read_with_global(path: &Path, global: Option<&Path>) -> Result<Self> {
+        let mut layers = vec![Layer {
+            origin: Origin::Instance(path.to_path_buf()),
+            table: read_table(path)?,
+        }];
+        let mut seen = vec![std::fs::canonicalize(path)?];
+        let mut current = path.to_path_buf();
+        while let Some(parent) = layers.last().unwrap().table.get("extends") {
+            let Some(parent) = parent.as_str() else {
+                bail!(
+                    "{}: extends must be the name of a config",
+                    current.display()
+                );
+            };
+            let parent_path = find_parent(&current, parent)?;
+            let canonical = std::fs::canonicalize(&parent_path)?;
+            if seen.contains(&canonical) {
+                bail!("{}: configs extend each other in a loop", current.display());
+            }
+            seen.push(canonical);
+            layers.push(Layer {
+                origin: Origin::Parent(parent_path.clone()),
+                table: read_table(&parent_path)?,
+            });
+            current = parent_path;
+        }
+        if let Some(global) = global
+            && global.exists()
+        {
+            layers.push(Layer {
+                origin: Origin::Global(global.to_path_buf()),
+                table: read_table(global)?,
+            });
+        }
+        Ok(Self { layers })
+    }
+
+    /// Every layer merged into one table, with tables merged key by key and anything else
+    /// taken from the first layer that has it.
+    pub fn merged(&self) -> Table {
+        let mut merged = Table::new();
+        for layer in self.layers.iter().rev() {
+            merge(&mut merged, &layer.table);
+        }
+        merged
+    }
+
+    pub fn config(&self) -> Result<UnresolvedConfig> {
+        let path = match &self.layers[0].origin {
+            Origin::Instance(path) => path.display().to_string(),
+            origin => origin.to_string(),
+        };
+        Value::Table(self.merged())
+            .try_into()
+            .map_err(|err| eyre!("{path}: {err}"))
+    }
+
+    /// Every value of the effective config with where it comes from, including the defaults
+    /// that [`UnresolvedConfig::resolve`] fills in. The values of a table come before its
+    /// subtables, like in a TOML file.
+    pub fn effective(&self) -> Result<Vec<(Vec<String>, Value, Origin)>> {
+        let mut table = Table::try_from(self.config()?)?;
+        let java = std::env::var("JAVA_HOME").ok().map(|it| {
+            (
+                Value::String(it),
+                Origin::Environment("JAVA_HOME".to_string()),
+            )
+        });
+        let defaults = [
+            (["paths", "java"], java),
+            (
+                ["paths", "libraries"],
+                Some((
+                    path_value(&super::default_libraries_path()),
+                    Origin::BuiltIn,
+                )),
+            ),
+            (
+                ["paths", "assets"],
+                Some((path_value(&super::default_assets_path()), Origin::BuiltIn)),
+            ),
+            (
+                ["args", "mem_min"],
+                Some((super::DEFAULT_MEM_MIN.into(), Origin::BuiltIn)),
+            ),
+            (
+                ["args", "mem_max"],
+                Some((super::DEFAULT_MEM_MAX.into(), Origin::BuiltIn)),
+            ),
+        ];
+        let mut default_origins = vec![];
+        for ([section, key], default) in defaults {
+            let Some((value, origin)) = default else {
+                continue;
+            };
+            let Value::Table(values) = table
+                .entry(section)
+                .or_insert_with(|| Value::Table(Table::new()))
+            else {
+                continue;
+            };
+            if !values.contains_key(key) {
+                values.insert(key.to_string(), value);
+                default_origins.push((vec![section.to_string(), key.to_string()], origin));
+            }
+        }
+
+        let mut values = vec![];
+        flatten(&table, &mut vec![], &mut values);
+        Ok(values
+            .into_iter()
+            .map(|(key, value)| {
+                let keys = key.iter().map(String::as_str).collect::<Vec<_>>();
+                let origin = self
+                    .origin(&keys)
+                    .or_else(|| {
+                        default_origins
+                            .iter()
+                            .find(|(it, _)| *it == key)
+                            .map(|(_, origin)| origin)
+                    })
+                    .cloned()
+                    .unwrap_or(Origin::BuiltIn);
+                (key, value, origin)
+            })
+            .collect())
+    }
+
+    /// The layer the value at `key`, a path of table keys, comes from, if any.
+    pub fn origin(&self, key: &[&str]) -> Option<&Origin> {
+        self.layers
+            .iter()
+            .find(|layer| lookup(&layer.table, key).is_some())
+            .map(|layer| &layer.origin)
+    }
+}
+
+/// Find the config named `name` that the config at `path` extends: a path to a `.toml` file
+/// relative to it, or an instance name, which is looked up next to it first.
+pub(crate) fn find_parent(path: &Path, name: &str) -> Result<PathBuf> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    if name.ends_with(".toml") {
+        return Ok(dir.join(name));
+    }
+    let sibling = crate::instance::sibling_config_path(path, name);
+    if sibling.exists() {
+        return Ok(sibling);
+    }
+    super::config_paths(name)?
+        .into_iter()
+        .find(|it| it.exists())
+        .ok_or_else(|| eyre!("{}: there is no config {name} to extend", path.display()))
+}
+
+fn path_value(path: &Path) -> Value {
+    Value::String(path.to_string_lossy().into_owned())
+}
+
+fn flatten(table: &Table, prefix: &mut Vec<String>, values: &mut Vec<(Vec<String>, Value)>) {
+    for (key, value) in table {
+        if !value.is_table() {
+            let mut key_path = prefix.clone();
+            key_path.push(key.clone());
+            values.push((key_path, value.clone()));
+        }
+    }
+    for (key, value) in table {
+        if let Value::Table(table) = value {
+            prefix.push(key.clone());
+            flatten(table, prefix, values);
+            prefix.pop();
+        }
+    }
+}
+
+fn merge(base: &mut Table, overlay: &Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(key), value) {
+            (Some(Value::Table(base)), Value::Table(overlay)) => merge(base, overlay),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+pub(crate) fn lookup<'a>(table: &'a Table, key: &[&str]) -> Option<&'a Value> {
+    let (first, rest) = key.split_first()?;
+    let value = table.get(*first)?;
+    match (rest.is_empty(), value) {
+        (true, value) => Some(value),
+        (false, Value::Table(table)) => lookup(table, rest),
+        (false, _) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        std::fs::write(
+            path("startmc.toml"),
+            "username = \"Steve\"\n[args]\nmem_max = \"4G\"\nmem_min = \"1G\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            path("base.toml"),
+            "[minecraft]\nversion = \"1.21\"\ndirectory = \"/base\"\n[args]\nmem_max = \"8G\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            path("a.toml"),
+            "extends = \"base\"\n[minecraft]\ndirectory = \"/a\"\n",
+        )
+        .unwrap();
+
+        let layers =
+            LayeredConfig::read_with_global(&path("a.toml"), Some(&path("startmc.toml"))).unwrap();
+        let config = layers.config().unwrap();
+        assert_eq!(config.minecraft.version, "1.21");
+        assert_eq!(config.minecraft.directory, "/a");
+        assert_eq!(config.args.mem_max.as_deref(), Some("8G"));
+        assert_eq!(config.args.mem_min.as_deref(), Some("1G"));
+        assert_eq!(config.username.as_deref(), Some("Steve"));
+        assert_eq!(
+            layers.origin(&["minecraft", "directory"]),
+            Some(&Origin::Instance(path("a.toml")))
+        );
+        assert_eq!(
+            layers.origin(&["args", "mem_max"]),
+            Some(&Origin::Parent(path("base.toml")))
+        );
+        assert_eq!(
+            layers.origin(&["username"]),
+            Some(&Origin::Global(path("startmc.toml")))
+        );
+        assert_eq!(layers.origin(&["args", "jvm"]), None);
+
+        std::fs::write(path("base.toml"), "extends = \"a\"\n").unwrap();
+        assert!(LayeredConfig::read_with_global(&path("a.toml"), None).is_err());
+    }
+}
@@ -1,8 +1,17 @@
+mod backup;
+mod config;
+mod database;
+mod export;
+mod history;
 mod init;
+mod instance;
+mod query;
 mod remove;
 mod run;
+mod servers;
 mod sync;
 mod upgrade;
+mod worlds;
 
 use crate::cli::{Cli, CliCommand};
 
@@ -10,10 +19,19 @@ impl Cli {
     pub async fn exec(self) -> color_eyre::Result<()> {
         match self.command {
             CliCommand::Remove(remove) => remove.exec(&self.instance).await,
-            CliCommand::Run => run::exec(&self.instance).await,
+            CliCommand::Run(run) => run.exec(&self.instance).await,
             CliCommand::Sync(sync) => sync.exec(&self.instance).await,
             CliCommand::Upgrade(upgrade) => upgrade.exec(&self.instance).await,
             CliCommand::Init(init) => init.exec(&self.instance).await,
+            CliCommand::Query(query) => query.exec(&self.instance).await,
+            CliCommand::Database(database) => database.exec(&self.instance).await,
+            CliCommand::Export(export) => export.exec(&self.instance).await,
+            CliCommand::History(history) => history.exec(&self.instance).await,
+            CliCommand::Backup(backup) => backup.exec(&self.instance).await,
+            CliCommand::Worlds(worlds) => worlds.exec(&self.instance).await,
+            CliCommand::Servers(servers) => servers.exec(&self.instance).await,
+            CliCommand::Instance(instance) => instance.exec(&self.instance).await,
+            CliCommand::Config(config) => config.exec(&self.instance).await,
         }
     }
 }
@@ -0,0 +1,97 @@
+use std::path::Path;
+
+use chrono::Local;
+use color_eyre::eyre::Context;
+use indicatif::HumanBytes;
+use owo_colors::OwoColorize;
+
+use crate::{
+    backup::{Backup, Backups},
+    cli::{BackupOperand, CliBackup},
+    config::UnresolvedConfig,
+    util::{ask_yn, cols, green_arrow},
+};
+
+impl CliBackup {
+    pub async fn exec(self, instance: &str) -> color_eyre::Result<()> {
+        let config = UnresolvedConfig::find(instance).context("find config")?;
+        let backups = Backups::new(Path::new(&config.minecraft.directory), &config.backup);
+
+        match self.operand {
+            BackupOperand::Create(world) => {
+                let worlds = match world {
+                    Some(world) => vec![world],
+                    None => backups.worlds()?,
+                };
+                back_up(&backups, &worlds)?;
+            }
+            BackupOperand::List(world) => {
+                let list = backups.list(world.as_deref())?;
+                if list.is_empty() {
+                    println!(" there are no backups");
+                }
+                for backup in &list {
+                    print_backup(backup);
+                }
+            }
+            BackupOperand::Restore { world, id } => {
+                let backup = backups.find(&world, id.as_deref())?;
+                println!(
+                    "{cols} {restoring}",
+                    cols = cols(),
+                    restoring = format!("Restoring {world} from backup").bold()
+                );
+                print_backup(&backup);
+                println!();
+                if ask_yn("Proceed with restoring?")? {
+                    backups.restore(&backup)?;
+                    println!("{}: restored from {}", world.bold(), backup.id);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Back up every world of the instance if the instance config asks for it with `enabled`.
+pub(super) fn back_up_all(config: &UnresolvedConfig, enabled: bool) -> color_eyre::Result<()> {
+    if !enabled {
+        return Ok(());
+    }
+    let backups = Backups::new(Path::new(&config.minecraft.directory), &config.backup);
+    back_up(&backups, &backups.worlds()?)
+}
+
+fn back_up(backups: &Backups, worlds: &[String]) -> color_eyre::Result<()> {
+    if worlds.is_empty() {
+        return Ok(());
+    }
+    println!(
+        "{cols} {backing_up}",
+        cols = cols(),
+        backing_up = format!("Backing up {} worlds...", worlds.len()).bold()
+    );
+    for world in worlds {
+        let backup = backups.create(world)?;
+        println!(
+            "{arrow} {world} ({size})",
+            arrow = green_arrow(),
+            size = HumanBytes(backup.size)
+        );
+    }
+    Ok(())
+}
+
+fn print_backup(backup: &Backup) {
+    println!(
+        "{world} {id} {date} {size}",
+        world = backup.world.bold(),
+        id = backup.id,
+        date = backup
+            .date
+            .with_timezone(&Local)
+            .format("[%Y-%m-%d %H:%M]")
+            .green(),
+        size = HumanBytes(backup.size).dimmed(),
+    );
+}
@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use color_eyre::eyre::{Context, bail};
+use owo_colors::OwoColorize;
+
+use crate::{
+    cli::CliConfig,
+    config::{
+        LayeredConfig, UnresolvedConfig,
+        check::{Severity, check},
+    },
+    util::{cols, pacman_error, pacman_warn},
+};
+
+impl CliConfig {
+    pub async fn exec(self, instance: &str) -> color_eyre::Result<()> {
+        let config_path = UnresolvedConfig::find_path(instance).context("find config")?;
+        if self.check {
+            return check_config(&config_path).await;
+        }
+        let layers = LayeredConfig::read(&config_path)?;
+
+        let mut table = vec![];
+        for (key, value, origin) in layers.effective()? {
+            let (name, section) = key.split_last().expect("keys aren't empty");
+            if section != table {
+                println!("\n{}", format!("[{}]", section.join(".")).bold());
+                table = section.to_vec();
+            }
+            // keep API keys off the screen
+            let value = if name == "api_key" {
+                "\"********\"".to_string()
+            } else {
+                value.to_string()
+            };
+            println!(
+                "{name} = {value} {origin}",
+                value = value.green(),
+                origin = format!("# {origin}").dimmed()
+            );
+        }
+        Ok(())
+    }
+}
+
+async fn check_config(config_path: &Path) -> color_eyre::Result<()> {
+    println!(
+        "{cols} {checking} {path}",
+        cols = cols(),
+        checking = "Checking config".bold(),
+        path = config_path.display()
+    );
+    let problems = check(config_path).await;
+    let mut errors = 0;
+    for problem in &problems {
+        match problem.severity {
+            Severity::Error => {
+                errors += 1;
+                pacman_error(&problem.message);
+            }
+            Severity::Warning => pacman_warn(&problem.message),
+        }
+    }
+    if errors > 0 {
+        bail!("the config has errors");
+    }
+    if problems.is_empty() {
+        println!(" there are no problems");
+    }
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use color_eyre::eyre::Context;
+use owo_colors::OwoColorize;
+
+use crate::{
+    cli::CliDatabase,
+    sync::{SyncIndex, Transaction},
+    util::pacman_error,
+};
+
+impl CliDatabase {
+    pub async fn exec(self, instance: &str) -> color_eyre::Result<()> {
+        let (config_path, config) =
+            crate::config::UnresolvedConfig::find_with_path(instance).context("find config")?;
+        let mut index = SyncIndex::read(&config_path)?;
+        let directory = Path::new(&config.minecraft.directory);
+        let names = self.packages.iter().map(|it| it.name.as_str());
+        let mut transaction = Transaction::begin(
+            &Transaction::history_dir(directory),
+            directory,
+            &format!(
+                "-D {} {}",
+                if self.held { "--hold" } else { "--unhold" },
+                names.collect::<Vec<_>>().join(" ")
+            ),
+            &mut index,
+        )?;
+
+        for filter in &self.packages {
+            let indices = index.find_packages(filter);
+            if indices.is_empty() {
+                pacman_error(format!("package '{}' was not found", filter.name));
+            }
+            for i in indices {
+                let pkg = &mut index.packages[i];
+                pkg.held = self.held;
+                println!(
+                    "{id}: {msg}",
+                    id = pkg.id.bold(),
+                    msg = if self.held {
+                        "package is now held at its installed version"
+                    } else {
+                        "package is no longer held"
+                    }
+                );
+            }
+        }
+
+        index.write(&config_path)?;
+        transaction.commit(&index)
+    }
+}
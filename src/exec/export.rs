@@ -0,0 +1,38 @@
+use std::path::Path;
+
+use color_eyre::eyre::Context;
+use owo_colors::OwoColorize;
+
+use crate::{cli::CliExport, modpack::mrpack::Export, util::cols};
+
+impl CliExport {
+    pub async fn exec(self, instance: &str) -> color_eyre::Result<()> {
+        let (config_path, config) =
+            crate::config::UnresolvedConfig::find_with_path(instance).context("find config")?;
+        let mut sync = crate::sync::Sync::new(&config_path, &config)?;
+        sync.maybe_refresh().await?;
+
+        let export = Export::new(
+            self.name.as_deref().unwrap_or(instance),
+            &self.version,
+            &config.minecraft,
+            &config.loader,
+            &sync.index,
+            &self.include,
+        )?;
+        let file = std::fs::File::create(&self.output)
+            .with_context(|| format!("create {}", self.output))?;
+        export.write(file, Path::new(&config.minecraft.directory))?;
+
+        println!(
+            "{cols} {msg} {output} ({files} files, {overrides} overrides)",
+            cols = cols(),
+            msg = "Exported to".bold(),
+            output = self.output,
+            files = export.index.files.len(),
+            overrides = export.overrides.len(),
+        );
+        sync.index.write(&config_path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use std::path::Path;
+
+use chrono::Local;
+use color_eyre::eyre::{Context, bail};
+use owo_colors::OwoColorize;
+
+use crate::{
+    cli::CliHistory,
+    sync::{
+        Transaction,
+        transaction::{self, TransactionRecord},
+    },
+    util::{ask_yn, cols},
+};
+
+impl CliHistory {
+    pub async fn exec(self, instance: &str) -> color_eyre::Result<()> {
+        let (config_path, config) =
+            crate::config::UnresolvedConfig::find_with_path(instance).context("find config")?;
+        let directory = Path::new(&config.minecraft.directory);
+        let history = Transaction::history_dir(directory);
+        let records = transaction::list(&history)?;
+
+        if !self.rollback {
+            if records.is_empty() {
+                println!(" there are no transactions");
+            }
+            for record in &records {
+                print_record(record);
+            }
+            return Ok(());
+        }
+
+        let id = match self
+            .transaction
+            .or_else(|| records.first().map(|it| it.id.clone()))
+        {
+            Some(id) => id,
+            None => {
+                println!(" there is nothing to do");
+                return Ok(());
+            }
+        };
+        let Some(position) = records.iter().position(|it| it.id == id) else {
+            bail!("there is no transaction {id}");
+        };
+        println!(
+            "{cols} {title}",
+            cols = cols(),
+            title = format!("Transactions to roll back ({})", position + 1).bold()
+        );
+        for record in &records[..=position] {
+            print_record(record);
+        }
+        println!();
+        if !ask_yn("Proceed with rollback?")? {
+            return Ok(());
+        }
+
+        for record in transaction::rollback(&history, directory, &config_path, &id)? {
+            println!("{}: rolled back {}", record.id.bold(), record.command);
+        }
+        Ok(())
+    }
+}
+
+fn print_record(record: &TransactionRecord) {
+    let files = match record.ops.len() {
+        1 => "1 file".to_string(),
+        n => format!("{n} files"),
+    };
+    println!(
+        "{id} {date} {command} {files}{incomplete}",
+        id = record.id.bold(),
+        date = record
+            .date
+            .with_timezone(&Local)
+            .format("[%Y-%m-%d %H:%M]")
+            .green(),
+        command = record.command,
+        files = format!("({files})").dimmed(),
+        incomplete = if record.complete {
+            String::new()
+        } else {
+            format!(" {}", "incomplete".yellow().bold())
+        },
+    );
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use color_eyre::eyre::{Context, ContextCompat};
 use owo_colors::OwoColorize;
@@ -10,7 +10,11 @@ use crate::{
     cache::use_cached_json,
     cli::CliInit,
     config::*,
-    util::{arrow_warn, ask_yn, green_arrow},
+    migrate,
+    modpack::{Modpack, curseforge, mrpack},
+    options::OptionsConfig,
+    sync::{ProviderKind, curseforge::CurseForgeConfig},
+    util::{arrow_warn, ask_yn, dialoguer_theme, green_arrow, pacman_warn},
 };
 
 impl CliInit {
@@ -27,6 +31,20 @@ impl CliInit {
             "{arrow} {text} {instance}",
             text = "Creating instance".bold()
         );
+        let (pack, pack_loader) = match &self.pack {
+            Some(path) => {
+                let pack = Modpack::open(Path::new(path))?;
+                let loader = pack.mod_loader()?;
+                println!(
+                    "{arrow} {text} {name} {version}",
+                    text = "From modpack".bold(),
+                    name = pack.name(),
+                    version = pack.version(),
+                );
+                (Some(pack), Some(loader))
+            }
+            None => (None, None),
+        };
         let versions = use_cached_json::<VersionManifestV2>(VERSION_MANIFEST_V2).await?;
         let version_ids = versions
             .versions
@@ -34,7 +52,9 @@ impl CliInit {
             .map(|v| v.id.as_str())
             .collect::<Vec<_>>();
 
-        let minecraft_version = if let Some(version) = self.version {
+        let minecraft_version = if let Some(pack) = &pack {
+            pack.game_version()?.to_string()
+        } else if let Some(version) = self.version {
             version
         } else {
             versions.versions[dialoguer::FuzzySelect::with_theme(&dialoguer_theme)
@@ -64,9 +84,10 @@ impl CliInit {
             .await
             .context("fs panic")??;
 
-        let modloader = match self.fabric {
-            Some(fabric) => ModLoader::Fabric { version: fabric },
-            None if self.vanilla => ModLoader::Vanilla,
+        let modloader = match (pack_loader, self.fabric) {
+            (Some(loader), _) => loader,
+            (None, Some(fabric)) => ModLoader::Fabric { version: fabric },
+            (None, None) if self.vanilla => ModLoader::Vanilla,
             _ => {
                 let loader = dialoguer::Select::with_theme(&dialoguer_theme)
                     .with_prompt("Mod loader")
@@ -109,7 +130,7 @@ impl CliInit {
         } else {
             let input =
                 dialoguer::Input::with_theme(&dialoguer_theme).with_prompt("Java path (optional)");
-            let text: String = (if let Some(java_home) = std::env::var("JAVA_HOME").ok() {
+            let text: String = (if let Ok(java_home) = std::env::var("JAVA_HOME") {
                 input.default(java_home)
             } else {
                 input
@@ -139,18 +160,31 @@ impl CliInit {
             }
         };
 
+        // CurseForge modpacks can't be installed without an API key
+        let curseforge = match (self.curseforge_key, &pack) {
+            (Some(api_key), _) => Some(api_key),
+            (None, Some(Modpack::CurseForge(_))) => Some(
+                dialoguer::Input::with_theme(&dialoguer_theme)
+                    .with_prompt("CurseForge API key")
+                    .interact_text()?,
+            ),
+            _ => None,
+        }
+        .map(|api_key| CurseForgeConfig {
+            api_key,
+            base_url: self.curseforge_url,
+        });
+
         let config = UnresolvedConfig {
+            schema: migrate::CONFIG.current(),
+            extends: None,
             args: ArgsConfig::default(),
             log4j: Log4jConfig::default(),
             minecraft: MinecraftConfig {
                 directory,
-                fabric: if let ModLoader::Fabric { version } = modloader {
-                    Some(FabricConfig { version })
-                } else {
-                    None
-                },
                 version: minecraft_version,
             },
+            loader: modloader,
             paths: PathsConfig {
                 java: java_path,
                 libraries: self.libraries,
@@ -158,6 +192,13 @@ impl CliInit {
             },
             username,
             uuid: None,
+            sync: SyncConfig {
+                curseforge,
+                ..Default::default()
+            },
+            backup: BackupConfig::default(),
+            servers: vec![],
+            options: OptionsConfig::default(),
         };
 
         let s = toml::to_string_pretty(&config).unwrap();
@@ -172,6 +213,11 @@ impl CliInit {
             "Write config to {config_path}?",
             config_path = config_path.display()
         ))? {
+            if let Some(pack) = pack {
+                install_pack(pack, &config_path, &config).await?;
+            }
+            super::run::write_options(&config_path, &config, true)?;
+            // written last, so that there's no instance if the pack can't be installed
             let cfg_path = config_path.clone();
             tokio::task::spawn_blocking(move || std::fs::write(cfg_path, s))
                 .await
@@ -189,3 +235,105 @@ impl CliInit {
         Ok(())
     }
 }
+
+/// Download the files of a modpack into the new instance, apply its overrides and build the
+/// content index, so that the pack's content can be upgraded with `-Syu` afterwards.
+async fn install_pack(
+    mut pack: Modpack,
+    config_path: &Path,
+    config: &UnresolvedConfig,
+) -> color_eyre::Result<()> {
+    let arrow = green_arrow();
+    let minecraft_directory = PathBuf::from(&config.minecraft.directory);
+    let mut sync = crate::sync::Sync::new(config_path, config)?;
+    let mut manual = vec![];
+    match &pack {
+        Modpack::Modrinth(pack) => {
+            let (optional, mut files): (Vec<_>, Vec<_>) = pack
+                .index
+                .client_files()
+                .cloned()
+                .partition(|it| it.is_optional());
+            files.extend(choose_optional(optional, |it| it.path.clone())?);
+
+            println!(
+                "{arrow} {msg}",
+                msg = format!("Downloading {} files...", files.len()).bold()
+            );
+            mrpack::download_files(&files, &minecraft_directory).await?;
+            sync.index
+                .packages
+                .extend(files.iter().filter_map(|it| it.to_entry()));
+        }
+        Modpack::CurseForge(pack) => {
+            println!("{arrow} {msg}", msg = "Resolving packages...".bold());
+            let provider = sync.provider(ProviderKind::CurseForge)?;
+            let (required, optional): (Vec<_>, Vec<_>) = pack
+                .manifest
+                .files
+                .iter()
+                .cloned()
+                .partition(|it| it.required);
+            let mut packages = curseforge::resolve(provider, &required).await?;
+            let optional = curseforge::resolve(provider, &optional).await?;
+            packages.extend(choose_optional(optional, |it| it.to_string())?);
+            // some authors only allow downloading their files from CurseForge itself
+            let (packages, blocked): (Vec<_>, Vec<_>) =
+                packages.into_iter().partition(|it| it.file().url.is_some());
+            manual = blocked;
+
+            println!(
+                "{arrow} {msg}",
+                msg = format!("Downloading {} packages...", packages.len()).bold()
+            );
+            sync.install(&packages).await?;
+        }
+    }
+    let overrides = pack.extract_overrides(&minecraft_directory)?;
+    debug!("Extracted {overrides} overrides");
+
+    println!("{arrow} {msg}", msg = "Building content index...".bold());
+    sync.refresh().await?;
+    sync.index.write(config_path)?;
+
+    if !manual.is_empty() {
+        pacman_warn(format!(
+            "{} files of the pack can only be downloaded from CurseForge, download them by hand:",
+            manual.len()
+        ));
+        for package in &manual {
+            println!(
+                "  {package} into {dir}: {page}",
+                package = package.to_string().bold(),
+                dir = minecraft_directory.join(package.kind.dir()).display(),
+                page = crate::sync::curseforge::file_page(
+                    package.kind,
+                    &package.project.slug,
+                    &package.version.id
+                )
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Ask which of the optional files of a modpack to install, all of them by default.
+fn choose_optional<T>(
+    optional: Vec<T>,
+    label: impl Fn(&T) -> String,
+) -> color_eyre::Result<Vec<T>> {
+    if optional.is_empty() {
+        return Ok(optional);
+    }
+    let chosen = dialoguer::MultiSelect::with_theme(&dialoguer_theme())
+        .with_prompt("Optional files to install")
+        .items(&optional.iter().map(label).collect::<Vec<_>>())
+        .defaults(&vec![true; optional.len()])
+        .interact()?;
+    Ok(optional
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| chosen.contains(i))
+        .map(|(_, it)| it)
+        .collect())
+}
@@ -0,0 +1,121 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{Context, bail};
+use owo_colors::OwoColorize;
+
+use crate::{
+    cli::{CliInstance, InstanceOperand},
+    config::UnresolvedConfig,
+    instance::{self, SavesMode, default_directory, sibling_config_path},
+    sync::SyncIndex,
+    util::{ask_ny, cols, green_arrow, pacman_warn},
+};
+
+impl CliInstance {
+    pub async fn exec(self, instance: &str) -> color_eyre::Result<()> {
+        let (config_path, config) =
+            UnresolvedConfig::find_with_path(instance).context("find config")?;
+        let arrow = green_arrow();
+
+        match self.operand {
+            InstanceOperand::Clone {
+                name,
+                directory,
+                share_saves,
+            } => {
+                let new_config_path = sibling_config_path(&config_path, &name);
+                let directory = directory
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| default_directory(&name));
+                println!(
+                    "{cols} {cloning}",
+                    cols = cols(),
+                    cloning = format!("Cloning instance {instance} into {name}").bold()
+                );
+                let saves = if share_saves {
+                    SavesMode::Share
+                } else {
+                    SavesMode::Copy
+                };
+                instance::clone(&config_path, &config, &new_config_path, &directory, saves)?;
+                println!(
+                    "{arrow} Wrote config to {}",
+                    new_config_path.display().bold()
+                );
+                println!(
+                    "{arrow} Copied the instance directory to {}{}",
+                    directory.display().bold(),
+                    if share_saves {
+                        ", sharing its worlds"
+                    } else {
+                        ""
+                    }
+                );
+            }
+            InstanceOperand::Rename { name, directory } => {
+                let new_config_path = sibling_config_path(&config_path, &name);
+                let directory = directory.map(PathBuf::from);
+                let children = instance::rename(
+                    &config_path,
+                    &config,
+                    &new_config_path,
+                    directory.as_deref(),
+                )?;
+                println!("{arrow} Renamed instance {instance} to {}", name.bold());
+                if let Some(directory) = directory {
+                    println!(
+                        "{arrow} Moved the instance directory to {}",
+                        directory.display().bold()
+                    );
+                }
+                for child in children {
+                    println!("{arrow} {} now extends {}", child.display(), name.bold());
+                }
+            }
+            InstanceOperand::Delete { keep_worlds } => {
+                let directory = Path::new(&config.minecraft.directory);
+                println!(
+                    "{cols} {deleting}",
+                    cols = cols(),
+                    deleting = format!("Deleting instance {instance}").bold()
+                );
+                let lock_path = SyncIndex::get_lock_path(&config_path);
+                for path in [&config_path, &lock_path, &directory.to_path_buf()] {
+                    if path.exists() {
+                        println!("{arrow} {}", path.display());
+                    }
+                }
+                if keep_worlds {
+                    println!("{arrow} the worlds in saves are kept");
+                }
+                println!();
+                let children = instance::extending(&config_path);
+                if !children.is_empty() {
+                    pacman_warn("these configs extend the instance, and can't be used without it:");
+                    for child in &children {
+                        println!("  {}", child.display());
+                    }
+                }
+                let sharing = instance::sharing_saves(&config_path, &config);
+                if !sharing.is_empty() && !keep_worlds {
+                    pacman_warn(
+                        "these instances share the worlds of the instance, and lose them too:",
+                    );
+                    for (path, _) in &sharing {
+                        println!("  {}", path.display());
+                    }
+                    if !ask_ny("Delete the worlds they share?")? {
+                        bail!(
+                            "not deleting worlds that other instances share, use --keep-worlds to keep them"
+                        );
+                    }
+                }
+                if ask_ny("Delete the instance? This can't be undone")? {
+                    instance::delete(&config_path, &config, keep_worlds)?;
+                    println!("{}: deleted", instance.bold());
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{Context, bail};
+use owo_colors::OwoColorize;
+use regex::Regex;
+
+use crate::{
+    cli::{CliQuery, QueryOperand},
+    sync::{SyncIndex, SyncIndexEntry},
+    util::pacman_error,
+};
+
+impl CliQuery {
+    pub async fn exec(self, instance: &str) -> color_eyre::Result<()> {
+        let (config_path, config) =
+            crate::config::UnresolvedConfig::find_with_path(instance).context("find config")?;
+        let index = SyncIndex::read(&config_path)?;
+
+        let mut not_found = false;
+        let mut indices = match &self.operand {
+            QueryOperand::Packages(filters) if filters.is_empty() => {
+                (0..index.packages.len()).collect()
+            }
+            QueryOperand::Packages(filters) => {
+                let mut indices = vec![];
+                for filter in filters {
+                    let found = index.find_packages(filter);
+                    if found.is_empty() {
+                        pacman_error(format!("package '{}' was not found", filter.name));
+                        not_found = true;
+                    }
+                    indices.extend(found);
+                }
+                indices
+            }
+            QueryOperand::Search(search) => {
+                let regex = Regex::new(search).context("invalid search regex")?;
+                index.search_packages(&regex)
+            }
+            QueryOperand::Owns(file) => {
+                let minecraft_directory = Path::new(&config.minecraft.directory);
+                // a file that exists is found from where it is, anything else is taken to be
+                // relative to the instance
+                let path = match (
+                    std::fs::canonicalize(file),
+                    std::fs::canonicalize(minecraft_directory),
+                ) {
+                    (Ok(path), Ok(dir)) => path
+                        .strip_prefix(&dir)
+                        .map(Path::to_path_buf)
+                        .unwrap_or(path),
+                    _ => PathBuf::from(file),
+                };
+                let Some(i) = index.find_owner(&path) else {
+                    bail!("no package owns {file}");
+                };
+                let pkg = &index.packages[i];
+                println!(
+                    "{file} is owned by {id} {version}",
+                    file = minecraft_directory
+                        .join(pkg.namespace_dir())
+                        .join(&pkg.file)
+                        .display(),
+                    id = pkg.id.bold(),
+                    version = pkg.version.green().bold(),
+                );
+                return Ok(());
+            }
+        };
+
+        if self.foreign {
+            indices.retain(|&i| index.packages[i].is_foreign());
+        }
+
+        for i in indices {
+            let pkg = &index.packages[i];
+            if self.info {
+                print_info(pkg);
+            } else if matches!(self.operand, QueryOperand::Search(_)) {
+                print_search_result(pkg);
+            } else {
+                println!(
+                    "{id} {version}",
+                    id = pkg.id.bold(),
+                    version = pkg.version.green().bold()
+                );
+            }
+        }
+
+        if not_found {
+            bail!("some packages were not found");
+        }
+        Ok(())
+    }
+}
+
+fn print_search_result(pkg: &SyncIndexEntry) {
+    // REFERENCE:
+    // {MAGENTA}local/{DEFAULT BOLD}pacman {GREEN BOLD}7.0.0.r6.gc685ae6-6
+    // \t{DEFAULT}A library-based package manager with dependency support
+    println!(
+        "{kind}/{id} {version}{disabled}\n    {file}",
+        kind = format!("{:?}", pkg.kind).to_lowercase().magenta().bold(),
+        id = pkg.id.bold(),
+        version = pkg.version.green().bold(),
+        disabled = if pkg.disabled {
+            format!(" {}", "[disabled]".cyan().bold())
+        } else {
+            String::new()
+        },
+        file = pkg.file,
+    );
+}
+
+fn print_info(pkg: &SyncIndexEntry) {
+    let line = |key: &str, value: &str| println!("{:<18}: {value}", key.bold());
+    line("Name", &pkg.id);
+    line("Version", &pkg.version);
+    line("Kind", &format!("{:?}", pkg.kind));
+    line("File", &pkg.file);
+    line("Disabled", if pkg.disabled { "Yes" } else { "No" });
+    line("Held", if pkg.held { "Yes" } else { "No" });
+    line(
+        "Provider",
+        pkg.provider.map(|it| it.as_str()).unwrap_or("None"),
+    );
+    line("Project ID", pkg.project_id.as_deref().unwrap_or("None"));
+    line("Version ID", pkg.version_id.as_deref().unwrap_or("None"));
+    if let Some(metadata) = &pkg.metadata {
+        let ids = |map: &std::collections::BTreeMap<String, Vec<String>>| {
+            if map.is_empty() {
+                "None".to_string()
+            } else {
+                map.keys().cloned().collect::<Vec<_>>().join("  ")
+            }
+        };
+        if let Some(description) = &metadata.description {
+            line("Description", description);
+        }
+        line("Depends On", &ids(&metadata.depends));
+        line("Conflicts With", &ids(&metadata.breaks));
+    }
+    println!();
+}
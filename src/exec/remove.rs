@@ -1,5 +1,3 @@
-use std::path::Path;
-
 use color_eyre::eyre::Context;
 use owo_colors::OwoColorize;
 
@@ -9,8 +7,7 @@ impl CliRemove {
     pub async fn exec(self, instance: &str) -> color_eyre::Result<()> {
         let (config_path, config) =
             crate::config::UnresolvedConfig::find_with_path(instance).context("find config")?;
-        let mut sync =
-            crate::sync::Sync::new(&config_path, Path::new(&config.minecraft.directory))?;
+        let mut sync = crate::sync::Sync::new(&config_path, &config)?;
         sync.maybe_refresh().await?;
         let cols = cols();
 
@@ -28,23 +25,26 @@ impl CliRemove {
             .bold()
         );
 
+        let names = self.packages.iter().map(|it| it.name.as_str());
+        sync.begin(&format!(
+            "-R{} {}",
+            if self.disable { "d" } else { "" },
+            names.collect::<Vec<_>>().join(" ")
+        ))?;
         for filter in self.packages {
             let indices = sync.index.find_packages(&filter);
 
             for i in indices {
                 if self.disable && !sync.index.packages[i].disabled {
-                    sync.index.packages[i]
-                        .disable_and_move(Path::new(&config.minecraft.directory))?;
+                    sync.index.packages[i].disable_and_move(&mut sync.transaction)?;
                 } else {
                     let pkg = sync.index.packages.swap_remove(i);
-                    pkg.remove_from_fs(Path::new(&config.minecraft.directory))
+                    pkg.remove_from_fs(&mut sync.transaction)
                         .context("remove from fs")?;
                 }
             }
         }
 
-        sync.index.write(&config_path)?;
-
-        Ok(())
+        sync.commit(&config_path)
     }
 }
@@ -1,66 +1,233 @@
-use color_eyre::eyre::Context;
+use std::path::Path;
+
+use color_eyre::eyre::{Context, bail};
 use owo_colors::OwoColorize;
 use startmc_downloader::DownloaderBuilder;
+use startmc_mojapi::model::{VERSION_MANIFEST_V2, VersionManifestV2};
 
-use crate::util::{cols, green_arrow};
+use crate::{
+    cache::use_cached_json,
+    cli::CliRun,
+    config::{ModLoader, UnresolvedConfig},
+    options::Options,
+    sync::{IndexEntryKind, SyncIndex, check_mods, read_mods},
+    util::{ask_ny, cols, green_arrow, pacman_error, pacman_warn},
+    world,
+};
 
-pub async fn exec(instance: &str) -> color_eyre::Result<()> {
-    let config = crate::config::UnresolvedConfig::find(instance).context("find config")?;
-    let config = config.resolve().await?;
-    let cols = cols();
-    let arrow = green_arrow();
+impl CliRun {
+    pub async fn exec(self, instance: &str) -> color_eyre::Result<()> {
+        let (config_path, config) =
+            UnresolvedConfig::find_with_path(instance).context("find config")?;
+        if self.check
+            && let ModLoader::Fabric { version } = &config.loader
+        {
+            check_dependencies(&config, version)?;
+        }
+        if self.check {
+            check_worlds(&config).await?;
+        }
+        super::backup::back_up_all(&config, config.backup.before_launch)?;
+        super::servers::ensure_servers(&config)?;
+        if config.options.merge_on_launch {
+            write_options(&config_path, &config, false)?;
+        }
+        let config = config.resolve().await?;
+        let cols = cols();
+        let arrow = green_arrow();
 
-    println!(
-        "{cols} {running} {instance}",
-        running = "Running instance".bold(),
-    );
+        println!(
+            "{cols} {running} {instance}",
+            running = "Running instance".bold(),
+        );
 
-    println!(
-        "{arrow} Using Java path: {javapath}",
-        javapath = config.java_path
-    );
-    println!(
-        "{arrow} Using libraries path: {librariespath}",
-        librariespath = config.libraries_path
-    );
-    println!(
-        "{arrow} Using Minecraft directory: {minecraftdir}",
-        minecraftdir = config.minecraft_dir
-    );
+        println!(
+            "{arrow} Using Java path: {javapath}",
+            javapath = config.java_path
+        );
+        println!(
+            "{arrow} Using libraries path: {librariespath}",
+            librariespath = config.libraries_path
+        );
+        println!(
+            "{arrow} Using Minecraft directory: {minecraftdir}",
+            minecraftdir = config.minecraft_dir
+        );
+
+        let mut queue: Vec<startmc_downloader::Download> = vec![];
 
-    let mut queue: Vec<startmc_downloader::Download> = vec![];
+        config.download_client(&mut queue);
+        config.download_libraries(&mut queue).await?;
+        config.download_assets(&mut queue).await?;
+        if !queue.is_empty() {
+            println!(
+                "{cols} {downloading}",
+                downloading = "Downloading assets...".bold()
+            );
+
+            let downloader = DownloaderBuilder::new().concurrent_downloads(10).build();
+            downloader.download(&queue).await;
+        }
 
-    config.download_client(&mut queue);
-    config.download_libraries(&mut queue).await?;
-    config.download_assets(&mut queue).await?;
-    if queue.len() > 0 {
         println!(
-            "{cols} {downloading}",
-            downloading = "Downloading assets...".bold()
+            "{cols} {starting} {version}",
+            starting = "Starting Minecraft".bold(),
+            version = config.version.id.green()
         );
 
-        let downloader = DownloaderBuilder::new().concurrent_downloads(10).build();
-        downloader.download(&queue).await;
+        let status = config.start().await?;
+        let code = status.code().unwrap_or(i32::MIN);
+
+        println!(
+            "{cols} {exited} {status}",
+            exited = "Minecraft finished".bold(),
+            status = if code == 0 {
+                "successfully".green().to_string()
+            } else {
+                format!("{} {}", "with exit code".red(), code)
+            }
+        );
+
+        Ok(())
     }
+}
 
+/// Merge the options template and the options in the instance config into the instance's
+/// `options.txt`. With `enable_resource_packs`, every resource pack in the index is enabled
+/// too, which is only done for new instances, so that packs turned off in the game stay off.
+pub(super) fn write_options(
+    config_path: &Path,
+    config: &UnresolvedConfig,
+    enable_resource_packs: bool,
+) -> color_eyre::Result<()> {
+    let path = Options::path(Path::new(&config.minecraft.directory));
+    let old = Options::read(&path)?;
+    let mut options = old.clone();
+    if let Some(template_path) = Options::template_path() {
+        let template = Options::read(&template_path)?;
+        if !options.merge(&template) {
+            pacman_warn(format!(
+                "{} is for a newer version of the game than the instance, not merging it",
+                template_path.display()
+            ));
+        }
+    }
+    for (key, value) in config.options.values() {
+        options.set(&key, &value);
+    }
+    if enable_resource_packs {
+        let resource_packs = SyncIndex::read(config_path)?
+            .packages
+            .into_iter()
+            .filter(|it| {
+                it.kind == IndexEntryKind::Resourcepack && !it.disabled && it.dir.is_none()
+            })
+            .map(|it| it.file)
+            .collect::<Vec<_>>();
+        options.enable_resource_packs(&resource_packs);
+    }
+
+    if options == old || options.entries.is_empty() {
+        return Ok(());
+    }
+    options.write(&path)?;
     println!(
-        "{cols} {starting} {version}",
-        starting = "Starting Minecraft".bold(),
-        version = config.version.id.green()
+        "{arrow} {msg} {path}",
+        arrow = green_arrow(),
+        msg = "Updated game options in".bold(),
+        path = path.display()
     );
+    Ok(())
+}
 
-    let status = config.start().await?;
-    let code = status.code().unwrap_or(i32::MIN);
+/// Enable resource packs that were just installed in the instance's `options.txt`, and keep
+/// upgraded ones enabled under their new file names. `changes` are the files that were
+/// replaced, if any, and the new files.
+pub(super) fn update_resource_packs(
+    config: &UnresolvedConfig,
+    changes: &[(Option<String>, String)],
+) -> color_eyre::Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let path = Options::path(Path::new(&config.minecraft.directory));
+    let old = Options::read(&path)?;
+    let mut options = old.clone();
+    for (replaced, file) in changes {
+        match replaced {
+            Some(replaced) => options.replace_resource_pack(replaced, file),
+            None => options.enable_resource_packs(std::slice::from_ref(file)),
+        }
+    }
+    if options != old {
+        options.write(&path)?;
+    }
+    Ok(())
+}
 
+/// Check the enabled mods against each other and the instance, and ask whether to launch anyway
+/// if they won't load.
+fn check_dependencies(config: &UnresolvedConfig, loader_version: &str) -> color_eyre::Result<()> {
+    let cols = cols();
     println!(
-        "{cols} {exited} {status}",
-        exited = "Minecraft finished".bold(),
-        status = if code == 0 {
-            "successfully".green().to_string()
-        } else {
-            format!("{} {}", "with exit code".red().to_string(), code)
-        }
+        "{cols} {checking}",
+        checking = "Checking mod dependencies...".bold()
     );
+    let mods = read_mods(&Path::new(&config.minecraft.directory).join("mods"))?;
+    let problems = check_mods(&mods, &config.minecraft.version, loader_version);
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    pacman_error("failed to prepare launch (could not satisfy dependencies)");
+    for problem in &problems {
+        println!("{cols} {problem}");
+    }
+    if !ask_ny("Launch anyway?")? {
+        bail!("mods have unsatisfied dependencies");
+    }
+    Ok(())
+}
 
+/// Warn about worlds that were saved by a newer version of the game than the instance's, which
+/// opening would downgrade, and ask whether to launch anyway.
+async fn check_worlds(config: &UnresolvedConfig) -> color_eyre::Result<()> {
+    let worlds = world::read_all(Path::new(&config.minecraft.directory))?;
+    if worlds.is_empty() {
+        return Ok(());
+    }
+    let manifest = match use_cached_json::<VersionManifestV2>(VERSION_MANIFEST_V2).await {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            pacman_warn(format!(
+                "can't check which versions of the game the worlds were saved by: {err}"
+            ));
+            return Ok(());
+        }
+    };
+    let versions = manifest
+        .versions
+        .iter()
+        .map(|it| it.id.as_str())
+        .collect::<Vec<_>>();
+    let game_version = &config.minecraft.version;
+    let newer = worlds
+        .iter()
+        .filter(|it| it.is_newer_than(game_version, &versions))
+        .collect::<Vec<_>>();
+    if newer.is_empty() {
+        return Ok(());
+    }
+
+    for world in newer {
+        pacman_warn(format!(
+            "{} was last played on {}, opening it on {game_version} will downgrade it and may corrupt it",
+            world.name,
+            world.version.as_deref().unwrap_or_default(),
+        ));
+    }
+    if !ask_ny("Launch anyway?")? {
+        bail!("worlds were saved by a newer version of the game");
+    }
     Ok(())
 }
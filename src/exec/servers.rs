@@ -0,0 +1,87 @@
+use std::path::Path;
+
+use color_eyre::eyre::{Context, ContextCompat, bail};
+use owo_colors::OwoColorize;
+
+use crate::{
+    cli::{CliServers, ServersOperand},
+    config::UnresolvedConfig,
+    servers::ServerList,
+    util::{cols, green_arrow},
+};
+
+impl CliServers {
+    pub async fn exec(self, instance: &str) -> color_eyre::Result<()> {
+        let config = UnresolvedConfig::find(instance).context("find config")?;
+        let directory = Path::new(&config.minecraft.directory);
+        let mut list = ServerList::read(directory)?;
+
+        let find = |list: &ServerList, server: &str| {
+            list.find(server)
+                .with_context(|| format!("there is no server {server} in the server list"))
+        };
+        match self.operand {
+            ServersOperand::List => {
+                if list.servers.is_empty() {
+                    println!(" there are no servers");
+                }
+                for (i, server) in list.servers.iter().enumerate() {
+                    println!(
+                        "{n} {name} {ip}",
+                        n = format!("{}.", i + 1).dimmed(),
+                        name = server.name.bold(),
+                        ip = server.ip.green()
+                    );
+                }
+                return Ok(());
+            }
+            ServersOperand::Add { name, ip } => {
+                list.add(&name, &ip)?;
+                println!("{}: added to the server list", name.bold());
+            }
+            ServersOperand::Remove(server) => {
+                let server = list.servers.remove(find(&list, &server)?);
+                println!("{}: removed from the server list", server.name.bold());
+            }
+            ServersOperand::Move { server, position } => {
+                let position = match position.parse::<usize>() {
+                    Ok(0) => bail!("positions are counted from 1"),
+                    Ok(position) => position,
+                    Err(_) => bail!("{position:?} isn't a position in the server list, like 1"),
+                };
+                let i = find(&list, &server)?;
+                let name = list.servers[i].name.clone();
+                list.move_to(i, position - 1);
+                println!(
+                    "{}: moved to position {}",
+                    name.bold(),
+                    position.min(list.servers.len())
+                );
+            }
+        }
+        list.write(directory)
+    }
+}
+
+/// Add the servers from the instance config that aren't in the server list.
+pub(super) fn ensure_servers(config: &UnresolvedConfig) -> color_eyre::Result<()> {
+    if config.servers.is_empty() {
+        return Ok(());
+    }
+    let directory = Path::new(&config.minecraft.directory);
+    let mut list = ServerList::read(directory)?;
+    let added = list.ensure(&config.servers);
+    if added.is_empty() {
+        return Ok(());
+    }
+    list.write(directory)?;
+    println!(
+        "{cols} {adding}",
+        cols = cols(),
+        adding = "Adding servers to the server list...".bold()
+    );
+    for name in added {
+        println!("{} {name}", green_arrow());
+    }
+    Ok(())
+}
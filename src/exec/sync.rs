@@ -1,15 +1,15 @@
-use std::{path::Path, time::Duration};
+use std::time::Duration;
 
 use chrono::Utc;
 use chrono_humanize::{Accuracy, Tense};
-use color_eyre::eyre::Context;
-use ferinth::structures::search::{Facet, Sort};
-use indicatif::ProgressBar;
-use owo_colors::OwoColorize;
+use color_eyre::eyre::{Context, bail};
+use indicatif::{HumanBytes, ProgressBar};
+use owo_colors::{AnsiColors, OwoColorize};
 
 use crate::{
     cli::{CliSync, SyncOperand},
-    util::cols,
+    sync::{Conflict, IndexEntryKind, ProviderKind, RefreshSummary, ResolvedPackage, SyncIndex},
+    util::{ask_yn, cols, dialoguer_theme, green_arrow, pacman_error, pacman_warn},
 };
 
 impl CliSync {
@@ -17,84 +17,466 @@ impl CliSync {
         let (config_path, config) =
             crate::config::UnresolvedConfig::find_with_path(instance).context("find config")?;
         let cols = cols();
-        let mut sync =
-            crate::sync::Sync::new(&config_path, Path::new(&config.minecraft.directory))?;
+        let mut sync = crate::sync::Sync::new(&config_path, &config)?;
+        if let Some(loader) = self.loader {
+            sync.loader = Some(loader);
+        }
+        if let Some(world) = &self.world {
+            sync.set_world(world)?;
+        }
+        sync.kind = self.kind;
+        if self.locked {
+            return restore_locked(&mut sync, &config_path).await;
+        }
         if self.refresh {
             println!(
                 "{cols} {refreshing}",
                 refreshing = "Refreshing content index...".bold()
             );
-            sync.refresh().await?;
+            let summary = if self.force_refresh {
+                sync.force_refresh().await?
+            } else {
+                sync.refresh().await?
+            };
+            print_refresh_summary(&summary);
         }
 
         if self.upgrade {
-            // TODO: upgrade content
             println!(
                 "{cols} {upgrading}",
                 upgrading = "Starting full content upgrade...".bold()
             );
+            sync.maybe_refresh().await?;
+            let mut upgrades = sync.find_upgrades().await?;
+            if !self.no_ignore {
+                upgrades.retain(|it| {
+                    let installed = sync
+                        .index
+                        .find_project(it.package.provider, &it.package.project.id)
+                        .unwrap();
+                    let held = sync.is_held(installed);
+                    if held {
+                        pacman_warn(format!(
+                            "{}: ignoring package upgrade ({} => {})",
+                            installed.id, installed.version, it.package.version.version_number
+                        ));
+                    }
+                    !held
+                });
+            }
+            let mut downgrades = vec![];
+            for it in upgrades.iter().filter(|it| it.downgrade) {
+                let installed = sync
+                    .index
+                    .find_project(it.package.provider, &it.package.project.id)
+                    .unwrap();
+                pacman_warn(format!(
+                    "{}: downgrading from version {} to version {}, since the installed version isn't compatible with the instance",
+                    installed.id, installed.version, it.package.version.version_number
+                ));
+                downgrades.push(it.package.project.id.clone());
+            }
+            if upgrades.is_empty() {
+                println!(" there is nothing to do");
+            } else {
+                let plan = sync
+                    .plan(upgrades.into_iter().map(|it| it.package).collect())
+                    .await?;
+                check_conflicts(plan.conflicts.iter())?;
+                print_upgrade_table(&transaction_items(&sync.index, &plan.packages, &downgrades));
+                if ask_yn("Proceed with installation?")? {
+                    super::backup::back_up_all(&config, config.backup.before_upgrade)?;
+                    sync.begin("-Su")?;
+                    let packs = resource_pack_changes(&sync.index, &plan.packages);
+                    sync.install(&plan.packages).await?;
+                    super::run::update_resource_packs(&config, &packs)?;
+                }
+            }
         }
 
         match self.operand {
             SyncOperand::Nothing => {}
             SyncOperand::Search { filter, sort } => {
-                let loader_facet = if let Some(loader) =
-                    self.loader.or_else(|| config.minecraft.get_loader_type())
-                {
-                    vec![Facet::Categories(loader)]
-                } else {
-                    vec![]
-                };
+                let (provider, query) = ProviderKind::split(&filter.name);
                 let spinner = ProgressBar::new_spinner();
                 spinner.enable_steady_tick(Duration::from_millis(100));
-                spinner.set_message("Querying Modrinth API...");
+                spinner.set_message(format!("Querying {provider} API..."));
                 let results = sync
-                    .fer
+                    .provider(provider)?
                     .search(
-                        &filter.name,
-                        sort.as_ref().unwrap_or(&Sort::Relevance),
-                        vec![loader_facet],
+                        query,
+                        sort.unwrap_or_default(),
+                        self.kind,
+                        &sync.game_version,
+                        sync.loader.as_deref(),
                     )
-                    .await?;
+                    .await;
                 spinner.finish_and_clear();
 
-                for result in results.hits {
+                for result in results? {
                     // REFERENCE:
                     // {CYAN}aur/{DEFAULT BOLD}mrpack-install {GREEN BOLD}0.16.10-1 [{DEFAULT BOLD}+0 {DEFAULT BOLD}~0.00]
                     // \t{DEFAULT}Modrinth Modpack server deployment
 
-                    let modified_since = Utc::now() - result.date_modified;
+                    let modified_since = Utc::now() - result.updated;
                     let ht = chrono_humanize::HumanTime::from(modified_since);
                     let upd = ht.to_text_en(Accuracy::Rough, Tense::Past);
-                    let slug = result.slug.as_deref().unwrap_or(&result.project_id);
+                    let repo = match provider {
+                        ProviderKind::Modrinth => String::new(),
+                        provider => format!("{provider}/"),
+                    };
                     let downloads = re_format::approximate_large_number(result.downloads as f64);
-                    let follows = re_format::approximate_large_number(result.follows as f64);
+                    let follows = result
+                        .follows
+                        .map(|follows| {
+                            format!(
+                                " {bar} {follow_icon} {follows}",
+                                bar = "|".bright_white().dimmed().bold(),
+                                follow_icon = "".bright_magenta().bold(),
+                                follows =
+                                    re_format::approximate_large_number(follows as f64).bold(),
+                            )
+                        })
+                        .unwrap_or_default();
                     println!(
-                        "{slug} <{upd}> [{dwl_icon} {downloads} {bar} {follow_icon} {follows}]\n    {title} {desc}",
-                        slug = slug.bold(),
+                        "{repo}{slug} <{upd}> [{dwl_icon} {downloads}{follows}]\n    {title} {desc}",
+                        repo = repo.magenta().bold(),
+                        slug = result.slug.bold(),
                         title = result.title.yellow().bold(),
                         upd = upd.green().italic(),
                         dwl_icon = "".green().bold(),
                         downloads = downloads.bold(),
-                        bar = "|".bright_white().dimmed().bold(),
-                        follow_icon = "".bright_magenta().bold(),
-                        follows = follows.bold(),
                         desc = result.description,
                     );
                 }
             }
 
             SyncOperand::Install(packages) => {
-                // TODO: install
+                sync.maybe_refresh().await?;
                 println!(
-                    "{cols} {installing} {len} packages",
-                    installing = "Installing".bold(),
-                    len = packages.len()
+                    "{cols} {resolving}",
+                    resolving = "Resolving packages...".bold()
                 );
+
+                let mut resolved: Vec<ResolvedPackage> = vec![];
+                for filter in &packages {
+                    let spinner = ProgressBar::new_spinner()
+                        .with_message(format!("Resolving {}...", filter.name));
+                    spinner.enable_steady_tick(Duration::from_millis(100));
+                    let pkg = sync.resolve(filter).await;
+                    spinner.finish_and_clear();
+                    let pkg = pkg?;
+                    let installed = sync.index.find_project(pkg.provider, &pkg.project.id);
+                    if installed
+                        .is_some_and(|it| it.version_id.as_deref() == Some(pkg.version.id.as_str()))
+                    {
+                        pacman_warn(format!("{pkg} is up to date -- skipping"));
+                    } else if installed.is_some_and(|it| sync.is_held(it))
+                        && !self.no_ignore
+                        && !ask_yn(format!("{} is held. Install anyway?", pkg.project.slug))?
+                    {
+                        continue;
+                    } else if !resolved
+                        .iter()
+                        .any(|it| it.provider == pkg.provider && it.project.id == pkg.project.id)
+                    {
+                        resolved.push(pkg);
+                    }
+                }
+
+                if resolved.is_empty() {
+                    println!(" there is nothing to do");
+                    return sync.commit(&config_path);
+                }
+
+                println!(
+                    "{cols} {resolving}",
+                    resolving = "Resolving dependencies...".bold()
+                );
+                let mut plan = sync.plan(resolved.clone()).await?;
+                if !plan.optional.is_empty() {
+                    let items = plan
+                        .optional
+                        .iter()
+                        .map(|it| format!("{it} ({})", it.project.title))
+                        .collect::<Vec<_>>();
+                    let chosen = dialoguer::MultiSelect::with_theme(&dialoguer_theme())
+                        .with_prompt("Optional dependencies to install")
+                        .items(&items)
+                        .interact()?;
+                    if !chosen.is_empty() {
+                        resolved.extend(chosen.into_iter().map(|i| plan.optional[i].clone()));
+                        plan = sync.plan(resolved).await?;
+                    }
+                }
+
+                check_conflicts(plan.conflicts.iter())?;
+                print_transaction(&transaction_items(&sync.index, &plan.packages, &[]));
+                if ask_yn("Proceed with installation?")? {
+                    let names = packages.iter().map(|it| it.name.as_str());
+                    sync.begin(&format!("-S {}", names.collect::<Vec<_>>().join(" ")))?;
+                    let packs = resource_pack_changes(&sync.index, &plan.packages);
+                    sync.install(&plan.packages).await?;
+                    super::run::update_resource_packs(&config, &packs)?;
+                }
             }
         }
 
-        sync.index.write(&config_path)?;
-        Ok(())
+        sync.commit(&config_path)
+    }
+}
+
+async fn restore_locked(
+    sync: &mut crate::sync::Sync,
+    config_path: &std::path::Path,
+) -> color_eyre::Result<()> {
+    println!(
+        "{cols} {checking}",
+        cols = cols(),
+        checking = "Checking content against the lock file...".bold()
+    );
+    let plan = sync.check_locked()?;
+    if plan.is_empty() {
+        println!(" there is nothing to do");
+        return Ok(());
+    }
+
+    if !plan.download.is_empty() {
+        let packages = plan
+            .download
+            .iter()
+            .map(|&i| {
+                let pkg = &sync.index.packages[i];
+                format!("{}-{}", pkg.id, pkg.version)
+            })
+            .collect::<Vec<_>>();
+        let size = plan
+            .download
+            .iter()
+            .filter_map(|&i| sync.index.packages[i].size)
+            .sum::<u64>();
+        println!();
+        println!(
+            "{packages_len} {list}",
+            packages_len = format!("Packages ({})", packages.len()).bold(),
+            list = packages.join("  ")
+        );
+        println!();
+        println!(
+            "{total} {size}",
+            total = "Total Download Size:".bold(),
+            size = HumanBytes(size)
+        );
+    }
+    if !plan.extraneous.is_empty() {
+        println!();
+        println!(
+            "{title}",
+            title = format!("Files to remove ({})", plan.extraneous.len()).bold()
+        );
+        for path in &plan.extraneous {
+            println!("    {}", path.display());
+        }
     }
+    println!();
+
+    if ask_yn("Proceed with restoring?")? {
+        sync.begin("-S --locked")?;
+        sync.apply_locked(&plan).await?;
+        sync.commit(config_path)?;
+    }
+    Ok(())
+}
+
+/// Print what a refresh changed in the index.
+pub(super) fn print_refresh_summary(summary: &RefreshSummary) {
+    let arrow = green_arrow();
+    if summary.is_empty() {
+        println!("{arrow} Content index is up to date");
+        return;
+    }
+    for (what, ids) in [
+        ("added", &summary.added),
+        ("removed", &summary.removed),
+        ("changed", &summary.changed),
+    ] {
+        if !ids.is_empty() {
+            println!(
+                "{arrow} {count} {what}: {ids}",
+                count = ids.len().bold(),
+                ids = ids.join(" ")
+            );
+        }
+    }
+}
+
+fn check_conflicts<'a>(conflicts: impl Iterator<Item = &'a Conflict>) -> color_eyre::Result<()> {
+    println!(
+        "{cols} {looking}",
+        cols = cols(),
+        looking = "Looking for conflicting packages...".bold()
+    );
+    let mut found = false;
+    for conflict in conflicts {
+        pacman_error(format!(
+            "{} and {} are in conflict",
+            conflict.package, conflict.with
+        ));
+        found = true;
+    }
+    if found {
+        bail!("unresolvable package conflicts detected");
+    }
+    Ok(())
+}
+
+/// A package that is about to be installed or upgraded.
+struct TransactionItem {
+    slug: String,
+    old_version: String,
+    new_version: String,
+    downgrade: bool,
+    size: u64,
+}
+
+/// The resource packs that installing `packages` adds, as the file of the package each one
+/// replaces, if any, and its new file.
+fn resource_pack_changes(
+    index: &SyncIndex,
+    packages: &[ResolvedPackage],
+) -> Vec<(Option<String>, String)> {
+    packages
+        .iter()
+        .filter(|it| it.kind == IndexEntryKind::Resourcepack)
+        .map(|it| {
+            let entry = it.to_entry();
+            let replaced = index
+                .packages
+                .iter()
+                .find(|installed| installed.same_project(&entry))
+                .map(|installed| installed.file.clone());
+            (replaced, entry.file)
+        })
+        .collect()
+}
+
+/// `downgrades` are the project IDs of the packages that go back to an older version.
+fn transaction_items(
+    index: &SyncIndex,
+    packages: &[ResolvedPackage],
+    downgrades: &[String],
+) -> Vec<TransactionItem> {
+    packages
+        .iter()
+        .map(|pkg| TransactionItem {
+            slug: pkg.project.slug.clone(),
+            old_version: index
+                .find_project(pkg.provider, &pkg.project.id)
+                .map(|it| it.version.clone())
+                .unwrap_or_default(),
+            new_version: pkg.version.version_number.clone(),
+            downgrade: downgrades.contains(&pkg.project.id),
+            size: pkg.file().size,
+        })
+        .collect()
+}
+
+/// Print a table of the packages that are about to be upgraded, with their old and new versions.
+/// Downgrades are marked as such.
+fn print_upgrade_table(items: &[TransactionItem]) {
+    // REFERENCE (pacman with VerbosePkgLists):
+    // Package (1)    Old Version  New Version  Download Size
+    //
+    // sodium         0.5.0        0.5.3             1.02 MiB
+    let header = [
+        format!("Package ({})", items.len()),
+        "Old Version".to_string(),
+        "New Version".to_string(),
+    ];
+    let rows = items
+        .iter()
+        .map(|it| {
+            [
+                it.slug.clone(),
+                it.old_version.clone(),
+                if it.downgrade {
+                    format!("{} (downgrade)", it.new_version)
+                } else {
+                    it.new_version.clone()
+                },
+                HumanBytes(it.size).to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let widths = (0..3)
+        .map(|i| {
+            rows.iter()
+                .map(|row| row[i].len())
+                .chain([header[i].len()])
+                .max()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    println!();
+    println!(
+        "{:<w0$}  {:<w1$}  {:<w2$}  {:>13}",
+        header[0].bold(),
+        header[1].bold(),
+        header[2].bold(),
+        "Download Size".bold(),
+        w0 = widths[0],
+        w1 = widths[1],
+        w2 = widths[2],
+    );
+    println!();
+    for (row, item) in rows.iter().zip(items) {
+        let new_color = if item.downgrade {
+            AnsiColors::Yellow
+        } else {
+            AnsiColors::Green
+        };
+        println!(
+            "{:<w0$}  {:<w1$}  {:<w2$}  {:>13}",
+            row[0],
+            row[1].red(),
+            row[2].color(new_color),
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+        );
+    }
+    println!();
+    println!(
+        "{total} {size}",
+        total = "Total Download Size:".bold(),
+        size = HumanBytes(items.iter().map(|it| it.size).sum())
+    );
+    println!();
+}
+
+/// Print the packages that are about to be installed, with their total download size.
+fn print_transaction(items: &[TransactionItem]) {
+    // REFERENCE:
+    // Packages (2) fabric-api-0.92.2+1.20.1  sodium-mc1.20.1-0.5.3
+    //
+    // Total Download Size:   3.21 MiB
+    println!();
+    println!(
+        "{packages_len} {list}",
+        packages_len = format!("Packages ({})", items.len()).bold(),
+        list = items
+            .iter()
+            .map(|it| format!("{}-{}", it.slug, it.new_version))
+            .collect::<Vec<_>>()
+            .join("  ")
+    );
+    println!();
+    println!(
+        "{total} {size}",
+        total = "Total Download Size:".bold(),
+        size = HumanBytes(items.iter().map(|it| it.size).sum())
+    );
+    println!();
 }
@@ -7,6 +7,7 @@ use startmc_downloader::DownloaderBuilder;
 
 use crate::{
     cli::{CliUpgrade, UpgradeKind},
+    sync::DEFAULT_DATAPACKS_DIR,
     util::cols,
 };
 
@@ -16,41 +17,45 @@ impl CliUpgrade {
             crate::config::UnresolvedConfig::find_with_path(instance).context("find config")?;
         let cols = cols();
         let mut queue: Vec<startmc_downloader::Download> = vec![];
-        let dest = match &self.kind {
-            UpgradeKind::Mod => {
-                println!(
-                    "{cols} {downloading} {amount} mods",
-                    downloading = "Downloading".bold(),
-                    amount = self.packages.len().green()
-                );
-
-                Path::new(&config.minecraft.directory).join("mods")
-            }
-            UpgradeKind::Resourcepack => {
-                println!(
-                    "{cols} {downloading} {amount} resourcepacks",
-                    downloading = "Downloading".bold(),
-                    amount = self.packages.len().green()
-                );
-
-                Path::new(&config.minecraft.directory).join("resourcepacks")
-            }
+        let (noun, dir) = match &self.kind {
+            UpgradeKind::Mod => ("mods", "mods".to_string()),
+            UpgradeKind::Resourcepack => ("resourcepacks", "resourcepacks".to_string()),
+            UpgradeKind::Shader => ("shader packs", "shaderpacks".to_string()),
+            UpgradeKind::Datapack => (
+                "datapacks",
+                config
+                    .sync
+                    .datapacks
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_DATAPACKS_DIR.to_string()),
+            ),
         };
+        println!(
+            "{cols} {downloading} {amount} {noun}",
+            downloading = "Downloading".bold(),
+            amount = self.packages.len().green()
+        );
+        let dest = Path::new(&config.minecraft.directory).join(dir);
+        std::fs::create_dir_all(&dest)?;
 
+        let mut sync = crate::sync::Sync::new(&config_path, &config)?;
+        sync.begin(&format!("-U {}", self.packages.join(" ")))?;
         for package in &self.packages {
-            match Url::parse(&package) {
+            match Url::parse(package) {
                 Ok(url) => {
+                    let target = dest.join(package.split('/').next_back().unwrap());
+                    sync.transaction.create(&target)?;
                     queue.push(startmc_downloader::Download::new(
                         &url,
-                        dest.join(package.split('/').last().unwrap())
-                            .to_str()
-                            .unwrap(),
+                        target.to_str().unwrap(),
                         None,
                     ));
                 }
                 Err(_) => {
                     let path = Path::new(&package);
-                    std::fs::copy(path, dest.join(path.file_name().unwrap())).unwrap();
+                    let target = dest.join(path.file_name().unwrap());
+                    sync.transaction.create(&target)?;
+                    std::fs::copy(path, target).unwrap();
                 }
             }
         }
@@ -58,31 +63,18 @@ impl CliUpgrade {
         let downloader = DownloaderBuilder::new().concurrent_downloads(10).build();
         downloader.download(&queue).await;
 
-        match &self.kind {
-            UpgradeKind::Mod => {
-                println!(
-                    "{cols} {installed} {amount} mods",
-                    installed = "Finished downloading".bold(),
-                    amount = self.packages.len().green()
-                );
-            }
-            UpgradeKind::Resourcepack => {
-                println!(
-                    "{cols} {installed} {amount} resourcepacks",
-                    installed = "Finished downloading".bold(),
-                    amount = self.packages.len().green()
-                );
-            }
-        }
+        println!(
+            "{cols} {installed} {amount} {noun}",
+            installed = "Finished downloading".bold(),
+            amount = self.packages.len().green()
+        );
 
-        let mut sync =
-            crate::sync::Sync::new(&config_path, Path::new(&config.minecraft.directory))?;
         println!(
             "{cols} {refreshing}",
             refreshing = "Refreshing content index...".bold()
         );
-        sync.refresh().await?;
-
-        Ok(())
+        let summary = sync.refresh().await?;
+        super::sync::print_refresh_summary(&summary);
+        sync.commit(&config_path)
     }
 }
@@ -0,0 +1,45 @@
+use std::path::Path;
+
+use chrono::Utc;
+use chrono_humanize::{Accuracy, Tense};
+use color_eyre::eyre::Context;
+use indicatif::HumanBytes;
+use owo_colors::OwoColorize;
+
+use crate::{cli::CliWorlds, config::UnresolvedConfig, util::dir_size, world};
+
+impl CliWorlds {
+    pub async fn exec(self, instance: &str) -> color_eyre::Result<()> {
+        let config = UnresolvedConfig::find(instance).context("find config")?;
+        let saves = Path::new(&config.minecraft.directory).join("saves");
+        let worlds = world::read_all(Path::new(&config.minecraft.directory))?;
+        if worlds.is_empty() {
+            println!(" there are no worlds");
+        }
+
+        for world in worlds {
+            let played = world
+                .last_played
+                .map(|it| {
+                    chrono_humanize::HumanTime::from(Utc::now() - it)
+                        .to_text_en(Accuracy::Rough, Tense::Past)
+                })
+                .unwrap_or_else(|| "never".to_string());
+            let mode = if world.hardcore {
+                format!("{} {}", world.game_mode, "Hardcore".red().bold())
+            } else {
+                world.game_mode.to_string()
+            };
+            println!(
+                "{name} {version} ({folder})\n    {mode} {bar} played {played} {bar} {size}",
+                name = world.name.bold(),
+                version = world.version.as_deref().unwrap_or("unknown").green().bold(),
+                folder = world.folder,
+                played = played.green().italic(),
+                bar = "|".bright_white().dimmed().bold(),
+                size = HumanBytes(dir_size(&saves.join(&world.folder))?),
+            );
+        }
+        Ok(())
+    }
+}
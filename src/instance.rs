@@ -0,0 +1,526 @@
+//! Cloning, renaming and deleting whole instances: their config, lock file and directory.
+
+use std::path::{Path, PathBuf};
+
+use color_eyre::{
+    Result,
+    eyre::{Context, bail},
+};
+
+use crate::{
+    backup::Backups,
+    config::{
+        UnresolvedConfig,
+        layers::{find_parent, global_path, read_table},
+    },
+    sync::{SyncIndex, Transaction, hash_cache::HashCache},
+    util::{copy_dir, move_dir, symlink_dir},
+};
+
+/// What a clone does with the worlds of the instance it's cloned from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SavesMode {
+    /// The clone gets its own copy of the worlds
+    Copy,
+    /// The clone's `saves` is a link to the original's, so both play the same worlds
+    Share,
+}
+
+/// Where `init` puts the directory of an instance named `instance` by default.
+pub fn default_directory(instance: &str) -> PathBuf {
+    dirs::data_dir()
+        .expect("data directory not found")
+        .join("startmc")
+        .join("instances")
+        .join(instance)
+}
+
+/// The config path of an instance named `instance` next to the config at `config_path`, with
+/// the same naming, so `a.toml` becomes `b.toml` and `a.startmc.toml` becomes `b.startmc.toml`.
+pub fn sibling_config_path(config_path: &Path, instance: &str) -> PathBuf {
+    let is_local = config_path
+        .file_name()
+        .is_some_and(|it| it.to_string_lossy().ends_with(".startmc.toml"));
+    config_path.with_file_name(if is_local {
+        format!("{instance}.startmc.toml")
+    } else {
+        format!("{instance}.toml")
+    })
+}
+
+fn check_free(config_path: &Path, directory: Option<&Path>) -> Result<()> {
+    if config_path.exists() {
+        bail!(
+            "there already is an instance config at {}",
+            config_path.display()
+        );
+    }
+    if let Some(directory) = directory
+        && directory.exists()
+        && std::fs::read_dir(directory)?.next().is_some()
+    {
+        bail!("{} already exists and isn't empty", directory.display());
+    }
+    Ok(())
+}
+
+/// Copy the instance with the config at `config_path` into a new instance with the config at
+/// `new_config_path` and the directory `directory`: its config, lock file and directory.
+pub fn clone(
+    config_path: &Path,
+    config: &UnresolvedConfig,
+    new_config_path: &Path,
+    directory: &Path,
+    saves: SavesMode,
+) -> Result<()> {
+    check_free(new_config_path, Some(directory))?;
+    let source = Path::new(&config.minecraft.directory);
+    std::fs::create_dir_all(directory)
+        .with_context(|| format!("create {}", directory.display()))?;
+    copy_dir(source, directory, |it| {
+        saves == SavesMode::Share && it.starts_with("saves")
+    })
+    .with_context(|| format!("copy {}", source.display()))?;
+    let source_saves = source.join("saves");
+    if saves == SavesMode::Share && source_saves.exists() {
+        // link to the worlds themselves if the original shares them too
+        let target = std::fs::canonicalize(&source_saves)?;
+        symlink_dir(&target, &directory.join("saves")).context("link saves")?;
+    }
+
+    let lock_path = SyncIndex::get_lock_path(config_path);
+    if lock_path.exists() {
+        std::fs::copy(&lock_path, SyncIndex::get_lock_path(new_config_path))
+            .context("copy lock file")?;
+    }
+    write_with_directory(config_path, new_config_path, directory)
+}
+
+/// Give the instance with the config at `config_path` the config at `new_config_path`, moving
+/// its lock file along, and move its directory to `directory` if it's given. Configs that
+/// extend the instance are changed to extend it under its new name, and are returned.
+pub fn rename(
+    config_path: &Path,
+    config: &UnresolvedConfig,
+    new_config_path: &Path,
+    directory: Option<&Path>,
+) -> Result<Vec<PathBuf>> {
+    check_free(new_config_path, directory)?;
+    let lock_path = SyncIndex::get_lock_path(config_path);
+    let children = extending(config_path);
+    if let Some(directory) = directory {
+        let source = Path::new(&config.minecraft.directory);
+        let sharing = sharing_saves(config_path, config);
+        let data = InstanceData::of(source);
+        if let Some(parent) = directory.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // an empty directory is in the way of the rename
+        if directory.exists() {
+            std::fs::remove_dir(directory)?;
+        }
+        move_dir(source, directory)
+            .with_context(|| format!("move {} to {}", source.display(), directory.display()))?;
+        data.move_to(&InstanceData::of(directory))?;
+        // clones that share the worlds would link to where they were
+        for (_, clone_directory) in &sharing {
+            let link = clone_directory.join("saves");
+            std::fs::remove_file(&link).or_else(|_| std::fs::remove_dir(&link))?;
+            symlink_dir(&std::fs::canonicalize(directory.join("saves"))?, &link)
+                .with_context(|| format!("link {}", link.display()))?;
+        }
+
+        write_with_directory(config_path, new_config_path, directory)?;
+        std::fs::remove_file(config_path)?;
+    } else {
+        std::fs::rename(config_path, new_config_path)
+            .with_context(|| format!("move {}", config_path.display()))?;
+    }
+    if lock_path.exists() {
+        std::fs::rename(&lock_path, SyncIndex::get_lock_path(new_config_path))
+            .context("move lock file")?;
+    }
+    for child in &children {
+        set_extends(child, new_config_path)?;
+    }
+    Ok(children)
+}
+
+/// Make the config at `path` extend the config at `parent`, by name if that finds it.
+fn set_extends(path: &Path, parent: &Path) -> Result<()> {
+    let name = parent
+        .file_name()
+        .and_then(|it| it.to_str())
+        .and_then(|it| {
+            it.strip_suffix(".startmc.toml")
+                .or_else(|| it.strip_suffix(".toml"))
+        })
+        .filter(|name| find_parent(path, name).is_ok_and(|it| same_file(&it, parent)))
+        .map(str::to_string)
+        .unwrap_or_else(|| parent.to_string_lossy().into_owned());
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let mut document = contents
+        .parse::<toml_edit::DocumentMut>()
+        .with_context(|| format!("parse {}", path.display()))?;
+    document["extends"] = toml_edit::value(name);
+    std::fs::write(path, document.to_string()).with_context(|| format!("write {}", path.display()))
+}
+
+/// Write the config at `from` to `to` with `directory` as its instance directory. Only the
+/// instance's own config is copied, so that it still extends the same config.
+fn write_with_directory(from: &Path, to: &Path, directory: &Path) -> Result<()> {
+    let contents =
+        std::fs::read_to_string(from).with_context(|| format!("read {}", from.display()))?;
+    let mut document = contents
+        .parse::<toml_edit::DocumentMut>()
+        .with_context(|| format!("parse {}", from.display()))?;
+    let minecraft = document.entry("minecraft").or_insert_with(toml_edit::table);
+    let Some(minecraft) = minecraft.as_table_like_mut() else {
+        bail!("{}: minecraft must be a table", from.display());
+    };
+    minecraft.insert(
+        "directory",
+        toml_edit::value(directory.to_string_lossy().into_owned()),
+    );
+    std::fs::write(to, document.to_string()).with_context(|| format!("write {}", to.display()))
+}
+
+/// Delete the instance with the config at `config_path`: its config, lock file, directory and
+/// content history. With `keep_worlds`, `saves` is left in the directory. Backups of its worlds
+/// are kept.
+pub fn delete(config_path: &Path, config: &UnresolvedConfig, keep_worlds: bool) -> Result<()> {
+    let directory = Path::new(&config.minecraft.directory);
+    let data = InstanceData::of(directory);
+    if directory.exists() {
+        if keep_worlds {
+            for entry in std::fs::read_dir(directory)? {
+                let entry = entry?;
+                if entry.file_name() == "saves" {
+                    continue;
+                }
+                if entry.file_type()?.is_dir() {
+                    std::fs::remove_dir_all(entry.path())?;
+                } else {
+                    std::fs::remove_file(entry.path())?;
+                }
+            }
+        } else {
+            // a shared `saves` is a link, which is removed without the worlds it links to
+            std::fs::remove_dir_all(directory)
+                .with_context(|| format!("delete {}", directory.display()))?;
+        }
+    }
+    for path in [data.history, data.hash_cache] {
+        if path.is_dir() {
+            std::fs::remove_dir_all(path)?;
+        } else if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    let lock_path = SyncIndex::get_lock_path(config_path);
+    if lock_path.exists() {
+        std::fs::remove_file(lock_path)?;
+    }
+    std::fs::remove_file(config_path).with_context(|| format!("delete {}", config_path.display()))
+}
+
+/// The configs of other instances: next to the config at `config_path`, in the config
+/// directory, and in the current directory. Outside of the config directory, only
+/// `*.startmc.toml` files are instance configs. Lock files and the global defaults are left out.
+fn other_configs(config_path: &Path) -> Vec<PathBuf> {
+    let config_dir = dirs::config_dir().map(|it| it.join("startmc"));
+    let mut dirs = vec![
+        config_path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+        PathBuf::from("."),
+    ];
+    dirs.extend(config_dir.clone());
+    let global = global_path();
+    let mut configs: Vec<PathBuf> = vec![];
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let suffix = if config_dir.as_ref().is_some_and(|it| same_file(it, &dir)) {
+            ".toml"
+        } else {
+            ".startmc.toml"
+        };
+        for path in entries.filter_map(|it| Some(it.ok()?.path())) {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if !name.ends_with(suffix)
+                || name.contains(".lock.")
+                || !path.is_file()
+                || same_file(&path, config_path)
+                || global.as_ref().is_some_and(|it| same_file(&path, it))
+                || configs.iter().any(|it| same_file(it, &path))
+            {
+                continue;
+            }
+            configs.push(path);
+        }
+    }
+    configs
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Configs of the instances that extend the config at `config_path` directly, which can't be
+/// read without it.
+pub fn extending(config_path: &Path) -> Vec<PathBuf> {
+    other_configs(config_path)
+        .into_iter()
+        .filter(|path| {
+            let Ok(table) = read_table(path) else {
+                return false;
+            };
+            table
+                .get("extends")
+                .and_then(|it| it.as_str())
+                .and_then(|name| find_parent(path, name).ok())
+                .is_some_and(|parent| same_file(&parent, config_path))
+        })
+        .collect()
+}
+
+/// Clones of the instance with the config at `config_path` that share its worlds, as their
+/// config paths and directories. Their `saves` is a link to the instance's.
+pub fn sharing_saves(config_path: &Path, config: &UnresolvedConfig) -> Vec<(PathBuf, PathBuf)> {
+    let saves = Path::new(&config.minecraft.directory).join("saves");
+    // an instance that shares another one's worlds itself doesn't own them
+    if saves
+        .symlink_metadata()
+        .is_ok_and(|it| it.file_type().is_symlink())
+    {
+        return vec![];
+    }
+    other_configs(config_path)
+        .into_iter()
+        .filter_map(|path| {
+            let directory = PathBuf::from(UnresolvedConfig::read(&path).ok()?.minecraft.directory);
+            let link = directory.join("saves");
+            let is_link = link.symlink_metadata().ok()?.file_type().is_symlink();
+            (is_link && same_file(&link, &saves)).then_some((path, directory))
+        })
+        .collect()
+}
+
+/// What startmc keeps about an instance outside of it, by its directory.
+struct InstanceData {
+    history: PathBuf,
+    backups: PathBuf,
+    hash_cache: PathBuf,
+}
+
+impl InstanceData {
+    fn of(minecraft_directory: &Path) -> Self {
+        Self {
+            history: Transaction::history_dir(minecraft_directory),
+            backups: Backups::new(minecraft_directory, &Default::default()).dir,
+            hash_cache: HashCache::path(minecraft_directory),
+        }
+    }
+
+    fn move_to(&self, other: &InstanceData) -> Result<()> {
+        for (from, to) in [
+            (&self.history, &other.history),
+            (&self.backups, &other.backups),
+            (&self.hash_cache, &other.hash_cache),
+        ] {
+            if from.exists() && !to.exists() {
+                std::fs::rename(from, to)
+                    .with_context(|| format!("move {} to {}", from.display(), to.display()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::MinecraftConfig;
+
+    use super::*;
+
+    fn instance(root: &Path) -> (PathBuf, UnresolvedConfig) {
+        let directory = root.join("a");
+        std::fs::create_dir_all(directory.join("mods")).unwrap();
+        std::fs::create_dir_all(directory.join("saves/World")).unwrap();
+        std::fs::write(directory.join("mods/sodium.jar"), "sodium").unwrap();
+        std::fs::write(directory.join("saves/World/level.dat"), "level").unwrap();
+        let config = UnresolvedConfig {
+            schema: crate::migrate::CONFIG.current(),
+            extends: None,
+            minecraft: MinecraftConfig {
+                version: "1.21".to_string(),
+                directory: directory.to_string_lossy().into_owned(),
+            },
+            loader: Default::default(),
+            paths: Default::default(),
+            args: Default::default(),
+            log4j: Default::default(),
+            username: None,
+            uuid: None,
+            sync: Default::default(),
+            backup: Default::default(),
+            servers: vec![],
+            options: Default::default(),
+        };
+        let config_path = root.join("a.startmc.toml");
+        config.write(&config_path).unwrap();
+        std::fs::write(SyncIndex::get_lock_path(&config_path), "packages = []\n").unwrap();
+        (config_path, config)
+    }
+
+    #[test]
+    fn test_sibling_config_path() {
+        assert_eq!(
+            sibling_config_path(Path::new("/config/startmc/a.toml"), "b"),
+            Path::new("/config/startmc/b.toml")
+        );
+        assert_eq!(
+            sibling_config_path(Path::new("./a.startmc.toml"), "b"),
+            Path::new("./b.startmc.toml")
+        );
+    }
+
+    #[test]
+    fn test_write_with_directory() {
+        let root = tempfile::tempdir().unwrap();
+        let from = root.path().join("a.toml");
+        std::fs::write(
+            &from,
+            "schema = 2\n# the modded one\n[minecraft]\nversion = \"1.21\" # pinned\ndirectory = \"/old\"\n",
+        )
+        .unwrap();
+        let to = root.path().join("b.toml");
+        write_with_directory(&from, &to, Path::new("/new")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&to).unwrap(),
+            "schema = 2\n# the modded one\n[minecraft]\nversion = \"1.21\" # pinned\ndirectory = \"/new\"\n"
+        );
+    }
+
+    #[test]
+    fn test_clone_and_delete() {
+        let root = tempfile::tempdir().unwrap();
+        let (config_path, config) = instance(root.path());
+
+        let b = root.path().join("b.startmc.toml");
+        clone(
+            &config_path,
+            &config,
+            &b,
+            &root.path().join("b"),
+            SavesMode::Copy,
+        )
+        .unwrap();
+        let cloned = UnresolvedConfig::read(&b).unwrap();
+        assert_eq!(
+            Path::new(&cloned.minecraft.directory),
+            root.path().join("b")
+        );
+        assert!(SyncIndex::get_lock_path(&b).exists());
+        assert!(root.path().join("b/saves/World/level.dat").is_file());
+        assert!(
+            clone(
+                &config_path,
+                &config,
+                &b,
+                &root.path().join("c"),
+                SavesMode::Copy
+            )
+            .is_err()
+        );
+
+        let c = root.path().join("c.startmc.toml");
+        clone(
+            &config_path,
+            &config,
+            &c,
+            &root.path().join("c"),
+            SavesMode::Share,
+        )
+        .unwrap();
+        let saves = root.path().join("c/saves");
+        assert!(saves.symlink_metadata().unwrap().file_type().is_symlink());
+        assert!(saves.join("World/level.dat").is_file());
+
+        // deleting the clone that shares the worlds leaves them alone
+        let c_config = UnresolvedConfig::read(&c).unwrap();
+        delete(&c, &c_config, false).unwrap();
+        assert!(!c.exists() && !root.path().join("c").exists());
+        assert!(root.path().join("a/saves/World/level.dat").is_file());
+
+        delete(&b, &cloned, true).unwrap();
+        assert!(!b.exists() && !SyncIndex::get_lock_path(&b).exists());
+        assert!(root.path().join("b/saves/World/level.dat").is_file());
+        assert!(!root.path().join("b/mods").exists());
+    }
+
+    #[test]
+    fn test_rename() {
+        let root = tempfile::tempdir().unwrap();
+        let (config_path, config) = instance(root.path());
+
+        let b = root.path().join("b.startmc.toml");
+        rename(&config_path, &config, &b, None).unwrap();
+        assert!(!config_path.exists() && b.exists());
+        assert!(SyncIndex::get_lock_path(&b).exists());
+        assert_eq!(
+            UnresolvedConfig::read(&b).unwrap().minecraft.directory,
+            config.minecraft.directory
+        );
+
+        let c = root.path().join("c.startmc.toml");
+        let config = UnresolvedConfig::read(&b).unwrap();
+        rename(&b, &config, &c, Some(&root.path().join("c"))).unwrap();
+        assert!(!b.exists() && !root.path().join("a").exists());
+        assert!(root.path().join("c/mods/sodium.jar").is_file());
+        assert_eq!(
+            Path::new(&UnresolvedConfig::read(&c).unwrap().minecraft.directory),
+            root.path().join("c")
+        );
+    }
+
+    #[test]
+    fn test_related_instances() {
+        let root = tempfile::tempdir().unwrap();
+        let (config_path, config) = instance(root.path());
+        let b = root.path().join("b.startmc.toml");
+        clone(
+            &config_path,
+            &config,
+            &b,
+            &root.path().join("b"),
+            SavesMode::Share,
+        )
+        .unwrap();
+        let child = root.path().join("child.startmc.toml");
+        std::fs::write(&child, "schema = 2\n# mine\nextends = \"a\"\n").unwrap();
+
+        let sharing = sharing_saves(&config_path, &config);
+        assert_eq!(sharing.len(), 1);
+        assert!(same_file(&sharing[0].0, &b));
+        // the clone doesn't own the worlds it shares
+        let b_config = UnresolvedConfig::read(&b).unwrap();
+        assert!(sharing_saves(&b, &b_config).is_empty());
+        assert_eq!(extending(&config_path).len(), 1);
+
+        // renaming keeps the clone's worlds and the child working
+        let c = root.path().join("c.startmc.toml");
+        let children = rename(&config_path, &config, &c, Some(&root.path().join("c"))).unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(
+            std::fs::read_to_string(&child).unwrap(),
+            "schema = 2\n# mine\nextends = \"c\"\n"
+        );
+        assert!(root.path().join("b/saves/World/level.dat").is_file());
+        assert!(UnresolvedConfig::read(&child).is_ok());
+    }
+}
@@ -1,12 +1,20 @@
 #[macro_use]
 extern crate tracing;
 
+pub mod backup;
 pub mod cache;
 pub mod cli;
 pub mod config;
 pub mod exec;
+pub mod instance;
+pub mod migrate;
+pub mod modpack;
+pub mod nbt;
+pub mod options;
+pub mod servers;
 pub mod sync;
 pub mod util;
+pub mod world;
 
 // Re-export crates for library users
 pub use startmc_downloader as downloader;
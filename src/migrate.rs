@@ -0,0 +1,203 @@
+//! Schema versions of the files startmc keeps, and the migrations that upgrade old files.
+//!
+//! Every file has a `schema` key with the version of its layout, and files without one are
+//! from before there were versions, version 1. A file is upgraded in place the first time it's
+//! read, after a copy of the old file is kept next to it. Migrations edit the document, so
+//! comments and formatting stay as they were.
+
+use std::path::{Path, PathBuf};
+
+use color_eyre::{
+    Result,
+    eyre::{Context, bail, eyre},
+};
+use toml_edit::{DocumentMut, Item, Table, value};
+
+/// A migration upgrades a document from one schema version to the next.
+type Migration = fn(&mut DocumentMut) -> Result<()>;
+
+/// The migrations of one kind of file, in order. The first one upgrades from version 1.
+pub struct Migrations {
+    /// What the file is, for messages
+    pub name: &'static str,
+    steps: &'static [Migration],
+}
+
+impl Migrations {
+    /// The schema version that files are upgraded to.
+    pub const fn current(&self) -> u32 {
+        self.steps.len() as u32 + 1
+    }
+}
+
+/// Instance configs, and the global defaults file.
+pub const CONFIG: Migrations = Migrations {
+    name: "config",
+    steps: &[config_loader_table],
+};
+
+/// Lock files, see [`crate::sync::SyncIndex`].
+pub const LOCK: Migrations = Migrations {
+    name: "lock file",
+    steps: &[lock_project_fields],
+};
+
+/// Read the TOML file at `path`, upgrading it in place first if it's from an older schema
+/// version. Fails if it's from a newer one, which this version of startmc can't read.
+pub fn read(path: &Path, migrations: &Migrations) -> Result<String> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let mut document = contents
+        .parse::<DocumentMut>()
+        .map_err(|err| eyre!("{}: {err}", path.display()))?;
+    let Some(schema) = migrate(&mut document, migrations)
+        .with_context(|| format!("upgrade {}", path.display()))?
+    else {
+        return Ok(contents);
+    };
+
+    let backup = backup_path(path, schema);
+    std::fs::copy(path, &backup).with_context(|| format!("back up {}", path.display()))?;
+    let migrated = document.to_string();
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, &migrated)?;
+    std::fs::rename(&temp, path)?;
+    info!(
+        "upgraded {name} {path} from schema {schema} to {current}, the old one is in {backup}",
+        name = migrations.name,
+        path = path.display(),
+        current = migrations.current(),
+        backup = backup.display()
+    );
+    Ok(migrated)
+}
+
+/// Where the copy of a file from `schema` is kept when it's upgraded.
+pub fn backup_path(path: &Path, schema: i64) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".v{schema}.bak"));
+    path.with_file_name(name)
+}
+
+/// Upgrade `document` to the current schema version. Returns the version it was upgraded
+/// from, or `None` if it's current.
+fn migrate(document: &mut DocumentMut, migrations: &Migrations) -> Result<Option<i64>> {
+    let schema = match document.get("schema") {
+        None => 1,
+        Some(item) => item
+            .as_integer()
+            .ok_or_else(|| eyre!("schema must be a number"))?,
+    };
+    let current = i64::from(migrations.current());
+    if schema > current {
+        bail!(
+            "the {name} is from schema version {schema}, but this version of startmc only knows \
+             up to {current}, upgrade startmc to use it",
+            name = migrations.name
+        );
+    }
+    if schema == current {
+        return Ok(None);
+    }
+    if schema < 1 {
+        bail!("schema version {schema} doesn't exist");
+    }
+    for step in &migrations.steps[schema as usize - 1..] {
+        step(document)?;
+    }
+    document.insert("schema", value(current));
+    // keep the version at the top, where it's easy to find
+    document.sort_values_by(|a, _, b, _| (b.get() == "schema").cmp(&(a.get() == "schema")));
+    Ok(Some(schema))
+}
+
+/// 1 to 2: `[minecraft.fabric]` becomes `[loader]` with a `type`, so that other loaders fit.
+fn config_loader_table(document: &mut DocumentMut) -> Result<()> {
+    let Some(minecraft) = document
+        .get_mut("minecraft")
+        .and_then(Item::as_table_like_mut)
+    else {
+        return Ok(());
+    };
+    let Some(fabric) = minecraft.remove("fabric") else {
+        return Ok(());
+    };
+    let version = fabric
+        .get("version")
+        .and_then(Item::as_str)
+        .ok_or_else(|| eyre!("minecraft.fabric.version must be a string"))?
+        .to_string();
+    let mut loader = Table::new();
+    loader.insert("type", value("fabric"));
+    loader.insert("version", value(version));
+    document.insert("loader", Item::Table(loader));
+    Ok(())
+}
+
+/// 1 to 2: packages from before there were providers only came from Modrinth, and had
+/// `modrinth_project` and `modrinth_version_id` instead of `project_id` and `version_id`.
+fn lock_project_fields(document: &mut DocumentMut) -> Result<()> {
+    let Some(packages) = document
+        .get_mut("packages")
+        .and_then(Item::as_array_of_tables_mut)
+    else {
+        return Ok(());
+    };
+    for package in packages.iter_mut() {
+        for (old, new) in [
+            ("modrinth_project", "project_id"),
+            ("modrinth_version_id", "version_id"),
+        ] {
+            if let Some(item) = package.remove(old) {
+                package.insert(new, item);
+            }
+        }
+        if !package.contains_key("provider") && package.contains_key("project_id") {
+            package.insert("provider", value("modrinth"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_migration() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("default.toml");
+        let old = "# my instance\nusername = \"Steve\"\n\n[minecraft]\nversion = \"1.21\"\ndirectory = \"/a\"\n\n[minecraft.fabric]\nversion = \"0.16.5\"\n";
+        std::fs::write(&path, old).unwrap();
+
+        let migrated = read(&path, &CONFIG).unwrap();
+        assert_eq!(
+            migrated,
+            "schema = 2\n# my instance\nusername = \"Steve\"\n\n[minecraft]\nversion = \"1.21\"\ndirectory = \"/a\"\n\n[loader]\ntype = \"fabric\"\nversion = \"0.16.5\"\n"
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), migrated);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("default.toml.v1.bak")).unwrap(),
+            old
+        );
+
+        // current files are left alone
+        assert_eq!(read(&path, &CONFIG).unwrap(), migrated);
+
+        std::fs::write(&path, "schema = 3\n").unwrap();
+        let err = read(&path, &CONFIG).unwrap_err();
+        assert!(format!("{err:?}").contains("upgrade startmc"));
+    }
+
+    #[test]
+    fn test_lock_migration() {
+        let mut document = "[[packages]]\nid = \"sodium\"\nmodrinth_project = \"AANobbMI\"\n\n[[packages]]\nid = \"custom\"\n"
+            .parse::<DocumentMut>()
+            .unwrap();
+        assert_eq!(migrate(&mut document, &LOCK).unwrap(), Some(1));
+        assert_eq!(
+            document.to_string(),
+            "schema = 2\n[[packages]]\nid = \"sodium\"\nproject_id = \"AANobbMI\"\nprovider = \"modrinth\"\n\n[[packages]]\nid = \"custom\"\n"
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Creating instances from modpacks, and exporting them.
+
+use std::{
+    fs::File,
+    io::{Read, Seek},
+    path::{Component, Path, PathBuf},
+};
+
+use color_eyre::{
+    Result,
+    eyre::{Context, bail},
+};
+use zip::ZipArchive;
+
+use crate::config::ModLoader;
+
+pub mod curseforge;
+pub mod mrpack;
+
+/// A modpack in one of the formats startmc can import.
+pub enum Modpack {
+    Modrinth(mrpack::Mrpack<File>),
+    CurseForge(curseforge::CurseForgePack<File>),
+}
+
+impl Modpack {
+    /// Open a modpack, telling the format apart by the index file inside of it.
+    pub fn open(path: &Path) -> Result<Self> {
+        let archive = ZipArchive::new(File::open(path)?)
+            .with_context(|| format!("read modpack {path}", path = path.display()))?;
+        if archive.index_for_name(mrpack::INDEX_FILE).is_some() {
+            Ok(Self::Modrinth(mrpack::Mrpack::from_archive(archive)?))
+        } else if archive.index_for_name(curseforge::MANIFEST_FILE).is_some() {
+            Ok(Self::CurseForge(curseforge::CurseForgePack::from_archive(
+                archive,
+            )?))
+        } else {
+            bail!(
+                "{path} is not a Modrinth or CurseForge modpack",
+                path = path.display()
+            )
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Modrinth(pack) => &pack.index.name,
+            Self::CurseForge(pack) => &pack.manifest.name,
+        }
+    }
+
+    pub fn version(&self) -> &str {
+        match self {
+            Self::Modrinth(pack) => &pack.index.version_id,
+            Self::CurseForge(pack) => &pack.manifest.version,
+        }
+    }
+
+    pub fn game_version(&self) -> Result<&str> {
+        match self {
+            Self::Modrinth(pack) => pack.index.game_version(),
+            Self::CurseForge(pack) => Ok(&pack.manifest.minecraft.version),
+        }
+    }
+
+    pub fn mod_loader(&self) -> Result<ModLoader> {
+        match self {
+            Self::Modrinth(pack) => pack.index.mod_loader(),
+            Self::CurseForge(pack) => pack.manifest.mod_loader(),
+        }
+    }
+
+    pub fn extract_overrides(&mut self, minecraft_directory: &Path) -> Result<usize> {
+        match self {
+            Self::Modrinth(pack) => pack.extract_overrides(minecraft_directory),
+            Self::CurseForge(pack) => pack.extract_overrides(minecraft_directory),
+        }
+    }
+}
+
+/// Join a path from a modpack onto `dir`, refusing paths that would end up outside of it.
+pub fn safe_join(dir: &Path, path: &str) -> Result<PathBuf> {
+    let relative = Path::new(path);
+    if path.is_empty()
+        || !relative
+            .components()
+            .all(|it| matches!(it, Component::Normal(_)))
+    {
+        bail!("unsafe path in modpack: {path}");
+    }
+    Ok(dir.join(relative))
+}
+
+/// Extract everything under `prefix/` in `archive` into `dir`, overwriting existing files.
+/// Returns the number of extracted files.
+pub fn extract_dir<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    prefix: &str,
+    dir: &Path,
+) -> Result<usize> {
+    let prefix = format!("{}/", prefix.trim_end_matches('/'));
+    let mut extracted = 0;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let Some(relative) = file.name().strip_prefix(&prefix) else {
+            continue;
+        };
+        let relative = relative.trim_end_matches('/');
+        if relative.is_empty() {
+            continue;
+        }
+        let path = safe_join(dir, relative)?;
+        if file.is_dir() {
+            std::fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut out = std::fs::File::create(&path)
+            .with_context(|| format!("create {path}", path = path.display()))?;
+        std::io::copy(&mut file, &mut out)?;
+        extracted += 1;
+    }
+    Ok(extracted)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    use super::*;
+
+    #[test]
+    fn test_safe_join() {
+        let dir = Path::new("/instance");
+        assert_eq!(
+            safe_join(dir, "mods/sodium.jar").unwrap(),
+            dir.join("mods/sodium.jar")
+        );
+        assert!(safe_join(dir, "../escape.jar").is_err());
+        assert!(safe_join(dir, "mods/../../escape.jar").is_err());
+        assert!(safe_join(dir, "/etc/passwd").is_err());
+        assert!(safe_join(dir, "").is_err());
+    }
+
+    #[test]
+    fn test_extract_dir() {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        for (name, contents) in [
+            ("overrides/config/sodium.json", "{}"),
+            ("overrides/options.txt", "fov:0.5"),
+            ("client-overrides/options.txt", "fov:1.0"),
+            ("modrinth.index.json", "{}"),
+        ] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        let mut archive = ZipArchive::new(zip.finish().unwrap()).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            extract_dir(&mut archive, "overrides", dir.path()).unwrap(),
+            2
+        );
+        assert_eq!(
+            extract_dir(&mut archive, "client-overrides", dir.path()).unwrap(),
+            1
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("options.txt")).unwrap(),
+            "fov:1.0"
+        );
+        assert!(dir.path().join("config/sodium.json").is_file());
+        assert!(!dir.path().join("modrinth.index.json").exists());
+    }
+}
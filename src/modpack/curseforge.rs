@@ -0,0 +1,289 @@
+//! CurseForge modpacks, which are zips with a `manifest.json` listing CurseForge files by id.
+
+use std::{
+    collections::HashMap,
+    io::{Read, Seek},
+    path::Path,
+};
+
+use color_eyre::{
+    Result,
+    eyre::{Context, ContextCompat, bail},
+};
+use serde::Deserialize;
+use zip::ZipArchive;
+
+use crate::{
+    config::ModLoader,
+    sync::{ContentProvider, ResolvedPackage},
+};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub minecraft: ManifestMinecraft,
+    pub manifest_type: String,
+    pub manifest_version: u32,
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub files: Vec<ManifestFile>,
+    /// Directory in the zip with files that are copied into the instance
+    #[serde(default = "default_overrides")]
+    pub overrides: String,
+}
+
+fn default_overrides() -> String {
+    "overrides".to_string()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestMinecraft {
+    pub version: String,
+    #[serde(default)]
+    pub mod_loaders: Vec<ManifestModLoader>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ManifestModLoader {
+    /// The loader and its version, like `fabric-0.14.22` or `forge-47.1.0`
+    pub id: String,
+    #[serde(default)]
+    pub primary: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ManifestFile {
+    #[serde(rename = "projectID")]
+    pub project_id: u64,
+    #[serde(rename = "fileID")]
+    pub file_id: u64,
+    #[serde(default = "default_required")]
+    pub required: bool,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+impl Manifest {
+    /// The mod loader the pack needs. Fails for loaders startmc can't launch.
+    pub fn mod_loader(&self) -> Result<ModLoader> {
+        let Some(loader) = self
+            .minecraft
+            .mod_loaders
+            .iter()
+            .find(|it| it.primary)
+            .or(self.minecraft.mod_loaders.first())
+        else {
+            return Ok(ModLoader::Vanilla);
+        };
+        match loader.id.split_once('-') {
+            Some(("fabric", version)) => Ok(ModLoader::Fabric {
+                version: version.to_string(),
+            }),
+            Some(("quilt", version)) => Ok(ModLoader::Quilt {
+                version: version.to_string(),
+            }),
+            Some((other, version)) => bail!(
+                "{} needs {other} {version}, which startmc can't launch",
+                self.name
+            ),
+            None => bail!(
+                "{} needs {}, which startmc can't launch",
+                self.name,
+                loader.id
+            ),
+        }
+    }
+}
+
+/// Look up files of a pack on `provider`, which should be CurseForge or a stand-in for it.
+/// Files of projects that startmc can't install are skipped.
+pub async fn resolve(
+    provider: &dyn ContentProvider,
+    files: &[ManifestFile],
+) -> Result<Vec<ResolvedPackage>> {
+    if files.is_empty() {
+        return Ok(vec![]);
+    }
+    let project_ids = files
+        .iter()
+        .map(|it| it.project_id.to_string())
+        .collect::<Vec<_>>();
+    let file_ids = files
+        .iter()
+        .map(|it| it.file_id.to_string())
+        .collect::<Vec<_>>();
+    let mut projects = provider
+        .projects(&project_ids.iter().map(|it| it.as_str()).collect::<Vec<_>>())
+        .await?
+        .into_iter()
+        .map(|it| (it.id.clone(), it))
+        .collect::<HashMap<_, _>>();
+    let mut versions = provider
+        .versions_by_id(&file_ids.iter().map(|it| it.as_str()).collect::<Vec<_>>())
+        .await?
+        .into_iter()
+        .map(|it| (it.id.clone(), it))
+        .collect::<HashMap<_, _>>();
+
+    let mut packages = vec![];
+    for (project_id, file_id) in project_ids.iter().zip(&file_ids) {
+        let project = projects
+            .remove(project_id)
+            .with_context(|| format!("project {project_id} not found on {}", provider.kind()))?;
+        let version = versions.remove(file_id).with_context(|| {
+            format!(
+                "file {file_id} of {} not found on {}",
+                project.slug,
+                provider.kind()
+            )
+        })?;
+        let Some(kind) = project.kind else {
+            error!("unsupported project type of {}, skipping...", project.slug);
+            continue;
+        };
+        packages.push(ResolvedPackage {
+            provider: provider.kind(),
+            project,
+            version,
+            kind,
+        });
+    }
+    Ok(packages)
+}
+
+pub struct CurseForgePack<R> {
+    pub manifest: Manifest,
+    archive: ZipArchive<R>,
+}
+
+impl<R: Read + Seek> CurseForgePack<R> {
+    pub fn from_archive(mut archive: ZipArchive<R>) -> Result<Self> {
+        let manifest: Manifest = serde_json::from_reader(
+            archive
+                .by_name(MANIFEST_FILE)
+                .with_context(|| format!("no {MANIFEST_FILE} in modpack"))?,
+        )
+        .with_context(|| format!("parse {MANIFEST_FILE}"))?;
+        if manifest.manifest_type != "minecraftModpack" {
+            bail!("unsupported manifest type {}", manifest.manifest_type);
+        }
+        Ok(Self { manifest, archive })
+    }
+
+    pub fn extract_overrides(&mut self, minecraft_directory: &Path) -> Result<usize> {
+        super::extract_dir(
+            &mut self.archive,
+            &self.manifest.overrides,
+            minecraft_directory,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    use super::*;
+    use crate::sync::{
+        IndexEntryKind, ProviderKind,
+        fake::FakeProvider,
+        fixtures::{project, version},
+    };
+
+    const MANIFEST: &str = r#"{
+        "minecraft": {
+            "version": "1.20.1",
+            "modLoaders": [{ "id": "fabric-0.14.22", "primary": true }]
+        },
+        "manifestType": "minecraftModpack",
+        "manifestVersion": 1,
+        "name": "Team Pack",
+        "version": "1.0.0",
+        "author": "team",
+        "files": [
+            { "projectID": 238222, "fileID": 4644453, "required": true },
+            { "projectID": 394468, "fileID": 4658223, "required": false },
+            { "projectID": 520110, "fileID": 4708211, "required": true }
+        ],
+        "overrides": "overrides"
+    }"#;
+
+    fn pack(manifest: &str) -> Result<CurseForgePack<Cursor<Vec<u8>>>> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file(MANIFEST_FILE, SimpleFileOptions::default())?;
+        zip.write_all(manifest.as_bytes())?;
+        zip.start_file("overrides/config/jei.toml", SimpleFileOptions::default())?;
+        zip.write_all(b"[jei]")?;
+        CurseForgePack::from_archive(ZipArchive::new(zip.finish()?)?)
+    }
+
+    #[test]
+    fn test_manifest() {
+        let mut pack = pack(MANIFEST).unwrap();
+        assert_eq!(pack.manifest.minecraft.version, "1.20.1");
+        assert!(matches!(
+            pack.manifest.mod_loader().unwrap(),
+            ModLoader::Fabric { version } if version == "0.14.22"
+        ));
+        assert!(!pack.manifest.files[1].required);
+
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(pack.extract_overrides(dir.path()).unwrap(), 1);
+        assert!(dir.path().join("config/jei.toml").is_file());
+    }
+
+    #[test]
+    fn test_unsupported_loader() {
+        let manifest = MANIFEST.replace("fabric-0.14.22", "forge-47.1.0");
+        let err = pack(&manifest).unwrap().manifest.mod_loader().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Team Pack needs forge 47.1.0, which startmc can't launch"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve() {
+        let date = "2023-01-01T00:00:00Z";
+        let mut unknown = project("520110", "complementary", IndexEntryKind::Mod);
+        unknown.kind = None;
+        let mut jei = version("238222", "15.3.0.4", date, &[]);
+        jei.id = "4644453".to_string();
+        let mut appleskin = version("394468", "2.5.1", date, &[]);
+        appleskin.id = "4658223".to_string();
+        let mut complementary = version("520110", "r5.1", date, &[]);
+        complementary.id = "4708211".to_string();
+        let mut provider = FakeProvider::new(
+            vec![
+                project("238222", "jei", IndexEntryKind::Mod),
+                project("394468", "appleskin", IndexEntryKind::Mod),
+                unknown,
+            ],
+            vec![jei, appleskin, complementary],
+        );
+        provider.kind = ProviderKind::CurseForge;
+
+        let manifest = pack(MANIFEST).unwrap().manifest;
+        let packages = resolve(&provider, &manifest.files).await.unwrap();
+        let names = packages.iter().map(|it| it.to_string()).collect::<Vec<_>>();
+        // projects startmc doesn't know the kind of are skipped
+        assert_eq!(names, vec!["jei-15.3.0.4", "appleskin-2.5.1"]);
+        assert!(
+            packages
+                .iter()
+                .all(|it| it.provider == ProviderKind::CurseForge)
+        );
+
+        let manifest = pack(&MANIFEST.replace("4644453", "1")).unwrap().manifest;
+        assert!(resolve(&provider, &manifest.files).await.is_err());
+    }
+}
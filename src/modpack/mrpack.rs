@@ -0,0 +1,590 @@
+//! Modrinth modpacks (`.mrpack`), see <https://support.modrinth.com/en/articles/8802351-modrinth-modpack-format-mrpack>
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{
+    Result,
+    eyre::{Context, ContextCompat, bail},
+};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use startmc_downloader::{Download, DownloaderBuilder, Status};
+use zip::{ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    config::{MinecraftConfig, ModLoader},
+    sync::{FileHashes, IndexEntryKind, ProviderKind, SyncIndex, SyncIndexEntry, VersionTuple},
+    util::walk_files,
+};
+
+pub const INDEX_FILE: &str = "modrinth.index.json";
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MrpackIndex {
+    pub format_version: u32,
+    pub game: String,
+    pub version_id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    pub files: Vec<MrpackFile>,
+    /// `minecraft` and the mod loader, like `fabric-loader`, mapped to their versions
+    pub dependencies: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MrpackFile {
+    /// Where the file goes, relative to the instance directory
+    pub path: String,
+    pub hashes: MrpackHashes,
+    /// Files without `env` are needed on both sides
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<MrpackEnv>,
+    pub downloads: Vec<String>,
+    pub file_size: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MrpackHashes {
+    pub sha1: String,
+    pub sha512: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MrpackEnv {
+    pub client: EnvSupport,
+    pub server: EnvSupport,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvSupport {
+    Required,
+    Optional,
+    Unsupported,
+}
+
+impl MrpackIndex {
+    pub fn game_version(&self) -> Result<&str> {
+        self.dependencies
+            .get("minecraft")
+            .map(|it| it.as_str())
+            .with_context(|| format!("{} doesn't say which Minecraft version it needs", self.name))
+    }
+
+    /// The mod loader the pack needs. Fails for loaders startmc can't launch.
+    pub fn mod_loader(&self) -> Result<ModLoader> {
+        let mut loader = ModLoader::Vanilla;
+        for (dependency, version) in &self.dependencies {
+            match dependency.as_str() {
+                "minecraft" => {}
+                "fabric-loader" => {
+                    loader = ModLoader::Fabric {
+                        version: version.clone(),
+                    }
+                }
+                "quilt-loader" => {
+                    loader = ModLoader::Quilt {
+                        version: version.clone(),
+                    }
+                }
+                other => bail!(
+                    "{} needs {other} {version}, which startmc can't launch",
+                    self.name
+                ),
+            }
+        }
+        Ok(loader)
+    }
+
+    /// Files that are needed or optional on the client.
+    pub fn client_files(&self) -> impl Iterator<Item = &MrpackFile> {
+        self.files.iter().filter(|it| {
+            it.env
+                .as_ref()
+                .is_none_or(|env| env.client != EnvSupport::Unsupported)
+        })
+    }
+}
+
+impl MrpackFile {
+    pub fn is_optional(&self) -> bool {
+        self.env
+            .as_ref()
+            .is_some_and(|env| env.client == EnvSupport::Optional)
+    }
+
+    pub fn matches(&self, hashes: &FileHashes) -> bool {
+        self.hashes.sha1 == hashes.sha1
+            && self.hashes.sha512 == hashes.sha512
+            && self.file_size == hashes.size
+    }
+
+    /// A file for a package from Modrinth. Fails if the index doesn't know everything about the
+    /// file that a modpack needs.
+    pub fn from_entry(entry: &SyncIndexEntry) -> Result<Self> {
+        let missing = || {
+            format!(
+                "{} has no hashes or URL in the index, refresh it with -Sy",
+                entry.id
+            )
+        };
+        Ok(Self {
+            path: format!("{}/{}", entry.namespace_dir(), entry.file),
+            hashes: MrpackHashes {
+                sha1: entry.sha1.clone().with_context(missing)?,
+                sha512: entry.sha512.clone().with_context(missing)?,
+            },
+            env: None,
+            downloads: vec![entry.url.clone().with_context(missing)?],
+            file_size: entry.size.with_context(missing)?,
+        })
+    }
+
+    /// An index entry for the file, if it's content that sync manages.
+    pub fn to_entry(&self) -> Option<SyncIndexEntry> {
+        let (dir, file) = self.path.split_once('/')?;
+        let kind = *IndexEntryKind::ALL.iter().find(|it| it.dir() == dir)?;
+        if file.contains('/') {
+            return None;
+        }
+        let tuple = VersionTuple::parse(file)?;
+        Some(SyncIndexEntry {
+            id: tuple.name,
+            version: tuple.version,
+            provider: None,
+            project_id: None,
+            version_id: None,
+            file: file.to_string(),
+            disabled: false,
+            kind,
+            dir: None,
+            held: false,
+            sha1: Some(self.hashes.sha1.clone()),
+            sha512: Some(self.hashes.sha512.clone()),
+            size: Some(self.file_size),
+            url: self.downloads.first().cloned(),
+            metadata: None,
+        })
+    }
+}
+
+pub struct Mrpack<R> {
+    pub index: MrpackIndex,
+    archive: ZipArchive<R>,
+}
+
+impl<R: Read + Seek> Mrpack<R> {
+    pub fn from_reader(reader: R) -> Result<Self> {
+        Self::from_archive(ZipArchive::new(reader)?)
+    }
+
+    pub fn from_archive(mut archive: ZipArchive<R>) -> Result<Self> {
+        let index: MrpackIndex = serde_json::from_reader(
+            archive
+                .by_name(INDEX_FILE)
+                .with_context(|| format!("no {INDEX_FILE} in modpack"))?,
+        )
+        .with_context(|| format!("parse {INDEX_FILE}"))?;
+        if index.format_version != 1 {
+            bail!(
+                "unsupported modpack format version {}",
+                index.format_version
+            );
+        }
+        if index.game != "minecraft" {
+            bail!(
+                "{} is a modpack for {}, not Minecraft",
+                index.name,
+                index.game
+            );
+        }
+        Ok(Self { index, archive })
+    }
+
+    /// Extract `overrides/`, and then `client-overrides/` over them, into `minecraft_directory`.
+    pub fn extract_overrides(&mut self, minecraft_directory: &Path) -> Result<usize> {
+        Ok(
+            super::extract_dir(&mut self.archive, "overrides", minecraft_directory)?
+                + super::extract_dir(&mut self.archive, "client-overrides", minecraft_directory)?,
+        )
+    }
+}
+
+/// Download `files` into `minecraft_directory`, failing if one doesn't match its declared hashes.
+pub async fn download_files(files: &[MrpackFile], minecraft_directory: &Path) -> Result<()> {
+    let mut queue = vec![];
+    for file in files {
+        let path = super::safe_join(minecraft_directory, &file.path)?;
+        let url = file
+            .downloads
+            .first()
+            .with_context(|| format!("{} has no downloads", file.path))?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // the downloader would try to resume a file that's already there
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        queue.push(Download::new(
+            &Url::parse(url).with_context(|| format!("invalid URL in modpack: {url}"))?,
+            path.to_str().unwrap(),
+            Some(file.path.clone()),
+        ));
+    }
+
+    let downloader = DownloaderBuilder::new().concurrent_downloads(10).build();
+    for summary in downloader.download(&queue).await {
+        if let Status::Fail(err) = summary.status() {
+            bail!("failed to download {}: {err}", summary.download().url);
+        }
+    }
+
+    for file in files {
+        let path = super::safe_join(minecraft_directory, &file.path)?;
+        if !file.matches(&FileHashes::read(&path)?) {
+            std::fs::remove_file(&path)?;
+            bail!(
+                "hash mismatch for {}, downloaded from {}",
+                file.path,
+                file.downloads[0]
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// A modpack built from an instance, ready to be written.
+pub struct Export {
+    pub index: MrpackIndex,
+    /// Files that go into `overrides/`, relative to the instance directory
+    pub overrides: Vec<PathBuf>,
+}
+
+impl Export {
+    /// Build a modpack out of the instance's content. Packages from Modrinth are downloaded by
+    /// whoever installs the pack, everything else goes into the overrides along with the
+    /// `include`d directories, like `config`. Disabled packages are left out.
+    pub fn new(
+        name: &str,
+        version_id: &str,
+        minecraft: &MinecraftConfig,
+        loader: &ModLoader,
+        sync_index: &SyncIndex,
+        include: &[String],
+    ) -> Result<Self> {
+        let mut dependencies =
+            BTreeMap::from([("minecraft".to_string(), minecraft.version.clone())]);
+        match loader {
+            ModLoader::Vanilla => {}
+            ModLoader::Fabric { version } => {
+                dependencies.insert("fabric-loader".to_string(), version.clone());
+            }
+            ModLoader::Quilt { version } => {
+                dependencies.insert("quilt-loader".to_string(), version.clone());
+            }
+        }
+
+        let mut files = vec![];
+        let mut overrides = vec![];
+        for entry in sync_index.packages.iter().filter(|it| !it.disabled) {
+            if entry.provider == Some(ProviderKind::Modrinth) {
+                files.push(MrpackFile::from_entry(entry)?);
+            } else {
+                overrides.push(Path::new(entry.namespace_dir()).join(&entry.file));
+            }
+        }
+
+        let minecraft_directory = Path::new(&minecraft.directory);
+        for dir in include {
+            let path = super::safe_join(minecraft_directory, dir)?;
+            if !path.is_dir() {
+                warn!("{dir} is not a directory in the instance, skipping...");
+                continue;
+            }
+            for file in walk_files(&path)? {
+                overrides.push(file.strip_prefix(minecraft_directory)?.to_path_buf());
+            }
+        }
+        overrides.sort();
+        overrides.dedup();
+
+        Ok(Self {
+            index: MrpackIndex {
+                format_version: 1,
+                game: "minecraft".to_string(),
+                version_id: version_id.to_string(),
+                name: name.to_string(),
+                summary: None,
+                files,
+                dependencies,
+            },
+            overrides,
+        })
+    }
+
+    /// Write the modpack as a `.mrpack` zip, reading the overrides from `minecraft_directory`.
+    pub fn write<W: Write + Seek>(&self, writer: W, minecraft_directory: &Path) -> Result<()> {
+        let mut zip = ZipWriter::new(writer);
+        let options = SimpleFileOptions::default();
+        zip.start_file(INDEX_FILE, options)?;
+        serde_json::to_writer_pretty(&mut zip, &self.index)?;
+        for path in &self.overrides {
+            let name = path
+                .components()
+                .map(|it| it.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            zip.start_file(format!("overrides/{name}"), options)?;
+            let full_path = minecraft_directory.join(path);
+            let mut file = File::open(&full_path)
+                .with_context(|| format!("open {path}", path = full_path.display()))?;
+            std::io::copy(&mut file, &mut zip)?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use super::*;
+    use crate::sync::{
+        fake::FakeProvider,
+        fixtures::{self, entry},
+    };
+
+    const INDEX: &str = r#"{
+        "formatVersion": 1,
+        "game": "minecraft",
+        "versionId": "1.0.0",
+        "name": "Team Pack",
+        "files": [
+            {
+                "path": "mods/sodium-fabric-0.5.3+mc1.20.1.jar",
+                "hashes": { "sha1": "aaaa", "sha512": "bbbb" },
+                "env": { "client": "required", "server": "unsupported" },
+                "downloads": ["https://cdn.modrinth.com/data/AANobbMI/versions/OihdIimA/sodium-fabric-0.5.3+mc1.20.1.jar"],
+                "fileSize": 1024
+            },
+            {
+                "path": "mods/lithium-fabric-mc1.20.1-0.11.2.jar",
+                "hashes": { "sha1": "cccc", "sha512": "dddd" },
+                "downloads": ["https://cdn.modrinth.com/data/gvQqBUqZ/versions/ZSNsJrPI/lithium-fabric-mc1.20.1-0.11.2.jar"],
+                "fileSize": 2048
+            },
+            {
+                "path": "mods/chunky-1.3.92.jar",
+                "hashes": { "sha1": "eeee", "sha512": "ffff" },
+                "env": { "client": "unsupported", "server": "required" },
+                "downloads": ["https://cdn.modrinth.com/data/fALzjamp/versions/chunky-1.3.92.jar"],
+                "fileSize": 512
+            },
+            {
+                "path": "shaderpacks/complementary.zip",
+                "hashes": { "sha1": "1111", "sha512": "2222" },
+                "env": { "client": "optional", "server": "unsupported" },
+                "downloads": ["https://cdn.modrinth.com/data/HVnmMxH1/versions/complementary.zip"],
+                "fileSize": 4096
+            }
+        ],
+        "dependencies": { "minecraft": "1.20.1", "fabric-loader": "0.14.22" }
+    }"#;
+
+    fn pack(index: &str) -> Result<Mrpack<Cursor<Vec<u8>>>> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file(INDEX_FILE, SimpleFileOptions::default())?;
+        zip.write_all(index.as_bytes())?;
+        Mrpack::from_reader(zip.finish()?)
+    }
+
+    #[test]
+    fn test_index() {
+        let pack = pack(INDEX).unwrap();
+        assert_eq!(pack.index.game_version().unwrap(), "1.20.1");
+        assert!(matches!(
+            pack.index.mod_loader().unwrap(),
+            ModLoader::Fabric { version } if version == "0.14.22"
+        ));
+
+        let files = pack.index.client_files().collect::<Vec<_>>();
+        let paths = files.iter().map(|it| it.path.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "mods/sodium-fabric-0.5.3+mc1.20.1.jar",
+                "mods/lithium-fabric-mc1.20.1-0.11.2.jar",
+                "shaderpacks/complementary.zip",
+            ]
+        );
+        assert!(!files[0].is_optional());
+        assert!(!files[1].is_optional());
+        assert!(files[2].is_optional());
+    }
+
+    #[test]
+    fn test_loaders() {
+        let index = INDEX.replace("fabric-loader", "quilt-loader");
+        assert!(matches!(
+            pack(&index).unwrap().index.mod_loader().unwrap(),
+            ModLoader::Quilt { version } if version == "0.14.22"
+        ));
+
+        let index = INDEX.replace("fabric-loader", "forge");
+        let err = pack(&index).unwrap().index.mod_loader().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Team Pack needs forge 0.14.22, which startmc can't launch"
+        );
+    }
+
+    #[test]
+    fn test_to_entry() {
+        let pack = pack(INDEX).unwrap();
+        let entry = pack.index.files[0].to_entry().unwrap();
+        assert_eq!(entry.file, "sodium-fabric-0.5.3+mc1.20.1.jar");
+        assert_eq!(entry.kind, IndexEntryKind::Mod);
+        assert_eq!(entry.sha1.as_deref(), Some("aaaa"));
+        assert_eq!(entry.size, Some(1024));
+        assert!(entry.url.unwrap().starts_with("https://cdn.modrinth.com/"));
+
+        let shader = pack.index.files[3].to_entry().unwrap();
+        assert_eq!(shader.kind, IndexEntryKind::Shader);
+        assert_eq!(shader.namespace_dir(), "shaderpacks");
+    }
+
+    #[tokio::test]
+    async fn test_import_identifies_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("mods")).unwrap();
+        let hashes = FileHashes::from_bytes(b"sodium");
+        std::fs::write(
+            dir.path().join("mods/sodium-fabric-0.5.3+mc1.20.1.jar"),
+            b"sodium",
+        )
+        .unwrap();
+        let pack = pack(&INDEX.replace("aaaa", &hashes.sha1)).unwrap();
+
+        let mut sodium = fixtures::version("AANobbMI", "0.5.3", "2023-09-01T00:00:00Z", &[]);
+        sodium.files[0].sha1 = Some(hashes.sha1.clone());
+        let provider = FakeProvider::new(
+            vec![fixtures::project("AANobbMI", "sodium", IndexEntryKind::Mod)],
+            vec![sodium],
+        );
+        let mut sync = fixtures::sync(SyncIndex::default(), provider, dir.path().to_path_buf());
+        // what installing the pack does
+        sync.index
+            .packages
+            .extend(pack.index.files[..1].iter().filter_map(|it| it.to_entry()));
+        sync.refresh().await.unwrap();
+
+        assert_eq!(sync.index.packages.len(), 1);
+        let package = &sync.index.packages[0];
+        assert_eq!(package.provider, Some(ProviderKind::Modrinth));
+        assert_eq!(package.project_id.as_deref(), Some("AANobbMI"));
+        assert_eq!(package.version_id.as_deref(), Some("AANobbMI-0.5.3"));
+    }
+
+    #[test]
+    fn test_export() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("mods")).unwrap();
+        std::fs::create_dir_all(dir.path().join("config/sodium")).unwrap();
+        std::fs::write(dir.path().join("mods/custom.jar"), "custom").unwrap();
+        std::fs::write(dir.path().join("config/sodium/options.json"), "{}").unwrap();
+
+        let mut sodium = entry("sodium", "0.5.3", "sodium.jar", true);
+        sodium.set_hashes(&FileHashes::from_bytes(b"sodium"));
+        sodium.url = Some("https://cdn.modrinth.com/data/AANobbMI/sodium.jar".to_string());
+        let index = SyncIndex {
+            packages: vec![
+                sodium,
+                entry("custom", "1.0", "custom.jar", false),
+                entry("iris", "1.6.4", "iris.jar.disabled", true),
+            ],
+            ..Default::default()
+        };
+        let minecraft = MinecraftConfig {
+            version: "1.20.1".to_string(),
+            directory: dir.path().to_str().unwrap().to_string(),
+        };
+        let loader = ModLoader::Fabric {
+            version: "0.14.22".to_string(),
+        };
+
+        let export = Export::new(
+            "Team Pack",
+            "1.0.0",
+            &minecraft,
+            &loader,
+            &index,
+            &["config".to_string(), "shaderpacks".to_string()],
+        )
+        .unwrap();
+        assert_eq!(
+            export.overrides,
+            vec![
+                PathBuf::from("config/sodium/options.json"),
+                PathBuf::from("mods/custom.jar"),
+            ]
+        );
+
+        let mut buf = Cursor::new(vec![]);
+        export.write(&mut buf, dir.path()).unwrap();
+        buf.set_position(0);
+        let mut pack = Mrpack::from_reader(buf).unwrap();
+        assert_eq!(pack.index.name, "Team Pack");
+        assert_eq!(pack.index.game_version().unwrap(), "1.20.1");
+        assert!(matches!(
+            pack.index.mod_loader().unwrap(),
+            ModLoader::Fabric { .. }
+        ));
+        assert_eq!(pack.index.files.len(), 1);
+        assert_eq!(pack.index.files[0].path, "mods/sodium.jar");
+        assert_eq!(pack.index.files[0].file_size, 6);
+
+        let out = tempfile::tempdir().unwrap();
+        assert_eq!(pack.extract_overrides(out.path()).unwrap(), 2);
+        assert_eq!(
+            std::fs::read_to_string(out.path().join("mods/custom.jar")).unwrap(),
+            "custom"
+        );
+    }
+
+    #[test]
+    fn test_export_needs_hashes() {
+        let index = SyncIndex {
+            packages: vec![entry("sodium", "0.5.3", "sodium.jar", true)],
+            ..Default::default()
+        };
+        let minecraft = MinecraftConfig {
+            version: "1.20.1".to_string(),
+            directory: "/nonexistent".to_string(),
+        };
+        let err = Export::new(
+            "Team Pack",
+            "1.0.0",
+            &minecraft,
+            &ModLoader::Vanilla,
+            &index,
+            &[],
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "sodium has no hashes or URL in the index, refresh it with -Sy"
+        );
+    }
+}
@@ -0,0 +1,381 @@
+//! Reading and writing Minecraft's NBT format, which files like `level.dat` and `servers.dat`
+//! are in.
+//! See <https://minecraft.wiki/w/NBT_format>.
+
+use std::{
+    collections::BTreeMap,
+    io::{BufReader, Read, Write},
+    path::Path,
+};
+
+use color_eyre::{
+    Result,
+    eyre::{Context, bail},
+};
+use flate2::read::GzDecoder;
+
+pub type Compound = BTreeMap<String, Tag>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+const END: u8 = 0;
+const BYTE: u8 = 1;
+const SHORT: u8 = 2;
+const INT: u8 = 3;
+const LONG: u8 = 4;
+const FLOAT: u8 = 5;
+const DOUBLE: u8 = 6;
+const BYTE_ARRAY: u8 = 7;
+const STRING: u8 = 8;
+const LIST: u8 = 9;
+const COMPOUND: u8 = 10;
+const INT_ARRAY: u8 = 11;
+const LONG_ARRAY: u8 = 12;
+
+/// Lists and compounds deeper than this are refused, like the game does.
+const MAX_DEPTH: usize = 512;
+
+impl Tag {
+    /// The tag named `name`, if this is a compound that has it.
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(compound) => compound.get(name),
+            _ => None,
+        }
+    }
+
+    /// Any integer tag, widened.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Tag::Byte(it) => Some(*it as i64),
+            Tag::Short(it) => Some(*it as i64),
+            Tag::Int(it) => Some(*it as i64),
+            Tag::Long(it) => Some(*it),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(it) => Some(it),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(it) => Some(it),
+            _ => None,
+        }
+    }
+}
+
+/// Read an NBT file, which may be gzipped like `level.dat` or not like `servers.dat`. Returns
+/// the root compound.
+pub fn read_file(path: &Path) -> Result<Compound> {
+    let contents = std::fs::read(path).with_context(|| format!("read {}", path.display()))?;
+    let (_, root) = if contents.starts_with(&[0x1f, 0x8b]) {
+        read(&mut BufReader::new(GzDecoder::new(contents.as_slice())))
+    } else {
+        read(&mut contents.as_slice())
+    }
+    .with_context(|| format!("parse {}", path.display()))?;
+    Ok(root)
+}
+
+/// Read an uncompressed NBT document, returning the name and contents of its root compound.
+pub fn read(reader: &mut impl Read) -> Result<(String, Compound)> {
+    let id = read_u8(reader)?;
+    if id != COMPOUND {
+        bail!("root tag is not a compound, but has id {id}");
+    }
+    let name = read_string(reader)?;
+    let Tag::Compound(root) = read_payload(reader, COMPOUND, 0)? else {
+        unreachable!()
+    };
+    Ok((name, root))
+}
+
+fn read_payload(reader: &mut impl Read, id: u8, depth: usize) -> Result<Tag> {
+    if depth > MAX_DEPTH {
+        bail!("tags are nested too deeply");
+    }
+    Ok(match id {
+        BYTE => Tag::Byte(read_u8(reader)? as i8),
+        SHORT => Tag::Short(i16::from_be_bytes(read_array(reader)?)),
+        INT => Tag::Int(i32::from_be_bytes(read_array(reader)?)),
+        LONG => Tag::Long(i64::from_be_bytes(read_array(reader)?)),
+        FLOAT => Tag::Float(f32::from_be_bytes(read_array(reader)?)),
+        DOUBLE => Tag::Double(f64::from_be_bytes(read_array(reader)?)),
+        BYTE_ARRAY => {
+            let len = read_len(reader)?;
+            let mut bytes = vec![0; len];
+            reader.read_exact(&mut bytes)?;
+            Tag::ByteArray(bytes.into_iter().map(|it| it as i8).collect())
+        }
+        STRING => Tag::String(read_string(reader)?),
+        LIST => {
+            let element = read_u8(reader)?;
+            let len = read_len(reader)?;
+            if element == END && len > 0 {
+                bail!("list of {len} end tags");
+            }
+            let mut list = Vec::with_capacity(len.min(1024));
+            for _ in 0..len {
+                list.push(read_payload(reader, element, depth + 1)?);
+            }
+            Tag::List(list)
+        }
+        COMPOUND => {
+            let mut compound = Compound::new();
+            loop {
+                let id = read_u8(reader)?;
+                if id == END {
+                    break;
+                }
+                let name = read_string(reader)?;
+                compound.insert(name, read_payload(reader, id, depth + 1)?);
+            }
+            Tag::Compound(compound)
+        }
+        INT_ARRAY => {
+            let len = read_len(reader)?;
+            let mut ints = Vec::with_capacity(len.min(1024));
+            for _ in 0..len {
+                ints.push(i32::from_be_bytes(read_array(reader)?));
+            }
+            Tag::IntArray(ints)
+        }
+        LONG_ARRAY => {
+            let len = read_len(reader)?;
+            let mut longs = Vec::with_capacity(len.min(1024));
+            for _ in 0..len {
+                longs.push(i64::from_be_bytes(read_array(reader)?));
+            }
+            Tag::LongArray(longs)
+        }
+        id => bail!("unknown tag id {id}"),
+    })
+}
+
+/// Write `root` to an uncompressed NBT file, like `servers.dat`. The file is replaced at once,
+/// so it's never left half-written.
+pub fn write_file(path: &Path, root: &Compound) -> Result<()> {
+    let mut bytes = vec![];
+    write(&mut bytes, "", root)?;
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, bytes).with_context(|| format!("write {}", temp.display()))?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// Write an uncompressed NBT document with a root compound named `name`.
+pub fn write(writer: &mut impl Write, name: &str, root: &Compound) -> Result<()> {
+    writer.write_all(&[COMPOUND])?;
+    write_string(writer, name)?;
+    write_compound(writer, root)
+}
+
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => BYTE,
+            Tag::Short(_) => SHORT,
+            Tag::Int(_) => INT,
+            Tag::Long(_) => LONG,
+            Tag::Float(_) => FLOAT,
+            Tag::Double(_) => DOUBLE,
+            Tag::ByteArray(_) => BYTE_ARRAY,
+            Tag::String(_) => STRING,
+            Tag::List(_) => LIST,
+            Tag::Compound(_) => COMPOUND,
+            Tag::IntArray(_) => INT_ARRAY,
+            Tag::LongArray(_) => LONG_ARRAY,
+        }
+    }
+}
+
+fn write_compound(writer: &mut impl Write, compound: &Compound) -> Result<()> {
+    for (name, tag) in compound {
+        writer.write_all(&[tag.id()])?;
+        write_string(writer, name)?;
+        write_payload(writer, tag)?;
+    }
+    writer.write_all(&[END])?;
+    Ok(())
+}
+
+fn write_payload(writer: &mut impl Write, tag: &Tag) -> Result<()> {
+    match tag {
+        Tag::Byte(it) => writer.write_all(&it.to_be_bytes())?,
+        Tag::Short(it) => writer.write_all(&it.to_be_bytes())?,
+        Tag::Int(it) => writer.write_all(&it.to_be_bytes())?,
+        Tag::Long(it) => writer.write_all(&it.to_be_bytes())?,
+        Tag::Float(it) => writer.write_all(&it.to_be_bytes())?,
+        Tag::Double(it) => writer.write_all(&it.to_be_bytes())?,
+        Tag::ByteArray(bytes) => {
+            write_len(writer, bytes.len())?;
+            writer.write_all(&bytes.iter().map(|it| *it as u8).collect::<Vec<_>>())?;
+        }
+        Tag::String(it) => write_string(writer, it)?,
+        Tag::List(list) => {
+            let element = list.first().map_or(END, Tag::id);
+            if list.iter().any(|it| it.id() != element) {
+                bail!("list has tags of different types");
+            }
+            writer.write_all(&[element])?;
+            write_len(writer, list.len())?;
+            for tag in list {
+                write_payload(writer, tag)?;
+            }
+        }
+        Tag::Compound(compound) => write_compound(writer, compound)?,
+        Tag::IntArray(ints) => {
+            write_len(writer, ints.len())?;
+            for it in ints {
+                writer.write_all(&it.to_be_bytes())?;
+            }
+        }
+        Tag::LongArray(longs) => {
+            write_len(writer, longs.len())?;
+            for it in longs {
+                writer.write_all(&it.to_be_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_len(writer: &mut impl Write, len: usize) -> Result<()> {
+    let len = i32::try_from(len).context("array is too long for NBT")?;
+    writer.write_all(&len.to_be_bytes())?;
+    Ok(())
+}
+
+fn write_string(writer: &mut impl Write, it: &str) -> Result<()> {
+    let len = u16::try_from(it.len()).context("string is too long for NBT")?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(it.as_bytes())?;
+    Ok(())
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u8(reader: &mut impl Read) -> Result<u8> {
+    Ok(read_array::<1>(reader)?[0])
+}
+
+fn read_len(reader: &mut impl Read) -> Result<usize> {
+    let len = i32::from_be_bytes(read_array(reader)?);
+    if len < 0 {
+        bail!("negative length {len}");
+    }
+    Ok(len as usize)
+}
+
+/// Strings are in Java's modified UTF-8, which only differs from UTF-8 for characters that
+/// don't come up in world and server names, so they are read as UTF-8.
+fn read_string(reader: &mut impl Read) -> Result<String> {
+    let len = u16::from_be_bytes(read_array(reader)?) as usize;
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{Compression, write::GzEncoder};
+
+    use super::*;
+
+    /// `{"": {list: [1L, 2L], name: "Bananrama", nested: {byte: 1b}}}` by hand.
+    fn document() -> Vec<u8> {
+        let mut bytes = vec![COMPOUND, 0, 0];
+        bytes.extend([LIST, 0, 4]);
+        bytes.extend(b"list");
+        bytes.extend([LONG, 0, 0, 0, 2]);
+        bytes.extend(1i64.to_be_bytes());
+        bytes.extend(2i64.to_be_bytes());
+        bytes.extend([STRING, 0, 4]);
+        bytes.extend(b"name");
+        bytes.extend([0, 9]);
+        bytes.extend(b"Bananrama");
+        bytes.extend([COMPOUND, 0, 6]);
+        bytes.extend(b"nested");
+        bytes.extend([BYTE, 0, 4]);
+        bytes.extend(b"byte");
+        bytes.extend([1, END, END]);
+        bytes
+    }
+
+    #[test]
+    fn test_read() {
+        let (name, root) = read(&mut document().as_slice()).unwrap();
+        assert_eq!(name, "");
+        let root = Tag::Compound(root);
+        assert_eq!(root.get("name").and_then(Tag::as_str), Some("Bananrama"));
+        assert_eq!(
+            root.get("list").and_then(Tag::as_list),
+            Some([Tag::Long(1), Tag::Long(2)].as_slice())
+        );
+        assert_eq!(
+            root.get("nested")
+                .and_then(|it| it.get("byte"))
+                .and_then(Tag::as_i64),
+            Some(1)
+        );
+
+        let truncated = document()[..20].to_vec();
+        assert!(read(&mut truncated.as_slice()).is_err());
+        assert!(read(&mut [STRING, 0, 0].as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_write() {
+        let (_, root) = read(&mut document().as_slice()).unwrap();
+        let mut bytes = vec![];
+        write(&mut bytes, "", &root).unwrap();
+        // compounds are written sorted by name, which the document already is
+        assert_eq!(bytes, document());
+
+        let mut mixed = Compound::new();
+        mixed.insert(
+            "list".to_string(),
+            Tag::List(vec![Tag::Byte(1), Tag::Int(2)]),
+        );
+        assert!(write(&mut vec![], "", &mixed).is_err());
+    }
+
+    #[test]
+    fn test_read_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("servers.dat");
+        std::fs::write(&plain, document()).unwrap();
+        let gzipped = dir.path().join("level.dat");
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&document()).unwrap();
+        std::fs::write(&gzipped, encoder.finish().unwrap()).unwrap();
+
+        assert_eq!(read_file(&plain).unwrap(), read_file(&gzipped).unwrap());
+    }
+}
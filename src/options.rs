@@ -0,0 +1,250 @@
+//! The game settings of an instance, which the game keeps in `options.txt`, and the template
+//! they can be merged from.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{Result, eyre::Context};
+use serde::{Deserialize, Serialize};
+
+/// How the options template and the instance config apply to the instance's `options.txt`.
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct OptionsConfig {
+    /// Merge the template and `set` into `options.txt` on every launch, not only on init
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub merge_on_launch: bool,
+    /// Options of this instance, which take priority over the template
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub set: BTreeMap<String, toml::Value>,
+}
+
+impl OptionsConfig {
+    pub fn is_empty(&self) -> bool {
+        !self.merge_on_launch && self.set.is_empty()
+    }
+
+    /// The options in `set` as the game writes them: strings as they are, anything else as
+    /// JSON, like `resourcePacks`.
+    pub fn values(&self) -> Vec<(String, String)> {
+        self.set
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    toml::Value::String(value) => value.clone(),
+                    value => serde_json::to_string(value).expect("toml value is valid json"),
+                };
+                (key.clone(), value)
+            })
+            .collect()
+    }
+}
+
+/// The lines of an `options.txt`, in order.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Options {
+    pub entries: Vec<(String, String)>,
+}
+
+impl Options {
+    pub fn path(minecraft_directory: &Path) -> PathBuf {
+        minecraft_directory.join("options.txt")
+    }
+
+    /// Where the options template shared by every instance is.
+    pub fn template_path() -> Option<PathBuf> {
+        Some(dirs::config_dir()?.join("startmc").join("options.txt"))
+    }
+
+    /// Read `path`, which is empty if it doesn't exist.
+    pub fn read(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents =
+            std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+        Ok(Self::parse(&contents))
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        Self {
+            entries: contents
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_string()).with_context(|| format!("write {}", path.display()))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(it, _)| it == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Set `key` to `value` where it is, or at the end if it isn't there.
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.entries.iter_mut().find(|(it, _)| it == key) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.entries.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    /// The data version of the game that wrote the options. The game upgrades options from
+    /// older data versions when it reads them.
+    pub fn data_version(&self) -> Option<u32> {
+        self.get("version")?.parse().ok()
+    }
+
+    /// Fill in the options of `template` that these options don't have. Options that are
+    /// already here are kept, so that settings changed in the game win over the template.
+    /// Options written for a newer version of the game can't be read by an older one, so a
+    /// template with a newer data version isn't merged, and `false` is returned. Options taken
+    /// from a template with an older one need to be upgraded by the game, so the data version
+    /// is lowered to the template's when any are taken.
+    pub fn merge(&mut self, template: &Options) -> bool {
+        let ours = self.data_version();
+        let theirs = template.data_version();
+        if let (Some(ours), Some(theirs)) = (ours, theirs)
+            && theirs > ours
+        {
+            return false;
+        }
+        let mut added = false;
+        for (key, value) in &template.entries {
+            if self.get(key).is_none() {
+                self.entries.push((key.clone(), value.clone()));
+                added |= key != "version";
+            }
+        }
+        if let (Some(ours), Some(theirs)) = (ours, theirs)
+            && added
+            && theirs < ours
+        {
+            self.set("version", &theirs.to_string());
+        }
+        true
+    }
+
+    /// Enable `new` in place of `old` if `old` is an enabled resource pack, for packs that were
+    /// upgraded to a new file.
+    pub fn replace_resource_pack(&mut self, old: &str, new: &str) {
+        let Some(mut packs) = self
+            .get("resourcePacks")
+            .and_then(|it| serde_json::from_str::<Vec<String>>(it).ok())
+        else {
+            return;
+        };
+        let old = format!("file/{old}");
+        let Some(pack) = packs.iter_mut().find(|it| **it == old) else {
+            return;
+        };
+        *pack = format!("file/{new}");
+        self.set(
+            "resourcePacks",
+            &serde_json::to_string(&packs).expect("strings are valid json"),
+        );
+    }
+
+    /// Add the resource packs with the file names `files` in `resourcepacks` to the enabled
+    /// resource packs, after the ones that already are, so that they take priority.
+    pub fn enable_resource_packs(&mut self, files: &[String]) {
+        let mut packs = self
+            .get("resourcePacks")
+            .and_then(|it| serde_json::from_str::<Vec<String>>(it).ok())
+            .unwrap_or_else(|| vec!["vanilla".to_string()]);
+        let added = files
+            .iter()
+            .map(|file| format!("file/{file}"))
+            .filter(|pack| !packs.contains(pack))
+            .collect::<Vec<_>>();
+        if added.is_empty() {
+            return;
+        }
+        packs.extend(added);
+        self.set(
+            "resourcePacks",
+            &serde_json::to_string(&packs).expect("strings are valid json"),
+        );
+    }
+}
+
+impl std::fmt::Display for Options {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (key, value) in &self.entries {
+            writeln!(f, "{key}:{value}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let mut options = Options::parse(
+            "version:3955\nfov:0.0\nkey_key.jump:key.keyboard.space\nsomeModOption:true\n",
+        );
+        let template =
+            Options::parse("version:3700\nfov:0.25\nkey_key.jump:key.keyboard.j\nlang:de_de\n");
+        assert!(options.merge(&template));
+        // only the missing option is taken, and the game upgrades it
+        assert_eq!(
+            options.to_string(),
+            "version:3700\nfov:0.0\nkey_key.jump:key.keyboard.space\nsomeModOption:true\nlang:de_de\n"
+        );
+
+        // nothing to take, so the data version stays, like after the game upgraded the options
+        options.set("version", "3955");
+        assert!(options.merge(&template));
+        assert_eq!(options.data_version(), Some(3955));
+
+        let newer = Options::parse("version:4000\nfov:1.0\nnarrator:0\n");
+        assert!(!options.merge(&newer));
+        assert_eq!(options.get("narrator"), None);
+
+        let mut new = Options::default();
+        assert!(new.merge(&newer));
+        assert_eq!(new, newer);
+    }
+
+    #[test]
+    fn test_resource_packs() {
+        let mut options = Options::default();
+        options.enable_resource_packs(&["Faithful.zip".to_string()]);
+        assert_eq!(
+            options.get("resourcePacks"),
+            Some(r#"["vanilla","file/Faithful.zip"]"#)
+        );
+        options.set("resourcePacks", r#"["file/Other.zip","vanilla"]"#);
+        options.enable_resource_packs(&["Faithful.zip".to_string(), "Other.zip".to_string()]);
+        assert_eq!(
+            options.get("resourcePacks"),
+            Some(r#"["file/Other.zip","vanilla","file/Faithful.zip"]"#)
+        );
+        options.replace_resource_pack("Other.zip", "Other-2.zip");
+        options.replace_resource_pack("Disabled.zip", "Disabled-2.zip");
+        assert_eq!(
+            options.get("resourcePacks"),
+            Some(r#"["file/Other-2.zip","vanilla","file/Faithful.zip"]"#)
+        );
+
+        let config: OptionsConfig =
+            toml::from_str("set = { fov = 0.5, lang = \"en_us\" }").unwrap();
+        assert_eq!(
+            config.values(),
+            vec![
+                ("fov".to_string(), "0.5".to_string()),
+                ("lang".to_string(), "en_us".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! The multiplayer server list of an instance, which the game keeps in `servers.dat`.
+
+use std::path::{Path, PathBuf};
+
+use color_eyre::{Result, eyre::bail};
+use serde::{Deserialize, Serialize};
+
+use crate::nbt::{self, Compound, Tag};
+
+/// A server that startmc adds to the server list of an instance on launch, from the instance
+/// config.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ServerConfig {
+    pub name: String,
+    /// Address of the server, with the port if it isn't the default one
+    pub ip: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Server {
+    pub name: String,
+    pub ip: String,
+    /// Everything else the game stores about the server, like its icon, kept as it is
+    pub extra: Compound,
+}
+
+#[derive(Debug, Default)]
+pub struct ServerList {
+    pub servers: Vec<Server>,
+}
+
+impl ServerList {
+    pub fn path(minecraft_directory: &Path) -> PathBuf {
+        minecraft_directory.join("servers.dat")
+    }
+
+    /// Read the server list of the instance in `minecraft_directory`, which is empty if the game
+    /// didn't write one yet.
+    pub fn read(minecraft_directory: &Path) -> Result<Self> {
+        let path = Self::path(minecraft_directory);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let root = nbt::read_file(&path)?;
+        let mut servers = vec![];
+        for tag in root.get("servers").and_then(Tag::as_list).unwrap_or(&[]) {
+            let Tag::Compound(compound) = tag else {
+                continue;
+            };
+            let mut extra = compound.clone();
+            let (Some(Tag::String(name)), Some(Tag::String(ip))) =
+                (extra.remove("name"), extra.remove("ip"))
+            else {
+                warn!("skipping server without a name or address in servers.dat");
+                continue;
+            };
+            servers.push(Server { name, ip, extra });
+        }
+        Ok(Self { servers })
+    }
+
+    pub fn write(&self, minecraft_directory: &Path) -> Result<()> {
+        let servers = self
+            .servers
+            .iter()
+            .map(|server| {
+                let mut compound = server.extra.clone();
+                compound.insert("name".to_string(), Tag::String(server.name.clone()));
+                compound.insert("ip".to_string(), Tag::String(server.ip.clone()));
+                Tag::Compound(compound)
+            })
+            .collect();
+        let mut root = Compound::new();
+        root.insert("servers".to_string(), Tag::List(servers));
+        nbt::write_file(&Self::path(minecraft_directory), &root)
+    }
+
+    /// Find a server by name, or by address if no server has that name.
+    pub fn find(&self, server: &str) -> Option<usize> {
+        self.servers
+            .iter()
+            .position(|it| it.name == server)
+            .or_else(|| {
+                self.servers
+                    .iter()
+                    .position(|it| it.ip.eq_ignore_ascii_case(server))
+            })
+    }
+
+    /// Add a server at the end of the list. Fails if there already is one with that address.
+    pub fn add(&mut self, name: &str, ip: &str) -> Result<()> {
+        if let Some(existing) = self
+            .servers
+            .iter()
+            .find(|it| it.ip.eq_ignore_ascii_case(ip))
+        {
+            bail!("{ip} is already in the server list as {}", existing.name);
+        }
+        self.servers.push(Server {
+            name: name.to_string(),
+            ip: ip.to_string(),
+            extra: Compound::new(),
+        });
+        Ok(())
+    }
+
+    /// Move the server at `from` to `to`, both counted from 0. Positions past the end move it
+    /// to the end.
+    pub fn move_to(&mut self, from: usize, to: usize) {
+        let server = self.servers.remove(from);
+        self.servers.insert(to.min(self.servers.len()), server);
+    }
+
+    /// Add the servers in `servers` that aren't in the list, by address. Servers that are there
+    /// already are left alone, so that players can rename and reorder them. Returns the names
+    /// of the added servers.
+    pub fn ensure(&mut self, servers: &[ServerConfig]) -> Vec<String> {
+        let mut added = vec![];
+        for server in servers {
+            if self.add(&server.name, &server.ip).is_ok() {
+                added.push(server.name.clone());
+            }
+        }
+        added
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut list = ServerList::read(dir.path()).unwrap();
+        assert!(list.servers.is_empty());
+
+        list.add("Hypixel", "mc.hypixel.net").unwrap();
+        list.add("Team", "mc.example.com:25566").unwrap();
+        assert!(list.add("Hypixel again", "MC.HYPIXEL.NET").is_err());
+        list.servers[0]
+            .extra
+            .insert("acceptTextures".to_string(), Tag::Byte(1));
+        list.write(dir.path()).unwrap();
+
+        let mut list = ServerList::read(dir.path()).unwrap();
+        assert_eq!(list.servers.len(), 2);
+        assert_eq!(
+            list.servers[0].extra.get("acceptTextures"),
+            Some(&Tag::Byte(1))
+        );
+        assert_eq!(list.find("Team"), Some(1));
+        assert_eq!(list.find("mc.hypixel.net"), Some(0));
+        assert_eq!(list.find("nothing"), None);
+
+        list.move_to(1, 0);
+        assert_eq!(list.servers[0].name, "Team");
+        list.move_to(0, 10);
+        assert_eq!(list.servers[1].name, "Team");
+
+        let added = list.ensure(&[
+            ServerConfig {
+                name: "Team (renamed in config)".to_string(),
+                ip: "mc.example.com:25566".to_string(),
+            },
+            ServerConfig {
+                name: "Creative".to_string(),
+                ip: "creative.example.com".to_string(),
+            },
+        ]);
+        assert_eq!(added, vec!["Creative"]);
+        assert_eq!(list.servers[1].name, "Team");
+        assert_eq!(list.servers[2].name, "Creative");
+    }
+}
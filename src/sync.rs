@@ -1,78 +1,301 @@
 use std::{
     collections::HashMap,
-    fs::DirEntry,
     path::{Path, PathBuf},
 };
 
 use color_eyre::{
     Result,
-    eyre::{Context, ContextCompat},
+    eyre::{Context, ContextCompat, bail},
 };
-use ferinth::structures::project::ProjectType;
 use indicatif::{ParallelProgressIterator, ProgressBar, ProgressFinish, ProgressStyle};
 use rayon::prelude::*;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use sha1_smol::Sha1;
 
+mod check;
+pub use check::*;
+pub mod curseforge;
+pub use curseforge::CurseForge;
+#[cfg(test)]
+pub(crate) mod fake;
 mod filter;
 pub use filter::*;
+pub(crate) mod hash_cache;
+pub use hash_cache::HashCache;
+mod install;
+pub use install::*;
+mod lock;
+pub use lock::*;
+mod metadata;
+pub use metadata::*;
+mod modrinth;
+pub use modrinth::Modrinth;
+mod plan;
+pub use plan::*;
+mod provider;
+pub use provider::*;
+mod upgrade;
+pub use upgrade::*;
+#[cfg(test)]
+pub(crate) mod fixtures;
+pub mod transaction;
+pub use transaction::Transaction;
 mod version;
 use startmc_downloader::ProgressBarOpts;
 pub use version::VersionTuple;
-use version_compare::Cmp;
 
-use crate::util::arrow_error;
+use crate::{config::UnresolvedConfig, migrate, util::arrow_error};
 
 #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IndexEntryKind {
     Mod,
     Resourcepack,
+    Shader,
+    Datapack,
+}
+
+/// Where datapacks go if the instance config doesn't say otherwise and no world is given.
+pub const DEFAULT_DATAPACKS_DIR: &str = "datapacks";
+
+impl IndexEntryKind {
+    pub const ALL: [IndexEntryKind; 4] = [
+        IndexEntryKind::Mod,
+        IndexEntryKind::Resourcepack,
+        IndexEntryKind::Shader,
+        IndexEntryKind::Datapack,
+    ];
+
+    /// The directory content of this kind goes into, relative to the instance directory.
+    /// Datapacks can also be in a world, see [`SyncIndexEntry::dir`].
+    pub fn dir(&self) -> &'static str {
+        match self {
+            IndexEntryKind::Mod => "mods",
+            IndexEntryKind::Resourcepack => "resourcepacks",
+            IndexEntryKind::Shader => "shaderpacks",
+            IndexEntryKind::Datapack => DEFAULT_DATAPACKS_DIR,
+        }
+    }
+
+    /// Whether a file named `filename` can be content of this kind.
+    pub fn accepts(&self, filename: &str) -> bool {
+        let filename = filename.trim_end_matches(".disabled");
+        match self {
+            IndexEntryKind::Mod => filename.ends_with(".jar"),
+            _ => filename.ends_with(".zip"),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct SyncIndexEntry {
     pub id: String,
     pub version: String,
-    pub modrinth_project: Option<String>,
-    pub modrinth_version_id: Option<String>,
+    /// Where the package was installed from, `None` for foreign packages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<ProviderKind>,
+    pub project_id: Option<String>,
+    pub version_id: Option<String>,
     pub file: String,
     pub disabled: bool,
     pub kind: IndexEntryKind,
+    /// Directory of the file relative to the instance directory, if it isn't the default one for
+    /// its kind, like datapacks installed into a world
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    /// Held packages are not upgraded by `-Syu`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub held: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha512: Option<String>,
+    /// Size of the file in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Where the file can be downloaded from, used by `-S --locked`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// What the file says about itself, like its mod id and dependencies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ContentMetadata>,
 }
 
 pub struct Sync {
-    pub fer: ferinth::Ferinth<()>,
+    /// Providers are asked in order when identifying files, so Modrinth comes first
+    pub providers: Vec<Box<dyn ContentProvider>>,
     pub index: SyncIndex,
     pub minecraft_directory: PathBuf,
+    /// The Minecraft version of the instance, used to pick compatible content versions
+    pub game_version: String,
+    /// The mod loader of the instance, as a Modrinth loader name (e.g. `fabric`)
+    pub loader: Option<String>,
+    /// Packages from the instance config that must not be upgraded
+    pub ignore: Vec<SyncFilter>,
+    /// Where new datapacks go relative to the instance directory, either the `datapacks` folder
+    /// of a world or the folder of a global datapack loader
+    pub datapacks: String,
+    /// Install projects as this kind instead of the one their provider says, for projects that
+    /// are more than one kind of content
+    pub kind: Option<IndexEntryKind>,
+    /// Where hashes of content files are cached between refreshes, `None` to hash every file
+    /// every time
+    pub hash_cache: Option<PathBuf>,
+    /// File operations of the running command, see [`Sync::begin`]
+    pub transaction: Transaction,
+}
+
+/// A name for the instance in `minecraft_directory` that is unique across instances, for
+/// keeping its data outside of the instance directory.
+pub(crate) fn instance_key(minecraft_directory: &Path) -> String {
+    let directory = std::fs::canonicalize(minecraft_directory)
+        .unwrap_or_else(|_| minecraft_directory.to_path_buf());
+    Sha1::from(directory.to_string_lossy().as_bytes()).hexdigest()
 }
 
 /// High level operations on sync, like refreshing the index
 impl Sync {
-    pub fn new(normal_config_path: &Path, minecraft_directory: &Path) -> Result<Self> {
+    pub fn new(normal_config_path: &Path, config: &UnresolvedConfig) -> Result<Self> {
+        let minecraft = &config.minecraft;
+        let mut providers: Vec<Box<dyn ContentProvider>> = vec![Box::new(Modrinth::new())];
+        if let Some(curseforge) = &config.sync.curseforge {
+            providers.push(Box::new(CurseForge::new(curseforge)?));
+        }
         Ok(Self {
-            fer: ferinth::Ferinth::<()>::new(
-                env!("CARGO_PKG_REPOSITORY"),
-                Some(env!("CARGO_PKG_VERSION")),
-                Some(env!("CARGO_PKG_HOMEPAGE")),
-            ),
+            providers,
             index: SyncIndex::read(normal_config_path)?,
-            minecraft_directory: minecraft_directory.to_path_buf(),
+            minecraft_directory: PathBuf::from(&minecraft.directory),
+            game_version: minecraft.version.clone(),
+            loader: config.loader.get_loader_type(),
+            ignore: config.sync.ignore_filters()?,
+            datapacks: config
+                .sync
+                .datapacks
+                .clone()
+                .unwrap_or_else(|| DEFAULT_DATAPACKS_DIR.to_string()),
+            kind: None,
+            hash_cache: Some(HashCache::path(Path::new(&minecraft.directory))),
+            transaction: Transaction::untracked(Path::new(&minecraft.directory)),
         })
     }
 
-    pub async fn refresh(&mut self) -> Result<()> {
-        let mods_dir = self.minecraft_directory.join("mods");
-        let resourcepacks_dir = self.minecraft_directory.join("resourcepacks");
-        let entries = tokio::task::spawn_blocking(|| {
-            std::fs::read_dir(mods_dir)?
-                .chain(std::fs::read_dir(resourcepacks_dir)?)
-                .collect::<std::io::Result<Vec<DirEntry>>>()
+    /// Record the file operations of `command` from now on, so that they can be rolled back.
+    /// They are also rolled back if the command fails before [`Sync::commit`]. Does nothing if
+    /// a transaction was already begun.
+    pub fn begin(&mut self, command: &str) -> Result<()> {
+        if self.transaction.is_tracked() {
+            return Ok(());
+        }
+        self.transaction = Transaction::begin(
+            &Transaction::history_dir(&self.minecraft_directory),
+            &self.minecraft_directory,
+            command,
+            &mut self.index,
+        )?;
+        Ok(())
+    }
+
+    /// Write the index and finish the transaction started by [`Sync::begin`].
+    pub fn commit(&mut self, normal_config_path: &Path) -> Result<()> {
+        self.index.write(normal_config_path)?;
+        self.transaction.commit(&self.index)
+    }
+
+    /// Install new datapacks into the world named `world` instead.
+    pub fn set_world(&mut self, world: &str) -> Result<()> {
+        let dir = Path::new("saves").join(world);
+        if !self.minecraft_directory.join(&dir).is_dir() {
+            bail!("there is no world named {world} in the instance");
+        }
+        self.datapacks = dir.join("datapacks").to_string_lossy().into_owned();
+        Ok(())
+    }
+
+    /// Every directory that can have content in it, relative to the instance directory, with
+    /// the kind of content in it. Datapack directories of every world are included.
+    pub fn content_dirs(&self) -> Vec<(String, IndexEntryKind)> {
+        let mut dirs = IndexEntryKind::ALL
+            .iter()
+            .map(|kind| (kind.dir().to_string(), *kind))
+            .collect::<Vec<_>>();
+        dirs.push((self.datapacks.clone(), IndexEntryKind::Datapack));
+        if let Ok(worlds) = std::fs::read_dir(self.minecraft_directory.join("saves")) {
+            for world in worlds.flatten() {
+                let dir = Path::new("saves").join(world.file_name()).join("datapacks");
+                dirs.push((dir.to_string_lossy().into_owned(), IndexEntryKind::Datapack));
+            }
+        }
+        for pkg in &self.index.packages {
+            dirs.push((pkg.namespace_dir().to_string(), pkg.kind));
+        }
+        dirs.sort_by(|a, b| a.0.cmp(&b.0));
+        dirs.dedup_by(|a, b| a.0 == b.0);
+        dirs
+    }
+
+    pub fn provider(&self, kind: ProviderKind) -> Result<&dyn ContentProvider> {
+        self.providers
+            .iter()
+            .find(|it| it.kind() == kind)
+            .map(|it| it.as_ref())
+            .with_context(|| match kind {
+                ProviderKind::CurseForge => {
+                    "no CurseForge API key, set one in [sync.curseforge] in the instance config"
+                        .to_string()
+                }
+                _ => format!("{kind} is not available"),
+            })
+    }
+
+    /// Whether `entry` is held, either in the index or by the instance's ignore list.
+    pub fn is_held(&self, entry: &SyncIndexEntry) -> bool {
+        entry.held || self.ignore.iter().any(|it| entry.matches(it))
+    }
+
+    /// Reconcile the index with the files in the content directories: new files are identified
+    /// and added, entries of files that are gone are removed, and files that were renamed (or
+    /// disabled and enabled) keep their entries. Files that no provider knew at the last refresh
+    /// are only looked up again once they change.
+    pub async fn refresh(&mut self) -> Result<RefreshSummary> {
+        self.reconcile(false).await
+    }
+
+    /// Like [`Sync::refresh`], but files that no provider knew are looked up again too.
+    pub async fn force_refresh(&mut self) -> Result<RefreshSummary> {
+        self.reconcile(true).await
+    }
+
+    async fn reconcile(&mut self, force: bool) -> Result<RefreshSummary> {
+        let mut summary = RefreshSummary::default();
+        let minecraft_directory = self.minecraft_directory.clone();
+        let dirs = self.content_dirs();
+        let (entries, dirs) = tokio::task::spawn_blocking(move || {
+            let mut entries = vec![];
+            // which content directory each file is in, and the kind of content in it
+            let mut file_dirs = HashMap::new();
+            for (dir, kind) in dirs {
+                let path = minecraft_directory.join(&dir);
+                if !path.is_dir() {
+                    continue;
+                }
+                for entry in std::fs::read_dir(path)? {
+                    let entry = entry?;
+                    file_dirs.insert(entry.path(), (dir.clone(), kind));
+                    entries.push(entry);
+                }
+            }
+            Ok::<_, std::io::Error>((entries, file_dirs))
         })
         .await
         .context("tokio fail")??;
 
+        let cache = self
+            .hash_cache
+            .as_deref()
+            .map(HashCache::read)
+            .unwrap_or_default();
         let entries_len = entries.len();
-        let hashes: HashMap<PathBuf, String> = entries
+        let files = entries
             .into_par_iter()
             .progress_count(entries_len as u64)
             .with_style(
@@ -83,27 +306,184 @@ impl Sync {
             )
             .with_message("(1/2) Hashing files...")
             .with_finish(ProgressFinish::AndLeave)
-            .filter(|it| !it.file_type().unwrap().is_dir())
             .map(|entry| {
                 let path = entry.path();
-                let hash =
-                    Sha1::from(std::fs::read(&path).expect("fs fail").as_slice()).hexdigest();
-                (path, hash)
+                if entry
+                    .file_type()
+                    .with_context(|| format!("read {}", path.display()))?
+                    .is_dir()
+                {
+                    return Ok(None);
+                }
+                let stat = entry
+                    .metadata()
+                    .with_context(|| format!("read {}", path.display()))?;
+                // only files that changed since the last refresh are read
+                let (hashes, metadata) = match cache.get(&path, &stat) {
+                    Some(cached) => cached,
+                    None => {
+                        let hashes = FileHashes::read(&path)
+                            .with_context(|| format!("hash {}", path.display()))?;
+                        let metadata = ContentMetadata::read(&path, dirs[&path].1)
+                            .inspect_err(|err| {
+                                warn!("can't read metadata of {}: {err}", path.display())
+                            })
+                            .ok()
+                            .flatten();
+                        (hashes, metadata)
+                    }
+                };
+                let unknown_to = cache.unknown_to(&path, &stat);
+                Ok(Some((path, stat, hashes, metadata, unknown_to)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // the new cache only has the files that are still there
+        let mut cache = HashCache::default();
+        let mut hashes = HashMap::new();
+        let mut metadata = HashMap::new();
+        let mut unknown_to = HashMap::new();
+        for (path, stat, file_hashes, file_metadata, file_unknown_to) in files.into_iter().flatten()
+        {
+            cache.insert(
+                path.clone(),
+                &stat,
+                file_hashes.clone(),
+                file_metadata.clone(),
+            );
+            cache.set_unknown_to(&path, file_unknown_to.clone());
+            hashes.insert(path.clone(), file_hashes);
+            metadata.insert(path.clone(), file_metadata);
+            unknown_to.insert(path, file_unknown_to);
+        }
+        self.write_hash_cache(&cache);
+
+        // entries whose files are gone, unless they turn out to be renamed
+        let mut missing = self
+            .index
+            .packages
+            .iter()
+            .enumerate()
+            .filter(|(_, it)| {
+                !hashes.contains_key(&it.get_namespace(&self.minecraft_directory).join(&it.file))
             })
-            .collect();
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
 
         debug!("Refreshing for {} files", hashes.len());
+        if hashes.is_empty() {
+            self.remove_missing(missing, &mut summary);
+            return Ok(summary);
+        }
 
-        let versions = self
-            .fer
-            .version_get_from_multiple_hashes(hashes.values().cloned().collect::<Vec<_>>())
-            .await?;
+        // where each file is in the index, if it is
+        let mut positions = hashes
+            .keys()
+            .map(|path| {
+                let filename = path.file_name().unwrap().to_str().unwrap();
+                let dir = &dirs[path].0;
+                let index = self
+                    .index
+                    .packages
+                    .iter()
+                    .position(|it| it.file == filename && it.namespace_dir() == dir);
+                (path.clone(), index)
+            })
+            .collect::<HashMap<_, _>>();
+        // a new file with the same contents as a missing one is that one, renamed
+        for (path, hash) in &hashes {
+            if positions[path].is_some() {
+                continue;
+            }
+            let Some(k) = missing
+                .iter()
+                .position(|&i| self.index.packages[i].sha1.as_ref() == Some(&hash.sha1))
+            else {
+                continue;
+            };
+            let i = missing.swap_remove(k);
+            let filename = path.file_name().unwrap().to_str().unwrap();
+            let (dir, kind) = &dirs[path];
+            let package = &mut self.index.packages[i];
+            debug!("{} was renamed to {filename}", package.file);
+            package.file = filename.to_string();
+            package.disabled = filename.ends_with(".disabled");
+            package.kind = *kind;
+            package.dir = (dir != kind.dir()).then(|| dir.clone());
+            summary.changed.push(package.id.clone());
+            positions.insert(path.clone(), Some(i));
+        }
+        // files that are in the index with the same hash don't need to be identified again,
+        // unless no provider knew them yet, like files from a modpack or added by hand, and
+        // there's a provider that wasn't asked about them since they last changed
+        let kinds = self
+            .providers
+            .iter()
+            .map(|it| it.kind())
+            .collect::<Vec<_>>();
+        let unknown = hashes
+            .iter()
+            .filter(|(path, hash)| {
+                positions[*path].is_none_or(|i| {
+                    let package = &self.index.packages[i];
+                    package.sha1.as_ref() != Some(&hash.sha1)
+                        || package.provider.is_none()
+                            && (force || kinds.iter().any(|it| !unknown_to[*path].contains(it)))
+                })
+            })
+            .collect::<HashMap<_, _>>();
 
+        // every provider gets asked about the files the previous ones didn't know
+        let mut identified: HashMap<String, (ProviderKind, ProjectVersion)> = HashMap::new();
+        for provider in &self.providers {
+            let remaining = unknown
+                .values()
+                .copied()
+                .filter(|it| !identified.contains_key(&it.sha1))
+                .collect::<Vec<_>>();
+            if remaining.is_empty() {
+                break;
+            }
+            let found = provider.identify(&remaining).await?;
+            debug!("{} identified {} files", provider.kind(), found.len());
+            identified.extend(
+                found
+                    .into_iter()
+                    .map(|(hash, version)| (hash, (provider.kind(), version))),
+            );
+        }
         debug!(
-            "Got {} versions, diff = {}",
-            versions.len(),
-            hashes.len() - versions.len()
+            "Identified {} files, diff = {}",
+            identified.len(),
+            unknown.len() - identified.len()
         );
+        // so that they're not looked up again until they change
+        for (path, hash) in &unknown {
+            if !identified.contains_key(&hash.sha1) {
+                cache.set_unknown_to(path, kinds.clone());
+            }
+        }
+        self.write_hash_cache(&cache);
+
+        // projects of files that aren't in the index yet, looked up all at once per provider
+        let mut projects: HashMap<(ProviderKind, String), Project> = HashMap::new();
+        for provider in &self.providers {
+            let mut ids = unknown
+                .iter()
+                .filter(|(path, _)| positions[**path].is_none())
+                .filter_map(|(_, hash)| identified.get(&hash.sha1))
+                .filter(|(kind, _)| *kind == provider.kind())
+                .map(|(_, version)| version.project_id.as_str())
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids.dedup();
+            if ids.is_empty() {
+                continue;
+            }
+            for project in provider.projects(&ids).await? {
+                projects.insert((provider.kind(), project.id.clone()), project);
+            }
+        }
 
         let progress = ProgressBar::new(hashes.len() as u64).with_finish(ProgressFinish::AndLeave);
         progress.set_style(
@@ -117,71 +497,118 @@ impl Sync {
         for (path, hash) in hashes.iter() {
             let filename = path.file_name().unwrap().to_str().unwrap();
             let disabled = filename.ends_with(".disabled");
-            let index = self
-                .index
-                .packages
-                .iter()
-                .enumerate()
-                .find(|(_, it)| it.file == filename)
-                .map(|it| it.0);
-            debug!("Processing {} ({})", filename, hash);
-            match versions.get(hash) {
-                Some(version) => {
-                    debug!("Found version {}", version.version_number);
+            let (dir, kind) = dirs[path].clone();
+            let metadata = metadata.remove(path).flatten();
+            let index = positions[path];
+            if !unknown.contains_key(path) {
+                // unchanged, but the index may be from before metadata was recorded
+                self.index.packages[index.unwrap()].metadata = metadata;
+                progress.inc(1);
+                continue;
+            }
+            // only record the directory if it isn't the default one for the kind
+            let dir = (dir != kind.dir()).then_some(dir);
+            debug!("Processing {} ({})", filename, hash.sha1);
+            match identified.get(&hash.sha1) {
+                Some((provider, version)) => {
+                    debug!("Found {provider} version {}", version.version_number);
+                    let url = version
+                        .files
+                        .iter()
+                        .find(|it| it.sha1.as_ref() == Some(&hash.sha1))
+                        .and_then(|it| it.url.clone());
                     if let Some(index) = index {
                         let package = &mut self.index.packages[index];
-                        package.modrinth_project = Some(version.project_id.clone());
-                        package.modrinth_version_id = Some(version.id.clone());
+                        package.provider = Some(*provider);
+                        package.project_id = Some(version.project_id.clone());
+                        package.version_id = Some(version.id.clone());
                         package.version = version.version_number.clone();
+                        package.set_hashes(hash);
+                        package.url = url;
+                        package.metadata = metadata;
+                        summary.changed.push(package.id.clone());
                     } else {
-                        let project = self.fer.project_get(&version.project_id).await?;
+                        let Some(project) = projects.get(&(*provider, version.project_id.clone()))
+                        else {
+                            error!(
+                                "project {} not found on {provider}, skipping...",
+                                version.project_id
+                            );
+                            continue;
+                        };
+                        if project.kind.is_none() {
+                            error!("unsupported project type of {}, skipping...", project.slug);
+                            continue;
+                        }
+                        summary.added.push(project.slug.clone());
                         self.index.packages.push(SyncIndexEntry {
-                            id: project.slug,
+                            id: project.slug.clone(),
                             disabled,
                             file: filename.to_string(),
-                            modrinth_project: Some(version.project_id.clone()),
-                            modrinth_version_id: Some(version.id.clone()),
+                            provider: Some(*provider),
+                            project_id: Some(version.project_id.clone()),
+                            version_id: Some(version.id.clone()),
                             version: version.version_number.clone(),
-                            kind: match project.project_type {
-                                ProjectType::Mod => IndexEntryKind::Mod,
-                                ProjectType::ResourcePack => IndexEntryKind::Resourcepack,
-                                t => {
-                                    error!("unsupported project type: {t:?}, skipping...");
-                                    continue;
-                                }
-                            },
+                            held: false,
+                            sha1: Some(hash.sha1.clone()),
+                            sha512: Some(hash.sha512.clone()),
+                            size: Some(hash.size),
+                            url,
+                            kind,
+                            dir,
+                            metadata,
                         });
                     }
                 }
                 None => {
                     let trimmed_filename = filename.trim_end_matches(".disabled");
-                    let tuple = VersionTuple::parse(trimmed_filename).context("parse version")?;
-                    let kind = if trimmed_filename.ends_with(".jar") {
-                        IndexEntryKind::Mod
-                    } else if trimmed_filename.ends_with(".zip") {
-                        IndexEntryKind::Resourcepack
-                    } else {
+                    if !kind.accepts(trimmed_filename) {
                         error!("unsupported file extension: {filename}, skipping...");
                         continue;
-                    };
+                    }
+                    let (name, version) =
+                        ContentMetadata::name_and_version(metadata.as_ref(), trimmed_filename);
 
                     debug!(
-                        "{filename} not found on modrinth, parsed as {tuple:?} and kind={kind:?}"
+                        "{filename} not found by any provider, read as {name} {version} and kind={kind:?}"
                     );
                     if let Some(index) = index {
                         debug!("{filename} found in index");
                         let package = &mut self.index.packages[index];
-                        package.version = tuple.version;
+                        if package.sha1.as_ref() == Some(&hash.sha1) {
+                            // still unknown to every provider, and unchanged
+                            package.metadata = metadata;
+                            progress.inc(1);
+                            continue;
+                        }
+                        if package.is_foreign() {
+                            package.id = name;
+                        }
+                        package.version = version;
+                        package.metadata = metadata;
+                        // the file changed, so it can't come from the recorded URL anymore
+                        package.url = None;
+                        package.set_hashes(hash);
+                        summary.changed.push(package.id.clone());
                     } else {
                         debug!("Inserting {filename} into index");
+                        summary.added.push(name.clone());
                         self.index.packages.push(SyncIndexEntry {
-                            id: tuple.name,
+                            id: name,
                             disabled,
                             file: filename.to_string(),
-                            modrinth_project: None,
-                            modrinth_version_id: None,
-                            version: tuple.version,
+                            provider: None,
+                            project_id: None,
+                            version_id: None,
+                            version,
                             kind,
+                            dir,
+                            held: false,
+                            sha1: Some(hash.sha1.clone()),
+                            sha512: Some(hash.sha512.clone()),
+                            size: Some(hash.size),
+                            url: None,
+                            metadata,
                         });
                     }
                 }
@@ -190,25 +617,76 @@ impl Sync {
         }
 
         progress.finish();
+        self.remove_missing(missing, &mut summary);
 
-        Ok(())
+        Ok(summary)
+    }
+
+    fn write_hash_cache(&self, cache: &HashCache) {
+        if let Some(path) = &self.hash_cache
+            && let Err(err) = cache.write(path)
+        {
+            warn!("can't write hash cache {}: {err}", path.display());
+        }
+    }
+
+    fn remove_missing(&mut self, mut missing: Vec<usize>, summary: &mut RefreshSummary) {
+        missing.sort_unstable_by(|a, b| b.cmp(a));
+        for i in missing {
+            let package = self.index.packages.remove(i);
+            debug!(
+                "{} is gone, removing {} from index",
+                package.file, package.id
+            );
+            summary.removed.push(package.id);
+        }
     }
 
     pub async fn maybe_refresh(&mut self) -> Result<()> {
         if self.index.packages.is_empty() {
-            self.refresh().await
-        } else {
-            Ok(())
+            self.refresh().await?;
         }
+        Ok(())
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+/// What a refresh changed in the index, by package id.
+#[derive(Debug, Default, PartialEq)]
+pub struct RefreshSummary {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Packages whose files were renamed or replaced
+    pub changed: Vec<String>,
+}
+
+impl RefreshSummary {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct SyncIndex {
+    /// Version of the layout of the lock file, see [`crate::migrate`]
+    #[serde(default = "lock_schema")]
+    pub schema: u32,
     #[serde(default)]
     pub packages: Vec<SyncIndexEntry>,
 }
 
+fn lock_schema() -> u32 {
+    migrate::LOCK.current()
+}
+
+impl Default for SyncIndex {
+    fn default() -> Self {
+        Self {
+            schema: lock_schema(),
+            packages: vec![],
+        }
+    }
+}
+
 /// Low level operations on the sync index and its entries
 impl SyncIndex {
     pub fn get_lock_path(normal_config_path: &Path) -> PathBuf {
@@ -225,16 +703,22 @@ impl SyncIndex {
     pub fn read(normal_config_path: &Path) -> Result<Self> {
         let path = Self::get_lock_path(normal_config_path);
         if path.exists() {
-            let contents = std::fs::read_to_string(path)?;
+            let contents = migrate::read(&path, &migrate::LOCK)?;
             Ok(toml::from_str(&contents)?)
         } else {
             Ok(Self::default())
         }
     }
 
-    pub fn write(&self, normal_config_path: &Path) -> Result<()> {
+    /// Write the index to the lock file. The lock file is replaced at once, so it's never left
+    /// half-written.
+    pub fn write(&mut self, normal_config_path: &Path) -> Result<()> {
+        self.sort();
         let contents = toml::to_string(&self)?;
-        std::fs::write(Self::get_lock_path(normal_config_path), contents)?;
+        let path = Self::get_lock_path(normal_config_path);
+        let temp = path.with_extension("tmp");
+        std::fs::write(&temp, contents)?;
+        std::fs::rename(&temp, &path)?;
         Ok(())
     }
 
@@ -243,27 +727,7 @@ impl SyncIndex {
             .packages
             .iter()
             .enumerate()
-            .filter_map(|(i, it)| {
-                if it.id != filter.name {
-                    return None;
-                }
-
-                if let Some(version) = &filter.version {
-                    match version {
-                        VersionFilter::Any => Some(i),
-                        VersionFilter::Op(version, Cmp::Eq) => {
-                            (it.version.as_str() == version.as_str()).then(|| i)
-                        }
-                        VersionFilter::Op(version, op) => matches!(
-                            version_compare::compare_to(it.version.as_str(), version.as_str(), *op),
-                            Ok(true)
-                        )
-                        .then(|| i),
-                    }
-                } else {
-                    Some(i)
-                }
-            })
+            .filter_map(|(i, it)| it.matches(filter).then_some(i))
             .collect::<Vec<_>>();
 
         if filter.version.is_none() && indices.len() > 1 {
@@ -271,46 +735,399 @@ impl SyncIndex {
                 "Multiple matches found for {name}, please specify version.",
                 name = filter.name
             ));
-            return vec![];
+            vec![]
         } else {
             indices
         }
     }
+
+    /// Find the package installed from `project_id` on `provider`.
+    pub fn find_project(
+        &self,
+        provider: ProviderKind,
+        project_id: &str,
+    ) -> Option<&SyncIndexEntry> {
+        self.packages
+            .iter()
+            .find(|it| it.is_from(provider, project_id))
+    }
+
+    /// Find all packages whose id or file name matches `regex`.
+    pub fn search_packages(&self, regex: &Regex) -> Vec<usize> {
+        self.packages
+            .iter()
+            .enumerate()
+            .filter(|(_, it)| regex.is_match(&it.id) || regex.is_match(&it.file))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Find the package that owns `file`, a path relative to the Minecraft directory like
+    /// `mods/sodium.jar`. The directory has to match too, so content of different kinds with
+    /// the same file name isn't mixed up.
+    ///
+    /// A disabled package still owns its enabled file name, and vice versa.
+    pub fn find_owner(&self, file: &Path) -> Option<usize> {
+        let filename = file.file_name()?.to_str()?;
+        let dir = file
+            .parent()?
+            .components()
+            .map(|it| it.as_os_str().to_str())
+            .collect::<Option<Vec<_>>>()?
+            .join("/");
+        let trimmed = filename.trim_end_matches(".disabled");
+        self.packages
+            .iter()
+            .position(|it| it.namespace_dir() == dir && it.file == filename)
+            .or_else(|| {
+                self.packages.iter().position(|it| {
+                    it.namespace_dir() == dir && it.file.trim_end_matches(".disabled") == trimmed
+                })
+            })
+    }
 }
 
 impl SyncIndexEntry {
-    fn get_namespace(&self, prefix: &Path) -> PathBuf {
-        match self.kind {
-            IndexEntryKind::Mod => prefix.join("mods"),
-            IndexEntryKind::Resourcepack => prefix.join("resourcepacks"),
-        }
+    /// Whether this package's id and version satisfy `filter`.
+    pub fn matches(&self, filter: &SyncFilter) -> bool {
+        self.id == filter.name
+            && filter
+                .version
+                .as_ref()
+                .is_none_or(|version| version.matches(&self.version))
     }
 
-    pub fn disable_and_move(&mut self, prefix: &Path) -> Result<(), std::io::Error> {
+    /// Whether this package was not found by any provider, i.e. it's a "foreign" package.
+    pub fn is_foreign(&self) -> bool {
+        self.provider.is_none()
+    }
+
+    pub fn is_from(&self, provider: ProviderKind, project_id: &str) -> bool {
+        self.provider == Some(provider) && self.project_id.as_deref() == Some(project_id)
+    }
+
+    /// Whether both packages were installed from the same project of the same provider.
+    pub fn same_project(&self, other: &SyncIndexEntry) -> bool {
+        self.provider.is_some()
+            && self.provider == other.provider
+            && self.project_id == other.project_id
+    }
+
+    /// The directory the package is in, relative to the instance directory.
+    pub fn namespace_dir(&self) -> &str {
+        self.dir.as_deref().unwrap_or(self.kind.dir())
+    }
+
+    pub fn get_namespace(&self, prefix: &Path) -> PathBuf {
+        prefix.join(self.namespace_dir())
+    }
+
+    pub fn disable_and_move(&mut self, transaction: &mut Transaction) -> Result<()> {
         self.disabled = true;
-        let ns = self.get_namespace(prefix);
-        std::fs::rename(
-            ns.join(&self.file),
-            ns.join(format!("{}.disabled", self.file)),
+        let ns = self.get_namespace(transaction.root());
+        transaction.rename(
+            &ns.join(&self.file),
+            &ns.join(format!("{}.disabled", self.file)),
         )?;
         self.file = format!("{}.disabled", self.file);
         Ok(())
     }
 
-    pub fn enable_and_move(&mut self, prefix: &Path) -> Result<(), std::io::Error> {
+    pub fn enable_and_move(&mut self, transaction: &mut Transaction) -> Result<()> {
         self.disabled = false;
-        let ns = self.get_namespace(prefix);
+        let ns = self.get_namespace(transaction.root());
         let enabled_filename = self.file.trim_end_matches(".disabled");
-        std::fs::rename(ns.join(&self.file), ns.join(enabled_filename))?;
+        transaction.rename(&ns.join(&self.file), &ns.join(enabled_filename))?;
         self.file = enabled_filename.to_string();
         Ok(())
     }
 
-    pub fn remove_from_fs(&self, prefix: &Path) -> Result<(), std::io::Error> {
-        let ns = self.get_namespace(prefix);
-        let path = ns.join(&self.file);
-        debug!("remove_from_fs {}", path.display());
-        std::fs::remove_file(&path)?;
-        Ok(())
+    pub fn remove_from_fs(&self, transaction: &mut Transaction) -> Result<()> {
+        let path = self.get_namespace(transaction.root()).join(&self.file);
+        transaction.remove(&path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fixtures::entry, *};
+
+    fn index() -> SyncIndex {
+        SyncIndex {
+            packages: vec![
+                entry("sodium", "0.5.3", "sodium-fabric-0.5.3.jar", true),
+                entry("iris", "1.6.4", "iris-1.6.4.jar.disabled", true),
+                entry("mymod", "1.0.0", "mymod-1.0.0.jar", false),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_find_packages() {
+        let index = index();
+        assert_eq!(index.find_packages(&"iris".parse().unwrap()), vec![1]);
+        assert!(index.find_packages(&"iris>1.7".parse().unwrap()).is_empty());
+        assert!(index.find_packages(&"nothing".parse().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_is_held() {
+        let mut index = index();
+        index.packages[2].held = true;
+        let sync = Sync {
+            providers: vec![],
+            index,
+            minecraft_directory: PathBuf::new(),
+            game_version: "1.20.1".to_string(),
+            loader: Some("fabric".to_string()),
+            ignore: vec!["sodium<0.6".parse().unwrap()],
+            datapacks: DEFAULT_DATAPACKS_DIR.to_string(),
+            kind: None,
+            hash_cache: None,
+            transaction: Transaction::untracked(Path::new("")),
+        };
+        let held = sync
+            .index
+            .packages
+            .iter()
+            .filter(|it| sync.is_held(it))
+            .map(|it| it.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(held, vec!["sodium", "mymod"]);
+    }
+
+    #[test]
+    fn test_search_packages() {
+        let index = index();
+        assert_eq!(
+            index.search_packages(&Regex::new("^(sodium|iris)$").unwrap()),
+            vec![0, 1]
+        );
+        assert_eq!(
+            index.search_packages(&Regex::new("fabric").unwrap()),
+            vec![0]
+        );
+    }
+
+    #[test]
+    fn test_find_owner() {
+        let mut index = index();
+        assert_eq!(
+            index.find_owner(Path::new("mods/sodium-fabric-0.5.3.jar")),
+            Some(0)
+        );
+        assert_eq!(index.find_owner(Path::new("mods/iris-1.6.4.jar")), Some(1));
+        assert_eq!(
+            index.find_owner(Path::new("mods/mymod-1.0.0.jar.disabled")),
+            Some(2)
+        );
+        assert_eq!(index.find_owner(Path::new("mods/other.jar")), None);
+        assert_eq!(index.find_owner(Path::new("iris-1.6.4.jar")), None);
+
+        // the same file name in another directory is another package
+        let mut pack = entry("iris-pack", "1.0", "iris-1.6.4.jar", false);
+        pack.kind = IndexEntryKind::Resourcepack;
+        index.packages.push(pack);
+        let mut datapack = entry("terralith", "2.4", "terralith.zip", false);
+        datapack.kind = IndexEntryKind::Datapack;
+        datapack.dir = Some("saves/Other/datapacks".to_string());
+        index.packages.push(datapack);
+        assert_eq!(
+            index.find_owner(Path::new("resourcepacks/iris-1.6.4.jar")),
+            Some(3)
+        );
+        assert_eq!(
+            index.find_owner(Path::new("saves/Other/datapacks/terralith.zip")),
+            Some(4)
+        );
+        assert_eq!(
+            index.find_owner(Path::new("saves/World/datapacks/terralith.zip")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_foreign() {
+        let index = index();
+        let foreign = index
+            .packages
+            .iter()
+            .filter(|it| it.is_foreign())
+            .map(|it| it.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(foreign, vec!["mymod"]);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_content_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &[u8]| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        write("shaderpacks/complementary-5.1.zip", b"shader");
+        write("saves/World/datapacks/terralith-2.4.zip", b"datapack");
+        write("saves/World/datapacks/notes.txt", b"not a datapack");
+
+        let mut terralith = fixtures::version("terralith", "2.4", "2023-06-01T00:00:00Z", &[]);
+        terralith.files[0].sha1 = Some(FileHashes::from_bytes(b"datapack").sha1);
+        let provider = fake::FakeProvider::new(
+            vec![fixtures::project(
+                "terralith",
+                "terralith",
+                IndexEntryKind::Datapack,
+            )],
+            vec![terralith],
+        );
+        let mut sync = fixtures::sync(SyncIndex::default(), provider, dir.path().to_path_buf());
+        assert!(sync.set_world("Nowhere").is_err());
+        sync.set_world("World").unwrap();
+        assert_eq!(sync.datapacks, "saves/World/datapacks");
+
+        sync.refresh().await.unwrap();
+        let mut packages = sync
+            .index
+            .packages
+            .iter()
+            .map(|it| (it.id.as_str(), it.kind, it.namespace_dir()))
+            .collect::<Vec<_>>();
+        packages.sort_by_key(|it| it.0);
+        assert_eq!(
+            packages,
+            vec![
+                ("complementary", IndexEntryKind::Shader, "shaderpacks"),
+                (
+                    "terralith",
+                    IndexEntryKind::Datapack,
+                    "saves/World/datapacks"
+                ),
+            ]
+        );
+        // shaderpacks is the default directory for shaders, so it isn't recorded
+        let shader = sync.index.find_packages(&"complementary".parse().unwrap())[0];
+        assert_eq!(sync.index.packages[shader].dir, None);
+    }
+
+    #[test]
+    fn test_metadata_in_index() {
+        let mut index = index();
+        let mut depends = std::collections::BTreeMap::new();
+        depends.insert("minecraft".to_string(), vec!["1.20.x".to_string()]);
+        index.packages[2].metadata = Some(ContentMetadata {
+            source: MetadataSource::Fabric,
+            id: Some("mymod".to_string()),
+            version: Some("1.0.0".to_string()),
+            name: Some("My Mod".to_string()),
+            description: None,
+            pack_format: None,
+            provides: Default::default(),
+            depends,
+            breaks: Default::default(),
+        });
+        let contents = toml::to_string(&index).unwrap();
+        let read: SyncIndex = toml::from_str(&contents).unwrap();
+        assert_eq!(read.packages[2].metadata, index.packages[2].metadata);
+        assert!(read.packages[0].metadata.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_incremental() {
+        let dir = tempfile::tempdir().unwrap();
+        let mods = dir.path().join("mods");
+        std::fs::create_dir_all(&mods).unwrap();
+        std::fs::write(mods.join("sodium-0.5.3.jar"), b"sodium").unwrap();
+        std::fs::write(mods.join("mymod-1.0.0.jar"), b"mymod").unwrap();
+
+        let mut sodium = fixtures::version("sodium", "0.5.3", "2023-09-01T00:00:00Z", &[]);
+        sodium.files[0].sha1 = Some(FileHashes::from_bytes(b"sodium").sha1);
+        let provider = fake::FakeProvider::new(
+            vec![fixtures::project("sodium", "sodium", IndexEntryKind::Mod)],
+            vec![sodium],
+        );
+        let asked = provider.asked.clone();
+        let mut sync = fixtures::sync(SyncIndex::default(), provider, dir.path().to_path_buf());
+        let cache_path = dir.path().join("hashes.json");
+        sync.hash_cache = Some(cache_path.clone());
+
+        sync.refresh().await.unwrap();
+        assert_eq!(sync.index.packages.len(), 2);
+        assert_eq!(asked.lock().unwrap().len(), 2);
+        assert!(cache_path.is_file());
+
+        // nothing changed, so nothing is asked about again, not even the file no provider knew
+        let summary = sync.refresh().await.unwrap();
+        assert!(summary.is_empty());
+        assert_eq!(sync.index.packages.len(), 2);
+        assert_eq!(asked.lock().unwrap().len(), 2);
+
+        // only the new file
+        std::fs::write(mods.join("other-2.0.jar"), b"other").unwrap();
+        sync.refresh().await.unwrap();
+        assert_eq!(sync.index.packages.len(), 3);
+        assert_eq!(
+            asked.lock().unwrap()[2..],
+            [FileHashes::from_bytes(b"other").sha1]
+        );
+
+        // the file no provider knew is asked about again once it changes
+        std::fs::write(mods.join("mymod-1.0.0.jar"), b"mymod 1.0.1").unwrap();
+        sync.refresh().await.unwrap();
+        assert_eq!(
+            asked.lock().unwrap()[3..],
+            [FileHashes::from_bytes(b"mymod 1.0.1").sha1]
+        );
+
+        // or when forced to, along with every other one
+        sync.force_refresh().await.unwrap();
+        let mut forced = asked.lock().unwrap()[4..].to_vec();
+        forced.sort();
+        let mut expected = vec![
+            FileHashes::from_bytes(b"mymod 1.0.1").sha1,
+            FileHashes::from_bytes(b"other").sha1,
+        ];
+        expected.sort();
+        assert_eq!(forced, expected);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_reconcile() {
+        let dir = tempfile::tempdir().unwrap();
+        let mods = dir.path().join("mods");
+        std::fs::create_dir_all(&mods).unwrap();
+        std::fs::write(mods.join("alpha-1.0.jar"), b"alpha").unwrap();
+        std::fs::write(mods.join("beta-2.0.jar"), b"beta").unwrap();
+        let provider = fake::FakeProvider::new(vec![], vec![]);
+        let mut sync = fixtures::sync(SyncIndex::default(), provider, dir.path().to_path_buf());
+
+        let summary = sync.refresh().await.unwrap();
+        let mut added = summary.added.clone();
+        added.sort();
+        assert_eq!(added, vec!["alpha", "beta"]);
+
+        // disabled by hand, deleted and a new one
+        std::fs::rename(
+            mods.join("alpha-1.0.jar"),
+            mods.join("alpha-1.0.jar.disabled"),
+        )
+        .unwrap();
+        std::fs::remove_file(mods.join("beta-2.0.jar")).unwrap();
+        std::fs::write(mods.join("gamma-3.0.jar"), b"gamma").unwrap();
+        let summary = sync.refresh().await.unwrap();
+        assert_eq!(
+            summary,
+            RefreshSummary {
+                added: vec!["gamma".to_string()],
+                removed: vec!["beta".to_string()],
+                changed: vec!["alpha".to_string()],
+            }
+        );
+        assert_eq!(sync.index.packages.len(), 2);
+        let alpha = sync.index.find_packages(&"alpha".parse().unwrap())[0];
+        assert!(sync.index.packages[alpha].disabled);
+        assert_eq!(sync.index.packages[alpha].file, "alpha-1.0.jar.disabled");
+
+        assert!(sync.refresh().await.unwrap().is_empty());
     }
 }
@@ -0,0 +1,313 @@
+//! Checking the mods of an instance against each other before launching it, so that missing
+//! dependencies show up here instead of on Fabric Loader's error screen.
+
+use std::{collections::BTreeMap, path::Path};
+
+use color_eyre::Result;
+use rayon::prelude::*;
+use version_compare::Cmp;
+
+use super::{ContentMetadata, IndexEntryKind, MetadataSource};
+
+/// A reason the mods of an instance won't load.
+#[derive(Debug, PartialEq)]
+pub enum LaunchProblem {
+    /// `package` needs a version of `dependency` matching any of `versions`, and `found` is
+    /// the installed one, if any
+    Unsatisfied {
+        package: String,
+        dependency: String,
+        versions: Vec<String>,
+        found: Option<String>,
+    },
+    /// `package` breaks the installed `with`
+    Breaks { package: String, with: String },
+    /// Mods with the same id, by file name
+    Duplicate { id: String, files: Vec<String> },
+    /// `package` is made for another mod loader
+    WrongLoader {
+        package: String,
+        source: MetadataSource,
+    },
+}
+
+impl std::fmt::Display for LaunchProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LaunchProblem::Unsatisfied {
+                package,
+                dependency,
+                versions,
+                found,
+            } => {
+                write!(f, "unable to satisfy dependency '{dependency}")?;
+                if versions.iter().any(|it| it != "*") {
+                    write!(f, " {}", versions.join(" || "))?;
+                }
+                write!(f, "' required by {package}")?;
+                match found {
+                    Some(found) => write!(f, " ({dependency} {found} is installed)"),
+                    None => Ok(()),
+                }
+            }
+            LaunchProblem::Breaks { package, with } => {
+                write!(f, "{package} and {with} are in conflict")
+            }
+            LaunchProblem::Duplicate { id, files } => {
+                write!(f, "{id} is installed more than once: {}", files.join(", "))
+            }
+            LaunchProblem::WrongLoader { package, source } => {
+                write!(f, "{package} is a {source:?} mod, which Fabric won't load")
+            }
+        }
+    }
+}
+
+/// Read the metadata of every enabled mod in `mods_dir`, by file name. Jars without metadata,
+/// like libraries, are left out.
+pub fn read_mods(mods_dir: &Path) -> Result<Vec<(String, ContentMetadata)>> {
+    if !mods_dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let files = std::fs::read_dir(mods_dir)?
+        .map(|it| Ok(it?.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let mut mods = files
+        .into_par_iter()
+        .filter(|it| it.is_file() && it.extension().is_some_and(|it| it == "jar"))
+        .filter_map(|path| {
+            let file = path.file_name()?.to_string_lossy().into_owned();
+            match ContentMetadata::read(&path, IndexEntryKind::Mod) {
+                Ok(metadata) => Some((file, metadata?)),
+                Err(err) => {
+                    warn!("can't read metadata of {file}: {err}");
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    mods.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(mods)
+}
+
+/// Find everything that would stop `mods` from loading on Fabric `loader_version` for
+/// Minecraft `game_version`.
+pub fn check_mods(
+    mods: &[(String, ContentMetadata)],
+    game_version: &str,
+    loader_version: &str,
+) -> Vec<LaunchProblem> {
+    let mut problems = vec![];
+
+    // every mod id that is there, with its version
+    let mut installed = BTreeMap::new();
+    installed.insert("minecraft", game_version);
+    installed.insert("fabricloader", loader_version);
+    let mut files: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (file, metadata) in mods {
+        let (Some(id), Some(version)) = (&metadata.id, &metadata.version) else {
+            continue;
+        };
+        installed.insert(id, version);
+        for (id, version) in &metadata.provides {
+            installed.entry(id).or_insert(version);
+        }
+        files.entry(id).or_default().push(file.clone());
+    }
+
+    for (id, files) in files {
+        if files.len() > 1 {
+            problems.push(LaunchProblem::Duplicate {
+                id: id.to_string(),
+                files,
+            });
+        }
+    }
+
+    for (_, metadata) in mods {
+        let Some(package) = &metadata.id else {
+            continue;
+        };
+        if metadata.source != MetadataSource::Fabric {
+            problems.push(LaunchProblem::WrongLoader {
+                package: package.clone(),
+                source: metadata.source,
+            });
+            continue;
+        }
+        for (dependency, versions) in &metadata.depends {
+            // startmc doesn't pick the Java version, so there is nothing to check it against
+            if dependency == "java" {
+                continue;
+            }
+            let found = installed.get(dependency.as_str());
+            if !found.is_some_and(|found| versions.iter().any(|it| matches_predicate(found, it))) {
+                problems.push(LaunchProblem::Unsatisfied {
+                    package: package.clone(),
+                    dependency: dependency.clone(),
+                    versions: versions.clone(),
+                    found: found.map(|it| it.to_string()),
+                });
+            }
+        }
+        for (with, versions) in &metadata.breaks {
+            if let Some(found) = installed.get(with.as_str())
+                && versions.iter().any(|it| matches_predicate(found, it))
+            {
+                problems.push(LaunchProblem::Breaks {
+                    package: package.clone(),
+                    with: with.clone(),
+                });
+            }
+        }
+    }
+
+    problems
+}
+
+/// Whether `version` matches a Fabric version predicate, like `>=0.14 <0.15`, `~1.20.1`,
+/// `^0.5` or `1.20.x`. Versions that can't be compared are assumed to match, so that odd version
+/// schemes aren't reported as problems.
+pub fn matches_predicate(version: &str, predicate: &str) -> bool {
+    predicate
+        .split_whitespace()
+        .all(|it| matches_single(version, it))
+}
+
+fn matches_single(version: &str, predicate: &str) -> bool {
+    // build metadata like `+mc1.20.1` doesn't count when comparing versions
+    let strip_build = |it: &str| it.split('+').next().unwrap_or(it).to_string();
+    let version = strip_build(version);
+    let (op, expected) = [">=", "<=", ">", "<", "=", "~", "^"]
+        .iter()
+        .find_map(|op| predicate.strip_prefix(op).map(|rest| (*op, rest)))
+        .unwrap_or(("", predicate));
+    let expected = strip_build(expected);
+    if expected == "*" {
+        return true;
+    }
+
+    let cmp = |expected: &str| version_compare::compare(&version, expected).ok();
+    let is = |expected: &str, allowed: &[Cmp]| cmp(expected).is_none_or(|it| allowed.contains(&it));
+    match op {
+        ">=" => is(&expected, &[Cmp::Gt, Cmp::Eq]),
+        "<=" => is(&expected, &[Cmp::Lt, Cmp::Eq]),
+        ">" => is(&expected, &[Cmp::Gt]),
+        "<" => is(&expected, &[Cmp::Lt]),
+        "~" | "^" => {
+            let parts = expected.split('.').collect::<Vec<_>>();
+            // ~ allows newer patch versions, ^ allows newer minor versions
+            let keep = if op == "~" && parts.len() > 1 { 2 } else { 1 };
+            let mut next = parts[..keep - 1].to_vec();
+            let bumped = parts[keep - 1]
+                .parse::<u64>()
+                .map(|it| (it + 1).to_string())
+                .unwrap_or_default();
+            next.push(&bumped);
+            is(&expected, &[Cmp::Gt, Cmp::Eq]) && is(&next.join("."), &[Cmp::Lt])
+        }
+        _ if expected.contains(['x', 'X', '*']) => {
+            let version = version.split('.').collect::<Vec<_>>();
+            expected
+                .split('.')
+                .take_while(|it| !matches!(*it, "x" | "X" | "*"))
+                .enumerate()
+                .all(|(i, it)| version.get(i) == Some(&it))
+        }
+        _ => is(&expected, &[Cmp::Eq]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fabric_mod(id: &str, version: &str, depends: &[(&str, &str)]) -> ContentMetadata {
+        ContentMetadata {
+            source: MetadataSource::Fabric,
+            id: Some(id.to_string()),
+            version: Some(version.to_string()),
+            name: None,
+            description: None,
+            pack_format: None,
+            provides: BTreeMap::new(),
+            depends: depends
+                .iter()
+                .map(|(id, it)| (id.to_string(), vec![it.to_string()]))
+                .collect(),
+            breaks: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_matches_predicate() {
+        assert!(matches_predicate("0.14.22", ">=0.14.0"));
+        assert!(!matches_predicate("0.13.3", ">=0.14.0"));
+        assert!(matches_predicate("1.20.1", ">=1.20 <1.21"));
+        assert!(!matches_predicate("1.21", ">=1.20 <1.21"));
+        assert!(matches_predicate("1.20.1", "1.20.x"));
+        assert!(!matches_predicate("1.19.4", "1.20.x"));
+        assert!(matches_predicate("1.20.4", "~1.20.1"));
+        assert!(!matches_predicate("1.21", "~1.20.1"));
+        assert!(matches_predicate("0.6.0", "^0.5"));
+        assert!(!matches_predicate("1.0.0", "^0.5"));
+        assert!(matches_predicate("0.5.3+mc1.20.1", "0.5.3"));
+        assert!(matches_predicate("anything", "*"));
+    }
+
+    #[test]
+    fn test_check_mods() {
+        let mut sodium = fabric_mod("sodium", "0.5.3", &[("minecraft", "1.20.x")]);
+        sodium
+            .breaks
+            .insert("optifabric".to_string(), vec!["*".to_string()]);
+        let mut fabric_api = fabric_mod("fabric-api", "0.92.2", &[]);
+        fabric_api
+            .provides
+            .insert("fabric-api-base".to_string(), "0.4.31".to_string());
+        let mut jei = fabric_mod("jei", "15.3.0.4", &[]);
+        jei.source = MetadataSource::Forge;
+        let mods = vec![
+            ("fabric-api.jar".to_string(), fabric_api),
+            (
+                "iris.jar".to_string(),
+                fabric_mod(
+                    "iris",
+                    "1.6.4",
+                    &[
+                        ("sodium", ">=0.5.0"),
+                        ("fabricloader", ">=0.15"),
+                        ("fabric-api-base", "*"),
+                        ("java", ">=17"),
+                    ],
+                ),
+            ),
+            ("jei.jar".to_string(), jei),
+            (
+                "optifabric.jar".to_string(),
+                fabric_mod("optifabric", "1.0", &[]),
+            ),
+            (
+                "reeses.jar".to_string(),
+                fabric_mod("reeses-sodium-options", "1.6.3", &[("sodium-extra", "*")]),
+            ),
+            (
+                "sodium-old.jar".to_string(),
+                fabric_mod("sodium", "0.4.10", &[]),
+            ),
+            ("sodium.jar".to_string(), sodium),
+        ];
+
+        let problems = check_mods(&mods, "1.20.1", "0.14.22");
+        assert_eq!(
+            problems.iter().map(|it| it.to_string()).collect::<Vec<_>>(),
+            vec![
+                "sodium is installed more than once: sodium-old.jar, sodium.jar",
+                "unable to satisfy dependency 'fabricloader >=0.15' required by iris (fabricloader 0.14.22 is installed)",
+                "jei is a Forge mod, which Fabric won't load",
+                "unable to satisfy dependency 'sodium-extra' required by reeses-sodium-options",
+                "sodium and optifabric are in conflict",
+            ]
+        );
+    }
+}
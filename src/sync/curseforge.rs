@@ -0,0 +1,706 @@
+//! A small client for the CurseForge API.
+//!
+//! Unlike Modrinth, CurseForge needs an API key, which is read from `[sync.curseforge]` in the
+//! instance config.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use color_eyre::{
+    Result,
+    eyre::{ContextCompat, eyre},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use super::{
+    ContentProvider, Dependency, DependencyKind, FileHashes, IndexEntryKind, Project,
+    ProjectVersion, ProviderKind, SearchSort, VersionFile, VersionTuple, compatible_loaders,
+};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.curseforge.com";
+pub const MINECRAFT_GAME_ID: u32 = 432;
+
+const CLASS_MODS: u32 = 6;
+const CLASS_RESOURCEPACKS: u32 = 12;
+const CLASS_SHADERS: u32 = 6552;
+const CLASS_DATAPACKS: u32 = 6945;
+const PAGE_SIZE: usize = 50;
+
+/// `[sync.curseforge]` in the instance config.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CurseForgeConfig {
+    pub api_key: String,
+    /// Defaults to [`DEFAULT_BASE_URL`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Mod {
+    pub id: u64,
+    pub name: String,
+    pub slug: String,
+    pub summary: String,
+    pub download_count: u64,
+    pub class_id: Option<u32>,
+    pub date_modified: DateTime<Utc>,
+}
+
+impl Mod {
+    pub fn kind(&self) -> Option<IndexEntryKind> {
+        match self.class_id {
+            Some(CLASS_MODS) => Some(IndexEntryKind::Mod),
+            Some(CLASS_RESOURCEPACKS) => Some(IndexEntryKind::Resourcepack),
+            Some(CLASS_SHADERS) => Some(IndexEntryKind::Shader),
+            Some(CLASS_DATAPACKS) => Some(IndexEntryKind::Datapack),
+            _ => None,
+        }
+    }
+}
+
+/// The page of a file on the CurseForge website. Files whose authors don't allow downloading
+/// them anywhere else have no download URL, and can only be downloaded by hand from there.
+pub fn file_page(kind: IndexEntryKind, slug: &str, file_id: &str) -> String {
+    let class = match kind {
+        IndexEntryKind::Mod => "mc-mods",
+        IndexEntryKind::Resourcepack => "texture-packs",
+        IndexEntryKind::Shader => "shaders",
+        IndexEntryKind::Datapack => "data-packs",
+    };
+    format!("https://www.curseforge.com/minecraft/{class}/{slug}/files/{file_id}")
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct File {
+    pub id: u64,
+    pub mod_id: u64,
+    pub display_name: String,
+    pub file_name: String,
+    pub file_date: DateTime<Utc>,
+    pub file_length: u64,
+    /// Missing if the author doesn't allow third party downloads
+    pub download_url: Option<String>,
+    #[serde(default)]
+    pub hashes: Vec<FileHash>,
+    #[serde(default)]
+    pub dependencies: Vec<FileDependency>,
+    #[serde(default)]
+    pub game_versions: Vec<String>,
+    pub file_fingerprint: u32,
+}
+
+impl File {
+    pub fn sha1(&self) -> Option<&str> {
+        self.hashes
+            .iter()
+            .find(|it| it.algo == 1)
+            .map(|it| it.value.as_str())
+    }
+
+    /// CurseForge files don't have version numbers, so the version is parsed out of the file name.
+    pub fn version(&self) -> String {
+        VersionTuple::parse(&self.file_name)
+            .map(|it| it.version)
+            .filter(|it| it != "0.0.0")
+            .unwrap_or_else(|| self.display_name.clone())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct FileHash {
+    pub value: String,
+    /// 1 is sha1, 2 is md5
+    pub algo: u8,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileDependency {
+    pub mod_id: u64,
+    pub relation_type: RelationType,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(from = "u8")]
+pub enum RelationType {
+    EmbeddedLibrary,
+    OptionalDependency,
+    RequiredDependency,
+    Tool,
+    Incompatible,
+    Include,
+    Unknown,
+}
+
+impl From<u8> for RelationType {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Self::EmbeddedLibrary,
+            2 => Self::OptionalDependency,
+            3 => Self::RequiredDependency,
+            4 => Self::Tool,
+            5 => Self::Incompatible,
+            6 => Self::Include,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FingerprintMatch {
+    pub id: u64,
+    pub file: File,
+}
+
+#[derive(Deserialize)]
+struct Response<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct Paginated<T> {
+    data: Vec<T>,
+    pagination: Pagination,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Pagination {
+    total_count: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FingerprintMatches {
+    exact_matches: Vec<FingerprintMatch>,
+}
+
+/// CurseForge's fingerprint of a file: murmur2 with a seed of 1, over the file's bytes with all
+/// whitespace removed.
+pub fn fingerprint(bytes: &[u8]) -> u32 {
+    const M: u32 = 0x5bd1_e995;
+    let data = bytes
+        .iter()
+        .copied()
+        .filter(|it| !matches!(it, b'\t' | b'\n' | b'\r' | b' '))
+        .collect::<Vec<_>>();
+
+    let mut hash = 1 ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> 24;
+        k = k.wrapping_mul(M);
+        hash = hash.wrapping_mul(M) ^ k;
+    }
+    let remainder = chunks.remainder();
+    if !remainder.is_empty() {
+        for (i, byte) in remainder.iter().enumerate() {
+            hash ^= (*byte as u32) << (8 * i);
+        }
+        hash = hash.wrapping_mul(M);
+    }
+
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(M);
+    hash ^ (hash >> 15)
+}
+
+/// CurseForge's `modLoaderType` for a Modrinth-style loader name.
+fn mod_loader_type(loader: &str) -> Option<u8> {
+    match loader {
+        "forge" => Some(1),
+        "fabric" => Some(4),
+        "quilt" => Some(5),
+        "neoforge" => Some(6),
+        _ => None,
+    }
+}
+
+pub struct CurseForge {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl CurseForge {
+    pub fn new(config: &CurseForgeConfig) -> Result<Self> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("x-api-key", config.api_key.parse()?);
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .user_agent(concat!("startmc/", env!("CARGO_PKG_VERSION")))
+                .default_headers(headers)
+                .build()?,
+            base_url: config
+                .base_url
+                .as_deref()
+                .unwrap_or(DEFAULT_BASE_URL)
+                .trim_end_matches('/')
+                .to_string(),
+        })
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<T> {
+        Ok(self
+            .client
+            .get(format!("{}{path}", self.base_url))
+            .query(query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    async fn post<T: DeserializeOwned>(&self, path: &str, body: serde_json::Value) -> Result<T> {
+        Ok(self
+            .client
+            .post(format!("{}{path}", self.base_url))
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    /// Search for content compatible with `game_version` and `loader`, only of `kind` if given.
+    pub async fn search_mods(
+        &self,
+        query: &str,
+        sort: SearchSort,
+        kind: Option<IndexEntryKind>,
+        game_version: &str,
+        loader: Option<&str>,
+    ) -> Result<Vec<Mod>> {
+        let sort_field = match sort {
+            SearchSort::Relevance | SearchSort::Follows => 2,
+            SearchSort::Downloads => 6,
+            SearchSort::Newest => 11,
+            SearchSort::Updated => 3,
+        };
+        let mut params = vec![
+            ("gameId", MINECRAFT_GAME_ID.to_string()),
+            ("searchFilter", query.to_string()),
+            ("gameVersion", game_version.to_string()),
+            ("sortField", sort_field.to_string()),
+            ("sortOrder", "desc".to_string()),
+        ];
+        if let Some(kind) = kind {
+            let class_id = match kind {
+                IndexEntryKind::Mod => CLASS_MODS,
+                IndexEntryKind::Resourcepack => CLASS_RESOURCEPACKS,
+                IndexEntryKind::Shader => CLASS_SHADERS,
+                IndexEntryKind::Datapack => CLASS_DATAPACKS,
+            };
+            params.push(("classId", class_id.to_string()));
+        }
+        // only mods are tagged with a loader
+        if let Some(loader) = loader.and_then(mod_loader_type)
+            && kind.is_none_or(|it| it == IndexEntryKind::Mod)
+        {
+            params.push(("modLoaderType", loader.to_string()));
+        }
+        let res: Response<Vec<Mod>> = self.get("/v1/mods/search", &params).await?;
+        Ok(res
+            .data
+            .into_iter()
+            .filter(|it| it.kind().is_some())
+            .collect())
+    }
+
+    /// Look up a mod or resourcepack by its slug or numeric id.
+    pub async fn find_mod(&self, name: &str) -> Result<Mod> {
+        if let Ok(id) = name.parse::<u64>() {
+            return self.get_mod(id).await;
+        }
+        let res: Response<Vec<Mod>> = self
+            .get(
+                "/v1/mods/search",
+                &[
+                    ("gameId", MINECRAFT_GAME_ID.to_string()),
+                    ("slug", name.to_string()),
+                ],
+            )
+            .await?;
+        res.data
+            .into_iter()
+            .find(|it| it.slug == name && it.kind().is_some())
+            .with_context(|| format!("{name} was not found on CurseForge"))
+    }
+
+    pub async fn get_mod(&self, id: u64) -> Result<Mod> {
+        let res: Response<Mod> = self.get(&format!("/v1/mods/{id}"), &[]).await?;
+        Ok(res.data)
+    }
+
+    pub async fn get_mods(&self, ids: &[u64]) -> Result<Vec<Mod>> {
+        let res: Response<Vec<Mod>> = self
+            .post("/v1/mods", serde_json::json!({ "modIds": ids }))
+            .await?;
+        Ok(res.data)
+    }
+
+    /// List all files of a mod for `game_version` and, if given, `loader`.
+    pub async fn get_files(
+        &self,
+        mod_id: u64,
+        game_version: &str,
+        loader: Option<&str>,
+    ) -> Result<Vec<File>> {
+        let mut files = vec![];
+        loop {
+            let mut params = vec![
+                ("gameVersion", game_version.to_string()),
+                ("index", files.len().to_string()),
+                ("pageSize", PAGE_SIZE.to_string()),
+            ];
+            if let Some(loader) = loader.and_then(mod_loader_type) {
+                params.push(("modLoaderType", loader.to_string()));
+            }
+            let page: Paginated<File> = self
+                .get(&format!("/v1/mods/{mod_id}/files"), &params)
+                .await?;
+            let done = page.data.is_empty();
+            files.extend(page.data);
+            if done || files.len() >= page.pagination.total_count {
+                return Ok(files);
+            }
+        }
+    }
+
+    pub async fn get_files_by_id(&self, ids: &[u64]) -> Result<Vec<File>> {
+        let res: Response<Vec<File>> = self
+            .post("/v1/mods/files", serde_json::json!({ "fileIds": ids }))
+            .await?;
+        Ok(res.data)
+    }
+
+    /// Identify files by their [`fingerprint`]s.
+    pub async fn fingerprint_matches(&self, fingerprints: &[u32]) -> Result<Vec<FingerprintMatch>> {
+        let res: Response<FingerprintMatches> = self
+            .post(
+                &format!("/v1/fingerprints/{MINECRAFT_GAME_ID}"),
+                serde_json::json!({ "fingerprints": fingerprints }),
+            )
+            .await?;
+        Ok(res.data.exact_matches)
+    }
+}
+
+impl From<Mod> for Project {
+    fn from(project: Mod) -> Self {
+        Self {
+            kind: project.kind(),
+            id: project.id.to_string(),
+            slug: project.slug,
+            title: project.name,
+            description: project.summary,
+            downloads: project.download_count,
+            follows: None,
+            updated: project.date_modified,
+        }
+    }
+}
+
+impl From<File> for ProjectVersion {
+    fn from(file: File) -> Self {
+        let version_number = file.version();
+        let sha1 = file.sha1().map(|it| it.to_string());
+        // CurseForge lists loaders among the game versions, e.g. ["1.20.1", "Fabric"]
+        let (game_versions, loaders) = file
+            .game_versions
+            .into_iter()
+            .partition(|it| it.starts_with(|c: char| c.is_ascii_digit()));
+        Self {
+            id: file.id.to_string(),
+            project_id: file.mod_id.to_string(),
+            version_number,
+            date_published: file.file_date,
+            game_versions,
+            loaders: loaders
+                .into_iter()
+                .map(|it: String| it.to_lowercase())
+                .collect(),
+            files: vec![VersionFile {
+                filename: file.file_name,
+                url: file.download_url,
+                size: file.file_length,
+                sha1,
+                sha512: None,
+                primary: true,
+            }],
+            dependencies: file
+                .dependencies
+                .into_iter()
+                .filter_map(|it| {
+                    let kind = match it.relation_type {
+                        RelationType::RequiredDependency => DependencyKind::Required,
+                        RelationType::OptionalDependency => DependencyKind::Optional,
+                        RelationType::Incompatible => DependencyKind::Incompatible,
+                        RelationType::EmbeddedLibrary | RelationType::Include => {
+                            DependencyKind::Embedded
+                        }
+                        RelationType::Tool | RelationType::Unknown => return None,
+                    };
+                    Some(Dependency {
+                        project_id: Some(it.mod_id.to_string()),
+                        version_id: None,
+                        kind,
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+fn parse_id(id: &str) -> Result<u64> {
+    id.parse().map_err(|_| eyre!("invalid CurseForge id: {id}"))
+}
+
+#[async_trait]
+impl ContentProvider for CurseForge {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::CurseForge
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        sort: SearchSort,
+        kind: Option<IndexEntryKind>,
+        game_version: &str,
+        loader: Option<&str>,
+    ) -> Result<Vec<Project>> {
+        Ok(self
+            .search_mods(query, sort, kind, game_version, loader)
+            .await?
+            .into_iter()
+            .map(Project::from)
+            .collect())
+    }
+
+    async fn project(&self, id: &str) -> Result<Project> {
+        Ok(self.find_mod(id).await?.into())
+    }
+
+    async fn projects(&self, ids: &[&str]) -> Result<Vec<Project>> {
+        let ids = ids
+            .iter()
+            .map(|it| parse_id(it))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(self
+            .get_mods(&ids)
+            .await?
+            .into_iter()
+            .map(Project::from)
+            .collect())
+    }
+
+    async fn versions(
+        &self,
+        project_id: &str,
+        kind: IndexEntryKind,
+        game_version: &str,
+        loader: Option<&str>,
+    ) -> Result<Vec<ProjectVersion>> {
+        let loaders = match kind {
+            IndexEntryKind::Mod => loader.map(compatible_loaders),
+            IndexEntryKind::Resourcepack | IndexEntryKind::Shader | IndexEntryKind::Datapack => {
+                None
+            }
+        };
+        // files can only be filtered by one loader, so the others are filtered here
+        let loader = match loaders.as_deref() {
+            Some([loader]) => Some(*loader),
+            _ => None,
+        };
+        Ok(self
+            .get_files(parse_id(project_id)?, game_version, loader)
+            .await?
+            .into_iter()
+            .map(ProjectVersion::from)
+            .filter(|it| {
+                loaders.as_ref().is_none_or(|loaders| {
+                    it.loaders
+                        .iter()
+                        .any(|loader| loaders.contains(&loader.as_str()))
+                })
+            })
+            .collect())
+    }
+
+    async fn version(&self, id: &str) -> Result<ProjectVersion> {
+        self.versions_by_id(&[id])
+            .await?
+            .pop()
+            .with_context(|| format!("file {id} was not found on CurseForge"))
+    }
+
+    async fn versions_by_id(&self, ids: &[&str]) -> Result<Vec<ProjectVersion>> {
+        let ids = ids
+            .iter()
+            .map(|it| parse_id(it))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(self
+            .get_files_by_id(&ids)
+            .await?
+            .into_iter()
+            .map(ProjectVersion::from)
+            .collect())
+    }
+
+    async fn identify(&self, files: &[&FileHashes]) -> Result<HashMap<String, ProjectVersion>> {
+        let fingerprints = files.iter().map(|it| it.fingerprint).collect::<Vec<_>>();
+        let matches = self.fingerprint_matches(&fingerprints).await?;
+        Ok(matches
+            .into_iter()
+            .filter_map(|it| {
+                let hashes = files
+                    .iter()
+                    .find(|hashes| hashes.fingerprint == it.file.file_fingerprint)?;
+                Some((hashes.sha1.clone(), it.file.into()))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Serve `body` as JSON to every request, returning the base URL of the server.
+    async fn mock_server(body: serde_json::Value) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 4096];
+                let _ = socket.read(&mut buf).await.unwrap();
+                let body = body.to_string();
+                let res = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                socket.write_all(res.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{addr}")
+    }
+
+    fn file(id: u64, name: &str, date: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "modId": 238222,
+            "displayName": name,
+            "fileName": name,
+            "fileDate": date,
+            "fileLength": 1024,
+            "downloadUrl": format!("https://edge.forgecdn.net/files/{id}/{name}"),
+            "hashes": [{ "value": "da39a3ee5e6b4b0d3255bfef95601890afd80709", "algo": 1 }],
+            "dependencies": [{ "modId": 306612, "relationType": 3 }],
+            "gameVersions": ["1.20.1", "Fabric"],
+            "fileFingerprint": 1234,
+        })
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        assert_eq!(fingerprint(b""), 1540447798);
+        assert_eq!(fingerprint(b"startmc"), fingerprint(b"start mc\r\n"));
+        assert_ne!(fingerprint(b"startmc"), fingerprint(b"startmd"));
+    }
+
+    #[test]
+    fn test_file_page() {
+        assert_eq!(
+            file_page(IndexEntryKind::Mod, "jei", "4644453"),
+            "https://www.curseforge.com/minecraft/mc-mods/jei/files/4644453"
+        );
+        assert_eq!(
+            file_page(IndexEntryKind::Resourcepack, "faithful-32x", "1"),
+            "https://www.curseforge.com/minecraft/texture-packs/faithful-32x/files/1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_files() {
+        let base_url = mock_server(serde_json::json!({
+            "data": [
+                file(1, "jei-1.20.1-fabric-15.2.0.27.jar", "2023-10-01T00:00:00Z"),
+                file(2, "jei-1.20.1-fabric-15.3.0.4.jar", "2024-01-01T00:00:00Z"),
+            ],
+            "pagination": { "index": 0, "pageSize": 50, "resultCount": 2, "totalCount": 2 },
+        }))
+        .await;
+        let curseforge = CurseForge::new(&CurseForgeConfig {
+            api_key: "key".to_string(),
+            base_url: Some(base_url),
+        })
+        .unwrap();
+
+        let files = curseforge
+            .get_files(238222, "1.20.1", Some("fabric"))
+            .await
+            .unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(
+            files[0].sha1(),
+            Some("da39a3ee5e6b4b0d3255bfef95601890afd80709")
+        );
+        assert_eq!(
+            files[0].dependencies[0].relation_type,
+            RelationType::RequiredDependency
+        );
+
+        let version = ProjectVersion::from(files[1].clone());
+        assert_eq!(version.id, "2");
+        assert_eq!(version.version_number, "1.20.1-fabric-15.3.0.4");
+        assert_eq!(version.game_versions, vec!["1.20.1"]);
+        assert_eq!(version.loaders, vec!["fabric"]);
+        assert_eq!(version.dependencies[0].kind, DependencyKind::Required);
+    }
+
+    #[tokio::test]
+    async fn test_versions_for_quilt() {
+        let mut quilt = file(2, "jei-1.20.1-quilt-15.3.0.4.jar", "2024-01-01T00:00:00Z");
+        quilt["gameVersions"] = serde_json::json!(["1.20.1", "Quilt"]);
+        let mut forge = file(3, "jei-1.20.1-forge-15.3.0.4.jar", "2024-01-01T00:00:00Z");
+        forge["gameVersions"] = serde_json::json!(["1.20.1", "Forge"]);
+        let base_url = mock_server(serde_json::json!({
+            "data": [
+                file(1, "jei-1.20.1-fabric-15.3.0.4.jar", "2024-01-01T00:00:00Z"),
+                quilt,
+                forge,
+            ],
+            "pagination": { "index": 0, "pageSize": 50, "resultCount": 3, "totalCount": 3 },
+        }))
+        .await;
+        let curseforge = CurseForge::new(&CurseForgeConfig {
+            api_key: "key".to_string(),
+            base_url: Some(base_url),
+        })
+        .unwrap();
+
+        // Quilt runs Fabric mods too
+        let versions = curseforge
+            .versions("238222", IndexEntryKind::Mod, "1.20.1", Some("quilt"))
+            .await
+            .unwrap();
+        assert_eq!(
+            versions.iter().map(|it| it.id.as_str()).collect::<Vec<_>>(),
+            vec!["1", "2"]
+        );
+    }
+}
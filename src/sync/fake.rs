@@ -0,0 +1,124 @@
+//! An in-memory [`ContentProvider`], so that sync can be tested without the network.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use color_eyre::{Result, eyre::ContextCompat};
+
+use super::{
+    ContentProvider, FileHashes, IndexEntryKind, Project, ProjectVersion, ProviderKind, SearchSort,
+};
+
+pub struct FakeProvider {
+    pub kind: ProviderKind,
+    pub projects: Vec<Project>,
+    pub versions: Vec<ProjectVersion>,
+    /// SHA-1 hashes of every file [`ContentProvider::identify`] was asked about
+    pub asked: Arc<Mutex<Vec<String>>>,
+}
+
+impl FakeProvider {
+    pub fn new(projects: Vec<Project>, versions: Vec<ProjectVersion>) -> Self {
+        Self {
+            kind: ProviderKind::Modrinth,
+            projects,
+            versions,
+            asked: Default::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl ContentProvider for FakeProvider {
+    fn kind(&self) -> ProviderKind {
+        self.kind
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        _sort: SearchSort,
+        kind: Option<IndexEntryKind>,
+        _game_version: &str,
+        _loader: Option<&str>,
+    ) -> Result<Vec<Project>> {
+        Ok(self
+            .projects
+            .iter()
+            .filter(|it| it.slug.contains(query) || it.title.contains(query))
+            .filter(|it| kind.is_none_or(|kind| it.kind == Some(kind)))
+            .cloned()
+            .collect())
+    }
+
+    async fn project(&self, id: &str) -> Result<Project> {
+        self.projects
+            .iter()
+            .find(|it| it.id == id || it.slug == id)
+            .cloned()
+            .with_context(|| format!("project {id} not found"))
+    }
+
+    async fn projects(&self, ids: &[&str]) -> Result<Vec<Project>> {
+        Ok(self
+            .projects
+            .iter()
+            .filter(|it| ids.contains(&it.id.as_str()))
+            .cloned()
+            .collect())
+    }
+
+    async fn versions(
+        &self,
+        project_id: &str,
+        _kind: IndexEntryKind,
+        game_version: &str,
+        loader: Option<&str>,
+    ) -> Result<Vec<ProjectVersion>> {
+        Ok(self
+            .versions
+            .iter()
+            .filter(|it| it.project_id == project_id)
+            .filter(|it| it.game_versions.iter().any(|it| it == game_version))
+            .filter(|it| loader.is_none_or(|loader| it.loaders.iter().any(|it| it == loader)))
+            .cloned()
+            .collect())
+    }
+
+    async fn version(&self, id: &str) -> Result<ProjectVersion> {
+        self.versions
+            .iter()
+            .find(|it| it.id == id)
+            .cloned()
+            .with_context(|| format!("version {id} not found"))
+    }
+
+    async fn versions_by_id(&self, ids: &[&str]) -> Result<Vec<ProjectVersion>> {
+        Ok(self
+            .versions
+            .iter()
+            .filter(|it| ids.contains(&it.id.as_str()))
+            .cloned()
+            .collect())
+    }
+
+    async fn identify(&self, files: &[&FileHashes]) -> Result<HashMap<String, ProjectVersion>> {
+        let mut found = HashMap::new();
+        for hashes in files {
+            self.asked.lock().unwrap().push(hashes.sha1.clone());
+            let version = self.versions.iter().find(|version| {
+                version
+                    .files
+                    .iter()
+                    .any(|it| it.sha1.as_ref() == Some(&hashes.sha1))
+            });
+            if let Some(version) = version {
+                found.insert(hashes.sha1.clone(), version.clone());
+            }
+        }
+        Ok(found)
+    }
+}
@@ -8,6 +8,20 @@ pub enum VersionFilter {
     Op(String, version_compare::Cmp),
 }
 
+impl VersionFilter {
+    /// Check whether `version` satisfies this filter.
+    pub fn matches(&self, version: &str) -> bool {
+        match self {
+            VersionFilter::Any => true,
+            VersionFilter::Op(expected, Cmp::Eq) => version == expected,
+            VersionFilter::Op(expected, op) => matches!(
+                version_compare::compare_to(version, expected.as_str(), *op),
+                Ok(true)
+            ),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct SyncFilter {
     pub name: String,
@@ -175,6 +189,16 @@ mod tests {
         assert_eq!(SyncFilter::from_str("=*"), Err("Missing identifier"));
     }
 
+    #[test]
+    fn test_version_matches() {
+        let ge = VersionFilter::Op("0.5.0".to_string(), Cmp::Ge);
+        assert!(ge.matches("0.5.3"));
+        assert!(!ge.matches("0.4.9"));
+        let eq = VersionFilter::Op("mc1.20.1-0.5.3".to_string(), Cmp::Eq);
+        assert!(eq.matches("mc1.20.1-0.5.3"));
+        assert!(VersionFilter::Any.matches("anything"));
+    }
+
     #[test]
     fn test_missing_version() {
         assert_eq!(
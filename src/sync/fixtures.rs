@@ -0,0 +1,103 @@
+//! Projects, versions and index entries for tests, so that sync logic can be tested without the network.
+
+use super::{fake::FakeProvider, *};
+
+pub fn project(id: &str, slug: &str, kind: IndexEntryKind) -> Project {
+    Project {
+        id: id.to_string(),
+        slug: slug.to_string(),
+        title: slug.to_string(),
+        description: String::new(),
+        kind: Some(kind),
+        downloads: 0,
+        follows: Some(0),
+        updated: "2023-01-01T00:00:00Z".parse().unwrap(),
+    }
+}
+
+/// A version of `project_id` for Minecraft 1.20.1 and Fabric with one primary file, and
+/// `dependencies` given as `(project_id, dependency_type)` pairs.
+pub fn version(
+    project_id: &str,
+    number: &str,
+    published: &str,
+    dependencies: &[(&str, &str)],
+) -> ProjectVersion {
+    ProjectVersion {
+        id: format!("{project_id}-{number}"),
+        project_id: project_id.to_string(),
+        version_number: number.to_string(),
+        date_published: published.parse().unwrap(),
+        game_versions: vec!["1.20.1".to_string()],
+        loaders: vec!["fabric".to_string()],
+        files: vec![VersionFile {
+            filename: format!("{project_id}-{number}.jar"),
+            url: Some(format!(
+                "https://cdn.modrinth.com/data/{project_id}/{number}.jar"
+            )),
+            size: 1024,
+            sha1: Some(format!("{project_id}-{number}-sha1")),
+            sha512: None,
+            primary: true,
+        }],
+        dependencies: dependencies
+            .iter()
+            .map(|(project_id, kind)| Dependency {
+                project_id: Some(project_id.to_string()),
+                version_id: None,
+                kind: match *kind {
+                    "required" => DependencyKind::Required,
+                    "optional" => DependencyKind::Optional,
+                    "incompatible" => DependencyKind::Incompatible,
+                    "embedded" => DependencyKind::Embedded,
+                    _ => panic!("unknown dependency type: {kind}"),
+                },
+            })
+            .collect(),
+    }
+}
+
+pub fn resolved(project_id: &str, slug: &str, version: ProjectVersion) -> ResolvedPackage {
+    ResolvedPackage {
+        provider: ProviderKind::Modrinth,
+        project: project(project_id, slug, IndexEntryKind::Mod),
+        version,
+        kind: IndexEntryKind::Mod,
+    }
+}
+
+pub fn entry(id: &str, version: &str, file: &str, modrinth: bool) -> SyncIndexEntry {
+    SyncIndexEntry {
+        id: id.to_string(),
+        version: version.to_string(),
+        provider: modrinth.then_some(ProviderKind::Modrinth),
+        project_id: modrinth.then(|| format!("{id}-project")),
+        version_id: modrinth.then(|| format!("{id}-project-{version}")),
+        file: file.to_string(),
+        disabled: file.ends_with(".disabled"),
+        kind: IndexEntryKind::Mod,
+        dir: None,
+        held: false,
+        sha1: None,
+        sha512: None,
+        size: None,
+        url: None,
+        metadata: None,
+    }
+}
+
+/// A Fabric 1.20.1 instance in `minecraft_directory`, whose only provider is `provider`.
+pub fn sync(index: SyncIndex, provider: FakeProvider, minecraft_directory: PathBuf) -> Sync {
+    Sync {
+        providers: vec![Box::new(provider)],
+        index,
+        game_version: "1.20.1".to_string(),
+        loader: Some("fabric".to_string()),
+        ignore: vec![],
+        datapacks: DEFAULT_DATAPACKS_DIR.to_string(),
+        kind: None,
+        hash_cache: None,
+        transaction: Transaction::untracked(&minecraft_directory),
+        minecraft_directory,
+    }
+}
@@ -0,0 +1,119 @@
+//! Hashes and metadata of content files from the last refresh, so that refreshing only reads
+//! files that changed since.
+
+use std::{
+    collections::HashMap,
+    fs::Metadata,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{ContentMetadata, FileHashes, ProviderKind};
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct HashCache {
+    #[serde(default)]
+    files: HashMap<PathBuf, CachedFile>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct CachedFile {
+    size: u64,
+    /// Modification time in nanoseconds since the Unix epoch
+    modified: u64,
+    hashes: FileHashes,
+    metadata: Option<ContentMetadata>,
+    /// Providers that didn't know the file when they were last asked
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    unknown_to: Vec<ProviderKind>,
+}
+
+/// Size and modification time of a file, which tell whether it changed.
+fn stamp(stat: &Metadata) -> Option<(u64, u64)> {
+    let modified = stat.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((stat.len(), modified.as_nanos() as u64))
+}
+
+impl HashCache {
+    /// Where the cache of the instance in `minecraft_directory` is kept, in the user's cache
+    /// directory so that it doesn't end up next to the lock file.
+    pub fn path(minecraft_directory: &Path) -> PathBuf {
+        let key = super::instance_key(minecraft_directory);
+        dirs::cache_dir()
+            .expect("cache directory not found")
+            .join("startmc")
+            .join("hashes")
+            .join(format!("{key}.json"))
+    }
+
+    /// Read the cache at `path`. A missing or broken cache is an empty one.
+    pub fn read(path: &Path) -> Self {
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&contents).unwrap_or_else(|err| {
+            debug!("ignoring broken hash cache {}: {err}", path.display());
+            Self::default()
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// The hashes and metadata of the file at `path`, if it didn't change since they were cached.
+    pub fn get(
+        &self,
+        path: &Path,
+        stat: &Metadata,
+    ) -> Option<(FileHashes, Option<ContentMetadata>)> {
+        let cached = self.files.get(path)?;
+        (stamp(stat)? == (cached.size, cached.modified))
+            .then(|| (cached.hashes.clone(), cached.metadata.clone()))
+    }
+
+    pub fn insert(
+        &mut self,
+        path: PathBuf,
+        stat: &Metadata,
+        hashes: FileHashes,
+        metadata: Option<ContentMetadata>,
+    ) {
+        if let Some((size, modified)) = stamp(stat) {
+            self.files.insert(
+                path,
+                CachedFile {
+                    size,
+                    modified,
+                    hashes,
+                    metadata,
+                    unknown_to: vec![],
+                },
+            );
+        }
+    }
+
+    /// The providers that didn't know the file at `path` when they were last asked, if it
+    /// didn't change since.
+    pub fn unknown_to(&self, path: &Path, stat: &Metadata) -> Vec<ProviderKind> {
+        self.files
+            .get(path)
+            .filter(|cached| stamp(stat) == Some((cached.size, cached.modified)))
+            .map(|cached| cached.unknown_to.clone())
+            .unwrap_or_default()
+    }
+
+    /// Record that none of `providers` knew the file at `path`.
+    pub fn set_unknown_to(&mut self, path: &Path, providers: Vec<ProviderKind>) {
+        if let Some(cached) = self.files.get_mut(path) {
+            cached.unknown_to = providers;
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use color_eyre::{
+    Result,
+    eyre::{ContextCompat, bail},
+};
+use reqwest::Url;
+use startmc_downloader::{Download, DownloaderBuilder, Status};
+
+use super::{
+    ContentMetadata, FileHashes, IndexEntryKind, Project, ProjectVersion, ProviderKind, Sync,
+    SyncFilter, SyncIndexEntry, VersionFile, VersionFilter,
+};
+
+/// A project resolved to a concrete version that can be installed onto the instance.
+#[derive(Debug, Clone)]
+pub struct ResolvedPackage {
+    pub provider: ProviderKind,
+    pub project: Project,
+    pub version: ProjectVersion,
+    pub kind: IndexEntryKind,
+}
+
+impl ResolvedPackage {
+    /// The file that will be downloaded, i.e. the primary file of the version, or the first one if
+    /// there are no primary files.
+    pub fn file(&self) -> &VersionFile {
+        self.version
+            .files
+            .iter()
+            .find(|it| it.primary)
+            .unwrap_or(&self.version.files[0])
+    }
+
+    pub fn to_entry(&self) -> SyncIndexEntry {
+        let file = self.file();
+        SyncIndexEntry {
+            id: self.project.slug.clone(),
+            version: self.version.version_number.clone(),
+            provider: Some(self.provider),
+            project_id: Some(self.project.id.clone()),
+            version_id: Some(self.version.id.clone()),
+            file: file.filename.clone(),
+            disabled: false,
+            kind: self.kind,
+            dir: None,
+            held: false,
+            sha1: file.sha1.clone(),
+            sha512: file.sha512.clone(),
+            size: Some(file.size),
+            url: file.url.clone(),
+            metadata: None,
+        }
+    }
+}
+
+impl std::fmt::Display for ResolvedPackage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.project.slug, self.version.version_number)
+    }
+}
+
+/// Pick the newest version out of `versions` that matches `filter`.
+pub fn newest_matching(
+    versions: Vec<ProjectVersion>,
+    filter: Option<&VersionFilter>,
+) -> Option<ProjectVersion> {
+    versions
+        .into_iter()
+        .filter(|it| !it.files.is_empty())
+        .filter(|it| filter.is_none_or(|filter| filter.matches(&it.version_number)))
+        .max_by_key(|it| it.date_published)
+}
+
+/// Resolving and installing content
+impl Sync {
+    /// The loader that content of `kind` must support to run on this instance, if any.
+    pub fn loader_for(&self, kind: IndexEntryKind) -> Result<Option<&str>> {
+        Ok(match kind {
+            IndexEntryKind::Mod => Some(
+                self.loader
+                    .as_deref()
+                    .context("cannot install mods on an instance without a mod loader")?,
+            ),
+            IndexEntryKind::Resourcepack | IndexEntryKind::Shader | IndexEntryKind::Datapack => {
+                None
+            }
+        })
+    }
+
+    /// List the versions of `project` that are compatible with the instance's game version and loader.
+    pub async fn compatible_versions(
+        &self,
+        provider: ProviderKind,
+        project_id: &str,
+        kind: IndexEntryKind,
+    ) -> Result<Vec<ProjectVersion>> {
+        self.provider(provider)?
+            .versions(project_id, kind, &self.game_version, self.loader_for(kind)?)
+            .await
+    }
+
+    /// Resolve a project of `provider` to the newest version compatible with the instance that
+    /// also satisfies `filter`.
+    pub async fn resolve_project(
+        &self,
+        provider: ProviderKind,
+        project: Project,
+        filter: Option<&VersionFilter>,
+    ) -> Result<ResolvedPackage> {
+        let kind = project
+            .kind
+            .with_context(|| format!("{}: unsupported project type", project.slug))?;
+        let versions = self
+            .compatible_versions(provider, &project.id, kind)
+            .await?;
+        let version = newest_matching(versions, filter).with_context(|| {
+            format!(
+                "no version of {} is compatible with Minecraft {}{}",
+                project.slug,
+                self.game_version,
+                self.loader
+                    .as_deref()
+                    .map(|it| format!(" and {it}"))
+                    .unwrap_or_default()
+            )
+        })?;
+        Ok(ResolvedPackage {
+            provider,
+            project,
+            version,
+            kind,
+        })
+    }
+
+    /// Resolve a [`SyncFilter`], picking the newest version compatible with the instance that
+    /// also satisfies the filter's version operator. The provider is picked by the filter's
+    /// prefix, see [`ProviderKind::split`].
+    pub async fn resolve(&self, filter: &SyncFilter) -> Result<ResolvedPackage> {
+        let (provider, name) = ProviderKind::split(&filter.name);
+        let mut project = self.provider(provider)?.project(name).await?;
+        if let Some(kind) = self.kind {
+            project.kind = Some(kind);
+        }
+        self.resolve_project(provider, project, filter.version.as_ref())
+            .await
+    }
+
+    /// Download resolved packages into their namespace directories and record them in the index.
+    ///
+    /// If a package from the same project is already installed, it is replaced, and stays
+    /// disabled or held if it was.
+    pub async fn install(&mut self, packages: &[ResolvedPackage]) -> Result<()> {
+        self.install_entries(
+            packages
+                .iter()
+                .map(|it| {
+                    let mut entry = it.to_entry();
+                    entry.dir = self.dir_for(&entry);
+                    (entry, it.to_string())
+                })
+                .collect(),
+        )
+        .await
+    }
+
+    /// Where a new entry goes if not into the default directory for its kind. Upgrades stay in
+    /// the directory of the package they replace, and new datapacks go to [`Sync::datapacks`].
+    fn dir_for(&self, entry: &SyncIndexEntry) -> Option<String> {
+        if let Some(installed) = self.index.packages.iter().find(|it| it.same_project(entry)) {
+            return installed.dir.clone();
+        }
+        (entry.kind == IndexEntryKind::Datapack && self.datapacks != entry.kind.dir())
+            .then(|| self.datapacks.clone())
+    }
+
+    /// Download index entries from their URLs, named after the second item of each pair, and
+    /// record them in the index, replacing packages from the same project.
+    pub(super) async fn install_entries(
+        &mut self,
+        entries: Vec<(SyncIndexEntry, String)>,
+    ) -> Result<()> {
+        let mut queue = vec![];
+        for (entry, name) in &entries {
+            let path = entry
+                .get_namespace(&self.minecraft_directory)
+                .join(&entry.file);
+            let url = entry.url.as_deref().with_context(|| {
+                format!("{name} can't be downloaded by launchers, download it manually")
+            })?;
+            queue.push(Download::new(
+                &Url::parse(url)?,
+                path.to_str().unwrap(),
+                Some(name.clone()),
+            ));
+        }
+        for (entry, _) in &entries {
+            let path = entry
+                .get_namespace(&self.minecraft_directory)
+                .join(&entry.file);
+            self.transaction.create(&path)?;
+        }
+
+        let downloader = DownloaderBuilder::new().concurrent_downloads(10).build();
+        let summaries = downloader.download(&queue).await;
+        for summary in &summaries {
+            if let Status::Fail(err) = summary.status() {
+                bail!("failed to download {}: {err}", summary.download().url);
+            }
+        }
+
+        for (mut entry, name) in entries {
+            let path = entry
+                .get_namespace(&self.minecraft_directory)
+                .join(&entry.file);
+            let hashes = FileHashes::read(&path)?;
+            if !entry.verify(&hashes)? {
+                std::fs::remove_file(&path)?;
+                bail!(
+                    "hash mismatch for {name}, downloaded from {}",
+                    entry.url.as_deref().unwrap()
+                );
+            }
+            entry.set_hashes(&hashes);
+            entry.metadata = ContentMetadata::read(&path, entry.kind)
+                .inspect_err(|err| warn!("can't read metadata of {name}: {err}"))
+                .ok()
+                .flatten();
+            match self
+                .index
+                .packages
+                .iter()
+                .position(|it| it.same_project(&entry))
+            {
+                Some(i) => {
+                    let old = std::mem::replace(&mut self.index.packages[i], entry);
+                    self.index.packages[i].held = old.held;
+                    if old.file != self.index.packages[i].file {
+                        old.remove_from_fs(&mut self.transaction)?;
+                    }
+                    if old.disabled {
+                        self.index.packages[i].disable_and_move(&mut self.transaction)?;
+                    }
+                }
+                None => self.index.packages.push(entry),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use version_compare::Cmp;
+
+    use super::{super::fixtures::version, *};
+
+    #[test]
+    fn test_newest_matching() {
+        let versions = vec![
+            version("sodium", "0.5.0", "2023-06-01T00:00:00Z", &[]),
+            version("sodium", "0.5.3", "2023-09-01T00:00:00Z", &[]),
+            version("sodium", "0.4.10", "2023-03-01T00:00:00Z", &[]),
+        ];
+        assert_eq!(
+            newest_matching(versions.clone(), None)
+                .unwrap()
+                .version_number,
+            "0.5.3"
+        );
+        let filter = VersionFilter::Op("0.5.3".to_string(), Cmp::Lt);
+        assert_eq!(
+            newest_matching(versions.clone(), Some(&filter))
+                .unwrap()
+                .version_number,
+            "0.5.0"
+        );
+        let filter = VersionFilter::Op("1.0.0".to_string(), Cmp::Ge);
+        assert!(newest_matching(versions, Some(&filter)).is_none());
+    }
+}
@@ -0,0 +1,276 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::{
+    Result,
+    eyre::{Context, bail, eyre},
+};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha1_smol::Sha1;
+use sha2::{Digest, Sha512};
+use startmc_downloader::{Download, DownloaderBuilder, Status};
+
+use super::{IndexEntryKind, Sync, SyncIndex, SyncIndexEntry};
+
+/// Hashes and size of a file, as recorded in the lock file.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileHashes {
+    pub sha1: String,
+    pub sha512: String,
+    pub size: u64,
+    /// CurseForge's murmur2 fingerprint, which it identifies files by
+    pub fingerprint: u32,
+}
+
+impl FileHashes {
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            sha1: Sha1::from(bytes).hexdigest(),
+            sha512: format!("{:x}", Sha512::digest(bytes)),
+            size: bytes.len() as u64,
+            fingerprint: super::curseforge::fingerprint(bytes),
+        }
+    }
+
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        Ok(Self::from_bytes(&std::fs::read(path)?))
+    }
+}
+
+impl SyncIndexEntry {
+    pub fn set_hashes(&mut self, hashes: &FileHashes) {
+        self.sha1 = Some(hashes.sha1.clone());
+        self.sha512 = Some(hashes.sha512.clone());
+        self.size = Some(hashes.size);
+    }
+
+    /// Check `hashes` of a file against the ones recorded in the index. Fails if the entry has
+    /// no hashes to check against.
+    pub fn verify(&self, hashes: &FileHashes) -> Result<bool> {
+        if self.sha1.is_none() && self.sha512.is_none() {
+            bail!("{} has no hashes in the lock file", self.id);
+        }
+        Ok(self.sha1.as_ref().is_none_or(|it| *it == hashes.sha1)
+            && self.sha512.as_ref().is_none_or(|it| *it == hashes.sha512)
+            && self.size.is_none_or(|it| it == hashes.size))
+    }
+}
+
+impl SyncIndex {
+    /// Sort the packages so that the lock file is the same for the same content, no matter
+    /// in which order it was installed.
+    pub fn sort(&mut self) {
+        self.packages.sort_by(|a, b| {
+            (a.namespace_dir(), &a.id, &a.file).cmp(&(b.namespace_dir(), &b.id, &b.file))
+        });
+    }
+}
+
+/// What needs to happen to make the instance match its lock file exactly.
+#[derive(Debug, Default)]
+pub struct LockedPlan {
+    /// Indices of the packages that are missing or don't match their hashes
+    pub download: Vec<usize>,
+    /// Files in the content directories that aren't in the lock
+    pub extraneous: Vec<PathBuf>,
+}
+
+impl LockedPlan {
+    pub fn is_empty(&self) -> bool {
+        self.download.is_empty() && self.extraneous.is_empty()
+    }
+}
+
+/// Restoring an instance from its lock file
+impl Sync {
+    /// The directories that locked mode makes match the lock exactly. The datapacks of worlds
+    /// are left alone, since worlds can come with their own.
+    fn locked_dirs(&self) -> Vec<String> {
+        let mut dirs = IndexEntryKind::ALL
+            .iter()
+            .map(|kind| kind.dir().to_string())
+            .collect::<Vec<_>>();
+        dirs.push(self.datapacks.clone());
+        dirs.retain(|it| !Path::new(it).starts_with("saves"));
+        dirs.sort();
+        dirs.dedup();
+        dirs
+    }
+
+    /// Compare the content directories against the lock file.
+    ///
+    /// Fails if a package needs to be downloaded but has no URL or hashes in the lock.
+    pub fn check_locked(&self) -> Result<LockedPlan> {
+        let mut plan = LockedPlan::default();
+        let mut known = vec![];
+
+        for (i, pkg) in self.index.packages.iter().enumerate() {
+            let path = pkg.get_namespace(&self.minecraft_directory).join(&pkg.file);
+            let up_to_date = path.is_file()
+                && pkg.verify(
+                    &FileHashes::read(&path)
+                        .with_context(|| format!("hash {path}", path = path.display()))?,
+                )?;
+            if !up_to_date {
+                if pkg.url.is_none() {
+                    bail!(
+                        "{} needs to be downloaded, but has no URL in the lock",
+                        pkg.file
+                    );
+                }
+                if pkg.sha1.is_none() && pkg.sha512.is_none() {
+                    bail!(
+                        "{} needs to be downloaded, but has no hashes in the lock",
+                        pkg.file
+                    );
+                }
+                plan.download.push(i);
+            }
+            known.push(path);
+        }
+
+        for dir in self.locked_dirs() {
+            let dir = self.minecraft_directory.join(dir);
+            if !dir.is_dir() {
+                continue;
+            }
+            for entry in std::fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.is_file() && !known.contains(&path) {
+                    plan.extraneous.push(path);
+                }
+            }
+        }
+        plan.extraneous.sort();
+
+        Ok(plan)
+    }
+
+    /// Apply a [`LockedPlan`], removing extraneous files and downloading packages from the URLs
+    /// in the lock. Fails if a downloaded file doesn't match its hashes.
+    pub async fn apply_locked(&mut self, plan: &LockedPlan) -> Result<()> {
+        for path in &plan.extraneous {
+            self.transaction.remove(path)?;
+        }
+
+        let mut queue = vec![];
+        for &i in &plan.download {
+            let pkg = &self.index.packages[i];
+            let path = pkg.get_namespace(&self.minecraft_directory).join(&pkg.file);
+            // the downloader would try to resume a file that's already there
+            self.transaction.create(&path)?;
+            let url = pkg.url.as_deref().unwrap();
+            queue.push(Download::new(
+                &Url::parse(url).with_context(|| format!("invalid URL in lock: {url}"))?,
+                path.to_str().unwrap(),
+                Some(pkg.file.clone()),
+            ));
+        }
+
+        let downloader = DownloaderBuilder::new().concurrent_downloads(10).build();
+        for summary in downloader.download(&queue).await {
+            if let Status::Fail(err) = summary.status() {
+                bail!("failed to download {}: {err}", summary.download().url);
+            }
+        }
+
+        for &i in &plan.download {
+            let pkg = &self.index.packages[i];
+            let path = pkg.get_namespace(&self.minecraft_directory).join(&pkg.file);
+            if !pkg.verify(&FileHashes::read(&path)?)? {
+                std::fs::remove_file(&path)?;
+                return Err(eyre!("hash mismatch for {}", pkg.file))
+                    .with_context(|| format!("downloaded from {}", pkg.url.as_deref().unwrap()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{
+            fake::FakeProvider,
+            fixtures::{entry, sync},
+        },
+        *,
+    };
+
+    #[test]
+    fn test_file_hashes() {
+        let hashes = FileHashes::from_bytes(b"startmc");
+        assert_eq!(hashes.sha1, "2a4ef7d278319611d0118acf9bbe44050ae28a50");
+        assert_eq!(hashes.size, 7);
+        assert_eq!(hashes.sha512.len(), 128);
+    }
+
+    #[test]
+    fn test_verify() {
+        let hashes = FileHashes::from_bytes(b"startmc");
+        let mut pkg = entry("sodium", "0.5.3", "sodium.jar", true);
+        assert!(pkg.verify(&hashes).is_err());
+
+        pkg.set_hashes(&hashes);
+        assert!(pkg.verify(&hashes).unwrap());
+        assert!(!pkg.verify(&FileHashes::from_bytes(b"other")).unwrap());
+
+        // older lock files might only have a sha1
+        pkg.sha512 = None;
+        pkg.size = None;
+        assert!(pkg.verify(&hashes).unwrap());
+    }
+
+    #[test]
+    fn test_sort() {
+        let mut index = SyncIndex {
+            packages: vec![
+                entry("sodium", "0.5.3", "sodium.jar", true),
+                entry("fabric-api", "0.92.2", "fabric-api.jar", true),
+                entry("iris", "1.6.4", "iris.jar", true),
+            ],
+            ..Default::default()
+        };
+        index.sort();
+        let ids = index
+            .packages
+            .iter()
+            .map(|it| it.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["fabric-api", "iris", "sodium"]);
+    }
+
+    #[test]
+    fn test_check_locked() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for path in [
+            "mods/sodium.jar",
+            "mods/extra.jar",
+            "resourcepacks/extra.zip",
+            "saves/World/datapacks/shipped.zip",
+        ] {
+            std::fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+            std::fs::write(root.join(path), path).unwrap();
+        }
+        let mut sodium = entry("sodium", "0.5.3", "sodium.jar", true);
+        sodium.set_hashes(&FileHashes::from_bytes(b"mods/sodium.jar"));
+        let index = SyncIndex {
+            packages: vec![sodium],
+            ..Default::default()
+        };
+        let sync = sync(index, FakeProvider::new(vec![], vec![]), root.to_path_buf());
+
+        let plan = sync.check_locked().unwrap();
+        assert!(plan.download.is_empty());
+        // datapacks that came with a world aren't touched
+        assert_eq!(
+            plan.extraneous,
+            vec![
+                root.join("mods/extra.jar"),
+                root.join("resourcepacks/extra.zip")
+            ]
+        );
+    }
+}
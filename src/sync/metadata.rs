@@ -0,0 +1,630 @@
+//! Metadata that mods and packs declare about themselves, read from files inside of them.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Cursor, Read, Seek},
+    path::Path,
+};
+
+use color_eyre::{
+    Result,
+    eyre::{Context, bail},
+};
+use serde::{Deserialize, Serialize};
+use zip::{ZipArchive, result::ZipError};
+
+use super::IndexEntryKind;
+
+const FABRIC_MOD_JSON: &str = "fabric.mod.json";
+const QUILT_MOD_JSON: &str = "quilt.mod.json";
+const NEOFORGE_MODS_TOML: &str = "META-INF/neoforge.mods.toml";
+const MODS_TOML: &str = "META-INF/mods.toml";
+const PACK_MCMETA: &str = "pack.mcmeta";
+
+/// The file the metadata was read from, which decides the syntax of version requirements.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataSource {
+    /// `fabric.mod.json`, with version predicates like `>=0.14 <0.15` or `1.20.x`
+    Fabric,
+    /// `quilt.mod.json`, with the same predicates as Fabric
+    Quilt,
+    /// `META-INF/mods.toml` of Forge and NeoForge, with Maven ranges like `[47,)`
+    Forge,
+    /// `pack.mcmeta` of resource packs and data packs
+    Pack,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ContentMetadata {
+    pub source: MetadataSource,
+    /// The mod id, `None` for packs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pack_format: Option<u32>,
+    /// Other mod ids this counts as, with their versions, from aliases it declares and mods
+    /// nested in it
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub provides: BTreeMap<String, String>,
+    /// Mod ids (including `minecraft` and the loader) that are needed, with version requirements
+    /// of which any one has to match
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub depends: BTreeMap<String, Vec<String>>,
+    /// Mod ids that this is incompatible with, in the same form as `depends`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub breaks: BTreeMap<String, Vec<String>>,
+}
+
+impl ContentMetadata {
+    /// Read the metadata of the content file at `path`. Returns `None` for files without any,
+    /// like shader packs or jars that aren't mods.
+    pub fn read(path: &Path, kind: IndexEntryKind) -> Result<Option<Self>> {
+        let archive = ZipArchive::new(File::open(path)?)
+            .with_context(|| format!("open {path}", path = path.display()))?;
+        Self::from_archive(archive, kind)
+    }
+
+    pub fn from_archive<R: Read + Seek>(
+        mut archive: ZipArchive<R>,
+        kind: IndexEntryKind,
+    ) -> Result<Option<Self>> {
+        match kind {
+            IndexEntryKind::Mod => {
+                if let Some(json) = read_file(&mut archive, FABRIC_MOD_JSON)? {
+                    let (mut metadata, jars) = parse_fabric(&json)?;
+                    for jar in jars {
+                        match read_nested(&mut archive, &jar) {
+                            Ok(Some(nested)) => {
+                                if let (Some(id), Some(version)) = (nested.id, nested.version) {
+                                    metadata.provides.insert(id, version);
+                                }
+                                metadata.provides.extend(nested.provides);
+                            }
+                            Ok(None) => {}
+                            Err(err) => warn!("can't read nested jar {jar}: {err}"),
+                        }
+                    }
+                    return Ok(Some(metadata));
+                }
+                if let Some(json) = read_file(&mut archive, QUILT_MOD_JSON)? {
+                    return parse_quilt(&json).map(Some);
+                }
+                for name in [NEOFORGE_MODS_TOML, MODS_TOML] {
+                    if let Some(toml) = read_file(&mut archive, name)? {
+                        let manifest = read_file(&mut archive, "META-INF/MANIFEST.MF")?;
+                        return parse_mods_toml(&toml, manifest.as_deref()).map(Some);
+                    }
+                }
+                Ok(None)
+            }
+            IndexEntryKind::Resourcepack | IndexEntryKind::Datapack => {
+                match read_file(&mut archive, PACK_MCMETA)? {
+                    Some(json) => parse_pack_mcmeta(&json).map(Some),
+                    None => Ok(None),
+                }
+            }
+            IndexEntryKind::Shader => Ok(None),
+        }
+    }
+
+    /// The name and version of a package without a provider, from its metadata or else guessed
+    /// from `filename`.
+    pub fn name_and_version(metadata: Option<&Self>, filename: &str) -> (String, String) {
+        if let Some(metadata) = metadata
+            && let (Some(id), Some(version)) = (&metadata.id, &metadata.version)
+        {
+            return (id.clone(), version.clone());
+        }
+        match super::VersionTuple::parse(filename) {
+            Some(tuple) => (tuple.name, tuple.version),
+            None => {
+                let name = Path::new(filename)
+                    .file_stem()
+                    .and_then(|it| it.to_str())
+                    .unwrap_or(filename);
+                (name.to_string(), "0.0.0".to_string())
+            }
+        }
+    }
+}
+
+fn read_bytes<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<Option<Vec<u8>>> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("read {name}")),
+    };
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes)
+        .with_context(|| format!("read {name}"))?;
+    Ok(Some(bytes))
+}
+
+fn read_file<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<Option<String>> {
+    Ok(read_bytes(archive, name)?.map(|it| String::from_utf8_lossy(&it).into_owned()))
+}
+
+/// Read the metadata of a mod nested in a Fabric mod, which Fabric Loader loads like any other.
+fn read_nested<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<Option<ContentMetadata>> {
+    let Some(bytes) = read_bytes(archive, name)? else {
+        return Ok(None);
+    };
+    ContentMetadata::from_archive(ZipArchive::new(Cursor::new(bytes))?, IndexEntryKind::Mod)
+}
+
+/// Version requirements in JSON metadata, one string or a list of which any one matches.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Versions {
+    One(String),
+    Any(Vec<String>),
+}
+
+impl From<Versions> for Vec<String> {
+    fn from(versions: Versions) -> Self {
+        match versions {
+            Versions::One(it) => vec![it],
+            Versions::Any(it) => it,
+        }
+    }
+}
+
+/// Parse a `fabric.mod.json`, also returning the paths of the jars nested in the mod.
+fn parse_fabric(json: &str) -> Result<(ContentMetadata, Vec<String>)> {
+    #[derive(Deserialize)]
+    struct FabricModJson {
+        id: String,
+        version: String,
+        name: Option<String>,
+        description: Option<String>,
+        #[serde(default)]
+        provides: Vec<String>,
+        #[serde(default)]
+        jars: Vec<FabricJar>,
+        #[serde(default)]
+        depends: BTreeMap<String, Versions>,
+        #[serde(default)]
+        breaks: BTreeMap<String, Versions>,
+    }
+
+    #[derive(Deserialize)]
+    struct FabricJar {
+        file: String,
+    }
+
+    // Fabric Loader accepts raw line breaks and tabs in strings, which plenty of mods have in
+    // their description
+    let json = json.replace(['\n', '\r', '\t'], " ");
+    let mod_json: FabricModJson =
+        serde_json::from_str(&json).with_context(|| format!("parse {FABRIC_MOD_JSON}"))?;
+    let metadata = ContentMetadata {
+        source: MetadataSource::Fabric,
+        id: Some(mod_json.id),
+        pack_format: None,
+        provides: mod_json
+            .provides
+            .into_iter()
+            .map(|it| (it, mod_json.version.clone()))
+            .collect(),
+        version: Some(mod_json.version),
+        name: mod_json.name,
+        description: mod_json.description,
+        depends: into_requirements(mod_json.depends),
+        breaks: into_requirements(mod_json.breaks),
+    };
+    Ok((
+        metadata,
+        mod_json.jars.into_iter().map(|it| it.file).collect(),
+    ))
+}
+
+fn into_requirements(map: BTreeMap<String, Versions>) -> BTreeMap<String, Vec<String>> {
+    map.into_iter().map(|(id, it)| (id, it.into())).collect()
+}
+
+fn parse_quilt(json: &str) -> Result<ContentMetadata> {
+    #[derive(Deserialize)]
+    struct QuiltModJson {
+        quilt_loader: QuiltLoader,
+    }
+
+    #[derive(Deserialize)]
+    struct QuiltLoader {
+        id: String,
+        version: String,
+        #[serde(default)]
+        metadata: QuiltMetadata,
+        #[serde(default)]
+        depends: Vec<QuiltDependency>,
+        #[serde(default)]
+        breaks: Vec<QuiltDependency>,
+    }
+
+    #[derive(Deserialize, Default)]
+    struct QuiltMetadata {
+        name: Option<String>,
+        description: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum QuiltDependency {
+        Id(String),
+        Object {
+            id: String,
+            versions: Option<serde_json::Value>,
+            #[serde(default)]
+            optional: bool,
+        },
+    }
+
+    impl QuiltDependency {
+        fn into_requirement(self) -> Option<(String, Vec<String>)> {
+            match self {
+                QuiltDependency::Id(id) => Some((id, vec!["*".to_string()])),
+                QuiltDependency::Object { optional: true, .. } => None,
+                QuiltDependency::Object { id, versions, .. } => {
+                    let versions = match versions {
+                        Some(serde_json::Value::String(it)) => vec![it],
+                        Some(serde_json::Value::Array(it)) => it
+                            .into_iter()
+                            .filter_map(|it| it.as_str().map(str::to_string))
+                            .collect(),
+                        // `{ "any": [...] }` and `{ "all": [...] }` are rare, and allowing any
+                        // version is better than reporting a problem that isn't there
+                        _ => vec!["*".to_string()],
+                    };
+                    Some((id, versions))
+                }
+            }
+        }
+    }
+
+    let mod_json: QuiltModJson =
+        serde_json::from_str(json).with_context(|| format!("parse {QUILT_MOD_JSON}"))?;
+    let loader = mod_json.quilt_loader;
+    Ok(ContentMetadata {
+        source: MetadataSource::Quilt,
+        id: Some(loader.id),
+        version: Some(loader.version),
+        name: loader.metadata.name,
+        description: loader.metadata.description,
+        pack_format: None,
+        provides: BTreeMap::new(),
+        depends: loader
+            .depends
+            .into_iter()
+            .filter_map(QuiltDependency::into_requirement)
+            .collect(),
+        breaks: loader
+            .breaks
+            .into_iter()
+            .filter_map(QuiltDependency::into_requirement)
+            .collect(),
+    })
+}
+
+fn parse_mods_toml(toml: &str, manifest: Option<&str>) -> Result<ContentMetadata> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ModsToml {
+        #[serde(default)]
+        mods: Vec<ModsTomlMod>,
+        #[serde(default)]
+        dependencies: BTreeMap<String, Vec<ModsTomlDependency>>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ModsTomlMod {
+        mod_id: String,
+        version: Option<String>,
+        display_name: Option<String>,
+        description: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ModsTomlDependency {
+        mod_id: String,
+        /// Forge only
+        mandatory: Option<bool>,
+        /// NeoForge only: `required`, `optional`, `incompatible` or `discouraged`
+        #[serde(rename = "type")]
+        kind: Option<String>,
+        version_range: Option<String>,
+    }
+
+    let mut mods_toml: ModsToml =
+        toml::from_str(toml).with_context(|| format!("parse {MODS_TOML}"))?;
+    let Some(first) = std::mem::take(&mut mods_toml.mods).into_iter().next() else {
+        bail!("{MODS_TOML} has no mods");
+    };
+    // the version is usually filled in from the jar manifest when building the mod
+    let version = match first.version {
+        Some(version) if version == "${file.jarVersion}" => manifest.and_then(|it| {
+            it.lines()
+                .find_map(|it| it.strip_prefix("Implementation-Version:"))
+                .map(|it| it.trim().to_string())
+        }),
+        version => version,
+    };
+
+    let mut depends = BTreeMap::new();
+    let mut breaks = BTreeMap::new();
+    for dependency in mods_toml
+        .dependencies
+        .remove(&first.mod_id)
+        .unwrap_or_default()
+    {
+        let range = vec![dependency.version_range.unwrap_or_else(|| "*".to_string())];
+        match (dependency.kind.as_deref(), dependency.mandatory) {
+            (Some("required"), _) | (None, Some(true)) => {
+                depends.insert(dependency.mod_id, range);
+            }
+            (Some("incompatible"), _) => {
+                breaks.insert(dependency.mod_id, range);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(ContentMetadata {
+        source: MetadataSource::Forge,
+        id: Some(first.mod_id),
+        version,
+        name: first.display_name,
+        description: first.description.map(|it| it.trim().to_string()),
+        pack_format: None,
+        provides: BTreeMap::new(),
+        depends,
+        breaks,
+    })
+}
+
+fn parse_pack_mcmeta(json: &str) -> Result<ContentMetadata> {
+    #[derive(Deserialize)]
+    struct PackMcmeta {
+        pack: Pack,
+    }
+
+    #[derive(Deserialize)]
+    struct Pack {
+        pack_format: u32,
+        #[serde(default)]
+        description: serde_json::Value,
+    }
+
+    // a lot of packs are saved with a byte order mark
+    let mcmeta: PackMcmeta = serde_json::from_str(json.trim_start_matches('\u{feff}'))
+        .with_context(|| format!("parse {PACK_MCMETA}"))?;
+    let description = component_text(&mcmeta.pack.description);
+    Ok(ContentMetadata {
+        source: MetadataSource::Pack,
+        id: None,
+        version: None,
+        name: None,
+        description: (!description.is_empty()).then_some(description),
+        pack_format: Some(mcmeta.pack.pack_format),
+        provides: BTreeMap::new(),
+        depends: BTreeMap::new(),
+        breaks: BTreeMap::new(),
+    })
+}
+
+/// The plain text of a JSON text component, without any formatting.
+fn component_text(component: &serde_json::Value) -> String {
+    match component {
+        serde_json::Value::String(it) => it.clone(),
+        serde_json::Value::Array(it) => it.iter().map(component_text).collect(),
+        serde_json::Value::Object(it) => {
+            let mut text = it.get("text").map(component_text).unwrap_or_default();
+            if let Some(extra) = it.get("extra") {
+                text.push_str(&component_text(extra));
+            }
+            text
+        }
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    use super::*;
+
+    fn archive<T: AsRef<[u8]>>(files: &[(&str, T)]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        for (name, contents) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_ref()).unwrap();
+        }
+        ZipArchive::new(zip.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_fabric() {
+        let json = r#"{
+            "schemaVersion": 1,
+            "id": "iris",
+            "version": "1.6.4",
+            "name": "Iris",
+            "description": "A shaders mod
+for Fabric",
+            "depends": {
+                "minecraft": ["1.20", "1.20.1"],
+                "fabricloader": ">=0.14.0"
+            },
+            "breaks": { "optifabric": "*" }
+        }"#;
+        let metadata =
+            ContentMetadata::from_archive(archive(&[(FABRIC_MOD_JSON, json)]), IndexEntryKind::Mod)
+                .unwrap()
+                .unwrap();
+        assert_eq!(metadata.source, MetadataSource::Fabric);
+        assert_eq!(metadata.id.as_deref(), Some("iris"));
+        assert_eq!(metadata.version.as_deref(), Some("1.6.4"));
+        assert_eq!(metadata.depends["minecraft"], vec!["1.20", "1.20.1"]);
+        assert_eq!(metadata.depends["fabricloader"], vec![">=0.14.0"]);
+        assert_eq!(metadata.breaks["optifabric"], vec!["*"]);
+    }
+
+    #[test]
+    fn test_fabric_nested() {
+        let nested = archive(&[(
+            FABRIC_MOD_JSON,
+            r#"{"id": "fabric-api-base", "version": "0.4.31", "provides": ["fabric-api-base-v0"]}"#,
+        )])
+        .into_inner()
+        .into_inner();
+        let json = r#"{
+            "id": "fabric-api",
+            "version": "0.92.2",
+            "provides": ["fabric"],
+            "jars": [{ "file": "META-INF/jars/fabric-api-base.jar" }]
+        }"#;
+        let metadata = ContentMetadata::from_archive(
+            archive(&[
+                (FABRIC_MOD_JSON, json.as_bytes()),
+                ("META-INF/jars/fabric-api-base.jar", &nested),
+            ]),
+            IndexEntryKind::Mod,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            metadata.provides.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("fabric".to_string(), "0.92.2".to_string()),
+                ("fabric-api-base".to_string(), "0.4.31".to_string()),
+                ("fabric-api-base-v0".to_string(), "0.4.31".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quilt() {
+        let json = r#"{
+            "schema_version": 1,
+            "quilt_loader": {
+                "id": "qsl",
+                "version": "6.1.0",
+                "metadata": { "name": "Quilt Standard Libraries" },
+                "depends": [
+                    "quilt_loader",
+                    { "id": "minecraft", "versions": ">=1.20" },
+                    { "id": "modmenu", "optional": true }
+                ]
+            }
+        }"#;
+        let metadata =
+            ContentMetadata::from_archive(archive(&[(QUILT_MOD_JSON, json)]), IndexEntryKind::Mod)
+                .unwrap()
+                .unwrap();
+        assert_eq!(metadata.name.as_deref(), Some("Quilt Standard Libraries"));
+        assert_eq!(
+            metadata.depends.keys().collect::<Vec<_>>(),
+            vec!["minecraft", "quilt_loader"]
+        );
+    }
+
+    #[test]
+    fn test_mods_toml() {
+        let toml = r#"
+            modLoader = "javafml"
+            loaderVersion = "[47,)"
+
+            [[mods]]
+            modId = "jei"
+            version = "${file.jarVersion}"
+            displayName = "Just Enough Items"
+
+            [[dependencies.jei]]
+            modId = "forge"
+            mandatory = true
+            versionRange = "[47.1.0,)"
+
+            [[dependencies.jei]]
+            modId = "roughlyenoughitems"
+            type = "incompatible"
+
+            [[dependencies.jei]]
+            modId = "emi"
+            mandatory = false
+        "#;
+        let manifest = "Manifest-Version: 1.0\r\nImplementation-Version: 15.3.0.4\r\n";
+        let metadata = ContentMetadata::from_archive(
+            archive(&[(MODS_TOML, toml), ("META-INF/MANIFEST.MF", manifest)]),
+            IndexEntryKind::Mod,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(metadata.source, MetadataSource::Forge);
+        assert_eq!(metadata.version.as_deref(), Some("15.3.0.4"));
+        assert_eq!(metadata.depends["forge"], vec!["[47.1.0,)"]);
+        assert!(!metadata.depends.contains_key("emi"));
+        assert!(metadata.breaks.contains_key("roughlyenoughitems"));
+
+        for toml in [
+            "modLoader = \"javafml\"\nmods = []\n",
+            "modLoader = \"javafml\"\n",
+        ] {
+            let err =
+                ContentMetadata::from_archive(archive(&[(MODS_TOML, toml)]), IndexEntryKind::Mod)
+                    .unwrap_err();
+            assert!(err.to_string().contains("has no mods"));
+        }
+    }
+
+    #[test]
+    fn test_pack_mcmeta() {
+        let json = "\u{feff}{\"pack\": {\"pack_format\": 15, \"description\": [\"Faithful \", {\"text\": \"32x\"}]}}";
+        let metadata = ContentMetadata::from_archive(
+            archive(&[(PACK_MCMETA, json)]),
+            IndexEntryKind::Resourcepack,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(metadata.pack_format, Some(15));
+        assert_eq!(metadata.description.as_deref(), Some("Faithful 32x"));
+
+        // a jar without any metadata, like a library
+        assert!(
+            ContentMetadata::from_archive(archive(&[("a.class", "")]), IndexEntryKind::Mod)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_name_and_version() {
+        let (metadata, _) = parse_fabric(r#"{"id": "sodium", "version": "0.5.3"}"#).unwrap();
+        assert_eq!(
+            ContentMetadata::name_and_version(Some(&metadata), "sodium-fabric-mc1.20.1.jar"),
+            ("sodium".to_string(), "0.5.3".to_string())
+        );
+        assert_eq!(
+            ContentMetadata::name_and_version(None, "mymod-1.0.0.jar"),
+            ("mymod".to_string(), "1.0.0".to_string())
+        );
+        // VersionTuple can't find a version in this, which used to abort the whole refresh
+        assert_eq!(
+            ContentMetadata::name_and_version(None, "Mod-Menu-Beta.jar"),
+            ("Mod-Menu-Beta".to_string(), "0.0.0".to_string())
+        );
+    }
+}
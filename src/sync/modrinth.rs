@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use color_eyre::Result;
+use ferinth::{
+    Ferinth,
+    structures::{
+        project::ProjectType,
+        search::{Facet, SearchHit, Sort},
+        version::{DependencyType, Version},
+    },
+};
+
+use super::{
+    ContentProvider, Dependency, DependencyKind, FileHashes, IndexEntryKind, Project,
+    ProjectVersion, ProviderKind, SearchSort, VersionFile, compatible_loaders,
+};
+
+impl IndexEntryKind {
+    pub fn from_project_type(project_type: ProjectType) -> Option<Self> {
+        match project_type {
+            ProjectType::Mod => Some(IndexEntryKind::Mod),
+            ProjectType::ResourcePack => Some(IndexEntryKind::Resourcepack),
+            ProjectType::Shader => Some(IndexEntryKind::Shader),
+            ProjectType::Datapack => Some(IndexEntryKind::Datapack),
+            _ => None,
+        }
+    }
+
+    pub fn to_project_type(self) -> ProjectType {
+        match self {
+            IndexEntryKind::Mod => ProjectType::Mod,
+            IndexEntryKind::Resourcepack => ProjectType::ResourcePack,
+            IndexEntryKind::Shader => ProjectType::Shader,
+            IndexEntryKind::Datapack => ProjectType::Datapack,
+        }
+    }
+}
+
+pub struct Modrinth {
+    fer: Ferinth<()>,
+}
+
+impl Modrinth {
+    pub fn new() -> Self {
+        Self {
+            fer: Ferinth::<()>::new(
+                env!("CARGO_PKG_REPOSITORY"),
+                Some(env!("CARGO_PKG_VERSION")),
+                Some(env!("CARGO_PKG_HOMEPAGE")),
+            ),
+        }
+    }
+}
+
+impl Default for Modrinth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<ferinth::structures::project::Project> for Project {
+    fn from(project: ferinth::structures::project::Project) -> Self {
+        Self {
+            kind: IndexEntryKind::from_project_type(project.project_type),
+            id: project.id,
+            slug: project.slug,
+            title: project.title,
+            description: project.description,
+            downloads: project.downloads as u64,
+            follows: Some(project.followers as u64),
+            updated: project.updated,
+        }
+    }
+}
+
+impl From<SearchHit> for Project {
+    fn from(hit: SearchHit) -> Self {
+        Self {
+            kind: IndexEntryKind::from_project_type(hit.project_type),
+            slug: hit.slug.unwrap_or_else(|| hit.project_id.clone()),
+            id: hit.project_id,
+            title: hit.title,
+            description: hit.description,
+            downloads: hit.downloads as u64,
+            follows: Some(hit.follows as u64),
+            updated: hit.date_modified,
+        }
+    }
+}
+
+impl From<Version> for ProjectVersion {
+    fn from(version: Version) -> Self {
+        Self {
+            id: version.id,
+            project_id: version.project_id,
+            version_number: version.version_number,
+            date_published: version.date_published,
+            game_versions: version.game_versions,
+            loaders: version.loaders,
+            files: version
+                .files
+                .into_iter()
+                .map(|it| VersionFile {
+                    filename: it.filename,
+                    url: Some(it.url.to_string()),
+                    size: it.size as u64,
+                    sha1: Some(it.hashes.sha1),
+                    sha512: Some(it.hashes.sha512),
+                    primary: it.primary,
+                })
+                .collect(),
+            dependencies: version
+                .dependencies
+                .into_iter()
+                .map(|it| Dependency {
+                    project_id: it.project_id,
+                    version_id: it.version_id,
+                    kind: match it.dependency_type {
+                        DependencyType::Required => DependencyKind::Required,
+                        DependencyType::Optional => DependencyKind::Optional,
+                        DependencyType::Incompatible => DependencyKind::Incompatible,
+                        DependencyType::Embedded => DependencyKind::Embedded,
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl ContentProvider for Modrinth {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Modrinth
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        sort: SearchSort,
+        kind: Option<IndexEntryKind>,
+        _game_version: &str,
+        loader: Option<&str>,
+    ) -> Result<Vec<Project>> {
+        let mut facets = vec![];
+        if let Some(kind) = kind {
+            facets.push(vec![Facet::ProjectType(kind.to_project_type())]);
+        }
+        // only mods are tagged with a loader
+        if let Some(loader) = loader
+            && kind.is_none_or(|it| it == IndexEntryKind::Mod)
+        {
+            facets.push(
+                compatible_loaders(loader)
+                    .into_iter()
+                    .map(|it| Facet::Categories(it.to_string()))
+                    .collect(),
+            );
+        }
+        let sort = match sort {
+            SearchSort::Relevance => Sort::Relevance,
+            SearchSort::Downloads => Sort::Downloads,
+            SearchSort::Follows => Sort::Follows,
+            SearchSort::Newest => Sort::Newest,
+            SearchSort::Updated => Sort::Updated,
+        };
+        let results = self.fer.search(query, &sort, facets).await?;
+        Ok(results.hits.into_iter().map(Project::from).collect())
+    }
+
+    async fn project(&self, id: &str) -> Result<Project> {
+        Ok(self.fer.project_get(id).await?.into())
+    }
+
+    async fn projects(&self, ids: &[&str]) -> Result<Vec<Project>> {
+        Ok(self
+            .fer
+            .project_get_multiple(ids)
+            .await?
+            .into_iter()
+            .map(Project::from)
+            .collect())
+    }
+
+    async fn versions(
+        &self,
+        project_id: &str,
+        kind: IndexEntryKind,
+        game_version: &str,
+        loader: Option<&str>,
+    ) -> Result<Vec<ProjectVersion>> {
+        // resourcepacks are "minecraft" loader content on Modrinth, and shaders are tagged with
+        // the shader loaders (iris, optifine, ...) they need
+        let loaders = match kind {
+            IndexEntryKind::Mod => Some(loader.map(compatible_loaders).unwrap_or_default()),
+            IndexEntryKind::Resourcepack => Some(vec!["minecraft"]),
+            IndexEntryKind::Shader => None,
+            IndexEntryKind::Datapack => Some(vec!["datapack"]),
+        };
+        Ok(self
+            .fer
+            .version_list_filtered(project_id, loaders.as_deref(), Some(&[game_version]), None)
+            .await?
+            .into_iter()
+            .map(ProjectVersion::from)
+            .collect())
+    }
+
+    async fn version(&self, id: &str) -> Result<ProjectVersion> {
+        Ok(self.fer.version_get(id).await?.into())
+    }
+
+    async fn versions_by_id(&self, ids: &[&str]) -> Result<Vec<ProjectVersion>> {
+        Ok(self
+            .fer
+            .version_get_multiple(ids)
+            .await?
+            .into_iter()
+            .map(ProjectVersion::from)
+            .collect())
+    }
+
+    async fn identify(&self, files: &[&FileHashes]) -> Result<HashMap<String, ProjectVersion>> {
+        Ok(self
+            .fer
+            .version_get_from_multiple_hashes(files.iter().map(|it| it.sha1.clone()).collect())
+            .await?
+            .into_iter()
+            .map(|(hash, version)| (hash, version.into()))
+            .collect())
+    }
+}
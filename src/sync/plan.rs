@@ -0,0 +1,446 @@
+use std::collections::HashSet;
+
+use color_eyre::{
+    Result,
+    eyre::{Context, bail},
+};
+
+use super::{
+    Dependency, DependencyKind, ProjectVersion, ProviderKind, ResolvedPackage, Sync, SyncIndex,
+    SyncIndexEntry,
+};
+use crate::util::pacman_warn;
+
+/// Two packages that declared themselves incompatible with each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub package: String,
+    pub with: String,
+}
+
+/// A set of packages to install as one transaction, with their dependencies resolved.
+#[derive(Debug, Default)]
+pub struct InstallPlan {
+    /// The requested packages and, transitively, their required dependencies
+    pub packages: Vec<ResolvedPackage>,
+    /// Optional dependencies of the packages that aren't installed or planned yet
+    pub optional: Vec<ResolvedPackage>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Whether `dep` of a package from `provider` points to `pkg`.
+fn is_dependency(dep: &Dependency, provider: ProviderKind, pkg: &ResolvedPackage) -> bool {
+    pkg.provider == provider
+        && (dep.version_id.as_deref() == Some(pkg.version.id.as_str())
+            || dep.project_id.as_deref() == Some(pkg.project.id.as_str()))
+}
+
+/// Whether `dep` of a package from `provider` is satisfied by the installed `entry`.
+fn is_installed_dependency(
+    dep: &Dependency,
+    provider: ProviderKind,
+    entry: &SyncIndexEntry,
+) -> bool {
+    entry.provider == Some(provider)
+        && (dep.version_id.is_some() && entry.version_id == dep.version_id
+            || dep.project_id.is_some() && entry.project_id == dep.project_id)
+}
+
+impl InstallPlan {
+    fn contains_project(&self, provider: ProviderKind, project_id: &str) -> bool {
+        self.packages
+            .iter()
+            .any(|it| it.provider == provider && it.project.id == project_id)
+    }
+
+    /// Check whether `dep` points to a package that is planned or installed, returning its name.
+    fn find_dependency(
+        &self,
+        dep: &Dependency,
+        provider: ProviderKind,
+        index: &SyncIndex,
+    ) -> Option<String> {
+        let planned = self
+            .packages
+            .iter()
+            .find(|it| is_dependency(dep, provider, it));
+        if let Some(planned) = planned {
+            return Some(planned.to_string());
+        }
+
+        index
+            .packages
+            .iter()
+            .find(|it| is_installed_dependency(dep, provider, it))
+            .map(|it| format!("{}-{}", it.id, it.version))
+    }
+
+    /// Look for `incompatible` dependencies between the planned packages themselves, and between
+    /// the planned packages and the `installed` versions of the index's packages.
+    pub fn check_conflicts(&mut self, index: &SyncIndex, installed: &[ProjectVersion]) {
+        let mut conflicts = vec![];
+        for pkg in &self.packages {
+            for dep in &pkg.version.dependencies {
+                if dep.kind != DependencyKind::Incompatible {
+                    continue;
+                }
+                if let Some(with) = self.find_dependency(dep, pkg.provider, index) {
+                    conflicts.push(Conflict {
+                        package: pkg.to_string(),
+                        with,
+                    });
+                }
+            }
+        }
+
+        for version in installed {
+            let Some(entry) = index
+                .packages
+                .iter()
+                .find(|it| it.version_id.as_deref() == Some(version.id.as_str()))
+            else {
+                continue;
+            };
+            let provider = entry.provider.unwrap();
+            // packages that are being replaced by the transaction can't conflict with it
+            if self.contains_project(provider, &version.project_id) {
+                continue;
+            }
+            for dep in &version.dependencies {
+                if dep.kind != DependencyKind::Incompatible {
+                    continue;
+                }
+                let planned = self
+                    .packages
+                    .iter()
+                    .find(|it| is_dependency(dep, provider, it));
+                if let Some(planned) = planned {
+                    conflicts.push(Conflict {
+                        package: planned.to_string(),
+                        with: format!("{}-{}", entry.id, entry.version),
+                    });
+                }
+            }
+        }
+
+        self.conflicts = conflicts;
+    }
+}
+
+/// Dependency resolution
+impl Sync {
+    /// Resolve a dependency of a package from `provider` to a version compatible with the instance.
+    ///
+    /// Dependencies that pin a version are honored if that version is compatible and has files,
+    /// otherwise the newest compatible version of the dependency's project is used.
+    async fn resolve_dependency(
+        &self,
+        provider: ProviderKind,
+        dep: &Dependency,
+    ) -> Result<ResolvedPackage> {
+        let pinned = match &dep.version_id {
+            Some(version_id) => Some(self.provider(provider)?.version(version_id).await?),
+            None => None,
+        };
+        let project_id = match (&dep.project_id, &pinned) {
+            (Some(project_id), _) => project_id.clone(),
+            (None, Some(version)) => version.project_id.clone(),
+            (None, None) => bail!("dependency has neither a project nor a version"),
+        };
+
+        let project = self.provider(provider)?.project(&project_id).await?;
+        let mut resolved = self.resolve_project(provider, project, None).await?;
+        if let Some(pinned) = pinned
+            && !pinned.files.is_empty()
+            && pinned.game_versions.contains(&self.game_version)
+            && self
+                .loader_for(resolved.kind)?
+                .is_none_or(|loader| pinned.loaders.iter().any(|it| it == loader))
+        {
+            resolved.version = pinned;
+        }
+        Ok(resolved)
+    }
+
+    /// Build an [`InstallPlan`] for `targets`, pulling in their required dependencies
+    /// transitively and collecting optional ones. Dependencies already satisfied by the index
+    /// are skipped.
+    pub async fn plan(&self, targets: Vec<ResolvedPackage>) -> Result<InstallPlan> {
+        let mut plan = InstallPlan::default();
+        let mut queue = targets;
+        let mut seen_optional = HashSet::new();
+
+        while let Some(pkg) = queue.pop() {
+            if plan.contains_project(pkg.provider, &pkg.project.id) {
+                continue;
+            }
+            for dep in &pkg.version.dependencies {
+                let required = match dep.kind {
+                    DependencyKind::Required => true,
+                    DependencyKind::Optional => false,
+                    DependencyKind::Incompatible | DependencyKind::Embedded => continue,
+                };
+
+                if let Some(installed) = self
+                    .index
+                    .packages
+                    .iter()
+                    .find(|it| is_installed_dependency(dep, pkg.provider, it))
+                {
+                    if required && installed.disabled {
+                        pacman_warn(format!(
+                            "{} is required by {pkg}, but is disabled",
+                            installed.id
+                        ));
+                    }
+                    continue;
+                }
+                if dep.project_id.as_ref().is_some_and(|id| {
+                    plan.contains_project(pkg.provider, id)
+                        || queue
+                            .iter()
+                            .any(|it| it.provider == pkg.provider && &it.project.id == id)
+                }) {
+                    continue;
+                }
+                if !required
+                    && !seen_optional.insert((
+                        pkg.provider,
+                        dep.project_id.clone().or(dep.version_id.clone()),
+                    ))
+                {
+                    continue;
+                }
+
+                let resolved = self
+                    .resolve_dependency(pkg.provider, dep)
+                    .await
+                    .with_context(|| format!("resolve dependency of {pkg}"))?;
+                // foreign packages can still satisfy a dependency if their id is the same as the project slug
+                if self
+                    .index
+                    .packages
+                    .iter()
+                    .any(|it| it.id == resolved.project.slug)
+                {
+                    continue;
+                }
+
+                if required {
+                    queue.push(resolved);
+                } else if !plan.optional.iter().any(|it| {
+                    it.provider == resolved.provider && it.project.id == resolved.project.id
+                }) {
+                    plan.optional.push(resolved);
+                }
+            }
+            plan.packages.push(pkg);
+        }
+
+        plan.optional.retain(|it| {
+            !plan
+                .packages
+                .iter()
+                .any(|pkg| pkg.provider == it.provider && pkg.project.id == it.project.id)
+        });
+
+        let mut installed = vec![];
+        for provider in &self.providers {
+            let ids = self
+                .index
+                .packages
+                .iter()
+                .filter(|it| it.provider == Some(provider.kind()))
+                .filter_map(|it| it.version_id.as_deref())
+                .collect::<Vec<_>>();
+            if !ids.is_empty() {
+                installed.extend(provider.versions_by_id(&ids).await?);
+            }
+        }
+        plan.check_conflicts(&self.index, &installed);
+
+        // dependencies were pushed after their dependents, install them first
+        plan.packages.reverse();
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{
+            IndexEntryKind,
+            fake::FakeProvider,
+            fixtures::{entry, project, resolved, sync, version},
+        },
+        *,
+    };
+
+    const DATE: &str = "2023-01-01T00:00:00Z";
+
+    #[test]
+    fn test_conflict_within_plan() {
+        let mut plan = InstallPlan {
+            packages: vec![
+                resolved(
+                    "sodium-project",
+                    "sodium",
+                    version("sodium-project", "0.5.3", DATE, &[]),
+                ),
+                resolved(
+                    "optifabric-project",
+                    "optifabric",
+                    version(
+                        "optifabric-project",
+                        "1.13.0",
+                        DATE,
+                        &[("sodium-project", "incompatible")],
+                    ),
+                ),
+            ],
+            ..Default::default()
+        };
+        plan.check_conflicts(&SyncIndex::default(), &[]);
+        assert_eq!(
+            plan.conflicts,
+            vec![Conflict {
+                package: "optifabric-1.13.0".to_string(),
+                with: "sodium-0.5.3".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_conflict_with_installed() {
+        let index = SyncIndex {
+            packages: vec![entry("optifabric", "1.13.0", "optifabric.jar", true)],
+            ..Default::default()
+        };
+        let installed = version(
+            "optifabric-project",
+            "1.13.0",
+            DATE,
+            &[("sodium-project", "incompatible")],
+        );
+        let mut plan = InstallPlan {
+            packages: vec![resolved(
+                "sodium-project",
+                "sodium",
+                version("sodium-project", "0.5.3", DATE, &[]),
+            )],
+            ..Default::default()
+        };
+        plan.check_conflicts(&index, &[installed]);
+        assert_eq!(
+            plan.conflicts,
+            vec![Conflict {
+                package: "sodium-0.5.3".to_string(),
+                with: "optifabric-1.13.0".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_no_conflict_when_replaced() {
+        // upgrading optifabric to a version that doesn't conflict with sodium anymore
+        let index = SyncIndex {
+            packages: vec![
+                entry("optifabric", "1.13.0", "optifabric.jar", true),
+                entry("sodium", "0.5.3", "sodium.jar", true),
+            ],
+            ..Default::default()
+        };
+        let installed = version(
+            "optifabric-project",
+            "1.13.0",
+            DATE,
+            &[("sodium-project", "incompatible")],
+        );
+        let mut plan = InstallPlan {
+            packages: vec![resolved(
+                "optifabric-project",
+                "optifabric",
+                version("optifabric-project", "1.14.0", DATE, &[]),
+            )],
+            ..Default::default()
+        };
+        plan.check_conflicts(&index, &[installed]);
+        assert!(plan.conflicts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_plan_dependencies() {
+        let provider = FakeProvider::new(
+            vec![
+                project("fabric-api-project", "fabric-api", IndexEntryKind::Mod),
+                project(
+                    "reeses-project",
+                    "reeses-sodium-options",
+                    IndexEntryKind::Mod,
+                ),
+            ],
+            vec![
+                version("fabric-api-project", "0.92.2", DATE, &[]),
+                version("reeses-project", "1.7.2", DATE, &[]),
+            ],
+        );
+        let index = SyncIndex {
+            packages: vec![entry("sodium", "0.5.3", "sodium.jar", true)],
+            ..Default::default()
+        };
+        let sync = sync(index, provider, "/nonexistent".into());
+        let iris = resolved(
+            "iris-project",
+            "iris",
+            version(
+                "iris-project",
+                "1.6.4",
+                DATE,
+                &[
+                    ("sodium-project", "required"),
+                    ("fabric-api-project", "required"),
+                    ("reeses-project", "optional"),
+                ],
+            ),
+        );
+
+        let plan = sync.plan(vec![iris]).await.unwrap();
+        let packages = plan
+            .packages
+            .iter()
+            .map(|it| it.to_string())
+            .collect::<Vec<_>>();
+        // sodium is already installed, fabric-api is installed before iris
+        assert_eq!(packages, vec!["fabric-api-0.92.2", "iris-1.6.4"]);
+        assert_eq!(plan.optional.len(), 1);
+        assert_eq!(plan.optional[0].project.slug, "reeses-sodium-options");
+        assert!(plan.conflicts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pinned_dependency_without_files() {
+        let mut pinned = version("fabric-api-project", "0.92.1", DATE, &[]);
+        pinned.files.clear();
+        let provider = FakeProvider::new(
+            vec![project(
+                "fabric-api-project",
+                "fabric-api",
+                IndexEntryKind::Mod,
+            )],
+            vec![pinned, version("fabric-api-project", "0.92.2", DATE, &[])],
+        );
+        let sync = sync(SyncIndex::default(), provider, "/nonexistent".into());
+        let dependency = Dependency {
+            project_id: None,
+            version_id: Some("fabric-api-project-0.92.1".to_string()),
+            kind: DependencyKind::Required,
+        };
+
+        let resolved = sync
+            .resolve_dependency(ProviderKind::Modrinth, &dependency)
+            .await
+            .unwrap();
+        assert_eq!(resolved.version.version_number, "0.92.2");
+        assert_eq!(resolved.file().filename, "fabric-api-project-0.92.2.jar");
+    }
+}
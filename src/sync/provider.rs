@@ -0,0 +1,183 @@
+//! Where content comes from. Every provider is queried through [`ContentProvider`], so the rest
+//! of sync doesn't care whether a package came from Modrinth or CurseForge.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{FileHashes, IndexEntryKind};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    Modrinth,
+    CurseForge,
+}
+
+impl ProviderKind {
+    pub const ALL: [ProviderKind; 2] = [ProviderKind::Modrinth, ProviderKind::CurseForge];
+
+    /// Split a package name like `curseforge/jei` into its provider and the name itself, like a
+    /// pacman repository. Names without a provider are looked up on Modrinth.
+    pub fn split(name: &str) -> (Self, &str) {
+        for kind in Self::ALL {
+            if let Some(name) = name
+                .strip_prefix(kind.as_str())
+                .and_then(|it| it.strip_prefix('/'))
+            {
+                return (kind, name);
+            }
+        }
+        (ProviderKind::Modrinth, name)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProviderKind::Modrinth => "modrinth",
+            ProviderKind::CurseForge => "curseforge",
+        }
+    }
+}
+
+impl std::fmt::Display for ProviderKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The loaders whose mods run on `loader`, as named in Modrinth's API. Quilt runs Fabric mods too.
+pub fn compatible_loaders(loader: &str) -> Vec<&str> {
+    match loader {
+        "quilt" => vec!["quilt", "fabric"],
+        loader => vec![loader],
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchSort {
+    #[default]
+    Relevance,
+    Downloads,
+    Follows,
+    Newest,
+    Updated,
+}
+
+#[derive(Debug, Clone)]
+pub struct Project {
+    pub id: String,
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    /// `None` if startmc can't install this kind of project
+    pub kind: Option<IndexEntryKind>,
+    pub downloads: u64,
+    /// Not every provider has followers
+    pub follows: Option<u64>,
+    pub updated: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProjectVersion {
+    pub id: String,
+    pub project_id: String,
+    pub version_number: String,
+    pub date_published: DateTime<Utc>,
+    pub game_versions: Vec<String>,
+    pub loaders: Vec<String>,
+    pub files: Vec<VersionFile>,
+    pub dependencies: Vec<Dependency>,
+}
+
+#[derive(Debug, Clone)]
+pub struct VersionFile {
+    pub filename: String,
+    /// Missing if the file can't be downloaded by third party launchers
+    pub url: Option<String>,
+    pub size: u64,
+    pub sha1: Option<String>,
+    pub sha512: Option<String>,
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyKind {
+    Required,
+    Optional,
+    Incompatible,
+    Embedded,
+}
+
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    pub project_id: Option<String>,
+    pub version_id: Option<String>,
+    pub kind: DependencyKind,
+}
+
+/// A source of content, like Modrinth or CurseForge.
+///
+/// `loader` is a Modrinth-style loader name (e.g. `fabric`), and is `None` for content that
+/// doesn't need a mod loader, like resourcepacks.
+#[async_trait]
+pub trait ContentProvider: Send + std::marker::Sync {
+    fn kind(&self) -> ProviderKind;
+
+    async fn search(
+        &self,
+        query: &str,
+        sort: SearchSort,
+        kind: Option<IndexEntryKind>,
+        game_version: &str,
+        loader: Option<&str>,
+    ) -> Result<Vec<Project>>;
+
+    /// Fetch a project by its id or slug.
+    async fn project(&self, id: &str) -> Result<Project>;
+
+    async fn projects(&self, ids: &[&str]) -> Result<Vec<Project>>;
+
+    /// List the versions of a project that are compatible with `game_version` and `loader`.
+    async fn versions(
+        &self,
+        project_id: &str,
+        kind: IndexEntryKind,
+        game_version: &str,
+        loader: Option<&str>,
+    ) -> Result<Vec<ProjectVersion>>;
+
+    async fn version(&self, id: &str) -> Result<ProjectVersion>;
+
+    async fn versions_by_id(&self, ids: &[&str]) -> Result<Vec<ProjectVersion>>;
+
+    /// Identify files by their hashes, returning the versions they belong to by their sha1.
+    async fn identify(&self, files: &[&FileHashes]) -> Result<HashMap<String, ProjectVersion>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        assert_eq!(
+            ProviderKind::split("curseforge/jei"),
+            (ProviderKind::CurseForge, "jei")
+        );
+        assert_eq!(
+            ProviderKind::split("modrinth/sodium"),
+            (ProviderKind::Modrinth, "sodium")
+        );
+        assert_eq!(
+            ProviderKind::split("sodium"),
+            (ProviderKind::Modrinth, "sodium")
+        );
+        assert_eq!(
+            ProviderKind::split("curseforgejei"),
+            (ProviderKind::Modrinth, "curseforgejei")
+        );
+    }
+}
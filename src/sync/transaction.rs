@@ -0,0 +1,407 @@
+//! Transactions of commands that change content, so that a command that fails halfway can be
+//! undone, and so can one that worked but shouldn't have.
+//!
+//! Files a transaction removes or replaces are moved into its directory instead of being
+//! deleted, next to a snapshot of the index from before the command.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use color_eyre::{
+    Result,
+    eyre::{Context, bail},
+};
+use serde::{Deserialize, Serialize};
+
+use super::SyncIndex;
+
+/// How many transactions are kept for each instance. Older ones are deleted on commit.
+pub const KEEP_TRANSACTIONS: usize = 20;
+
+const RECORD_FILE: &str = "transaction.toml";
+const INDEX_FILE: &str = "index.toml";
+const FILES_DIR: &str = "files";
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum TransactionOp {
+    /// The file was moved into the transaction's directory
+    Removed {
+        path: PathBuf,
+    },
+    /// The file didn't exist before
+    Created {
+        path: PathBuf,
+    },
+    Renamed {
+        from: PathBuf,
+        to: PathBuf,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TransactionRecord {
+    pub id: String,
+    /// What was run, like `-R sodium`
+    pub command: String,
+    pub date: DateTime<Utc>,
+    /// Whether the command finished. Transactions of commands that were killed stay incomplete.
+    #[serde(default)]
+    pub complete: bool,
+    /// Paths are relative to the instance directory
+    #[serde(default)]
+    pub ops: Vec<TransactionOp>,
+}
+
+/// File operations in an instance directory. A tracked transaction records them in a directory
+/// of its own and undoes them if it is dropped before [`Transaction::commit`], an untracked one
+/// just does them.
+pub struct Transaction {
+    root: PathBuf,
+    dir: Option<PathBuf>,
+    record: Option<TransactionRecord>,
+    /// The index from before the transaction, serialized
+    index: String,
+}
+
+impl Transaction {
+    /// Where the transactions of the instance in `minecraft_directory` are kept.
+    pub fn history_dir(minecraft_directory: &Path) -> PathBuf {
+        dirs::data_dir()
+            .expect("data directory not found")
+            .join("startmc")
+            .join("transactions")
+            .join(super::instance_key(minecraft_directory))
+    }
+
+    pub fn untracked(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            dir: None,
+            record: None,
+            index: String::new(),
+        }
+    }
+
+    /// Start recording a transaction of `command` into `history`, with `index` as it is before
+    /// the command changes anything. The index is sorted like it would be when written.
+    pub fn begin(
+        history: &Path,
+        root: &Path,
+        command: &str,
+        index: &mut SyncIndex,
+    ) -> Result<Self> {
+        let date = Utc::now();
+        let base = date.format("%Y%m%d%H%M%S").to_string();
+        let mut id = base.clone();
+        let mut n = 1;
+        while history.join(&id).exists() {
+            n += 1;
+            id = format!("{base}-{n}");
+        }
+        let dir = history.join(&id);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("create transaction directory {}", dir.display()))?;
+        index.sort();
+        let index = toml::to_string(index)?;
+        std::fs::write(dir.join(INDEX_FILE), &index)?;
+
+        let transaction = Self {
+            root: root.to_path_buf(),
+            dir: Some(dir),
+            record: Some(TransactionRecord {
+                id,
+                command: command.to_string(),
+                date,
+                complete: false,
+                ops: vec![],
+            }),
+            index,
+        };
+        transaction.save()?;
+        Ok(transaction)
+    }
+
+    pub fn is_tracked(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    /// The instance directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn save(&self) -> Result<()> {
+        if let (Some(dir), Some(record)) = (&self.dir, &self.record) {
+            std::fs::write(dir.join(RECORD_FILE), toml::to_string(record)?)?;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, op: TransactionOp) -> Result<()> {
+        if let Some(record) = &mut self.record {
+            record.ops.push(op);
+        }
+        self.save()
+    }
+
+    fn relative(&self, path: &Path) -> Result<PathBuf> {
+        match path.strip_prefix(&self.root) {
+            Ok(relative) => Ok(relative.to_path_buf()),
+            Err(_) => bail!("{} is not in the instance directory", path.display()),
+        }
+    }
+
+    /// Remove the file at `path`, keeping it in the transaction.
+    pub fn remove(&mut self, path: &Path) -> Result<()> {
+        debug!("removing {}", path.display());
+        let Some(dir) = &self.dir else {
+            std::fs::remove_file(path)?;
+            return Ok(());
+        };
+        let relative = self.relative(path)?;
+        move_file(path, &dir.join(FILES_DIR).join(&relative))
+            .with_context(|| format!("remove {}", path.display()))?;
+        self.push(TransactionOp::Removed { path: relative })
+    }
+
+    pub fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
+        std::fs::rename(from, to)
+            .with_context(|| format!("rename {} to {}", from.display(), to.display()))?;
+        if self.is_tracked() {
+            let op = TransactionOp::Renamed {
+                from: self.relative(from)?,
+                to: self.relative(to)?,
+            };
+            self.push(op)?;
+        }
+        Ok(())
+    }
+
+    /// Make way for a file that is about to be written to `path`. A file that is already there
+    /// is removed, and the new one is removed again when the transaction is rolled back.
+    pub fn create(&mut self, path: &Path) -> Result<()> {
+        if path.exists() {
+            self.remove(path)?;
+        }
+        if self.is_tracked() {
+            let relative = self.relative(path)?;
+            self.push(TransactionOp::Created { path: relative })?;
+        }
+        Ok(())
+    }
+
+    /// Finish the transaction, after `index` was written. Transactions that changed nothing
+    /// are not kept. The transaction is untracked afterwards.
+    pub fn commit(&mut self, index: &SyncIndex) -> Result<()> {
+        let (Some(dir), Some(mut record)) = (self.dir.take(), self.record.take()) else {
+            return Ok(());
+        };
+        if record.ops.is_empty() && toml::to_string(index)? == self.index {
+            std::fs::remove_dir_all(&dir)?;
+            return Ok(());
+        }
+        record.complete = true;
+        std::fs::write(dir.join(RECORD_FILE), toml::to_string(&record)?)?;
+
+        if let Some(history) = dir.parent() {
+            for old in list(history)?.iter().skip(KEEP_TRANSACTIONS) {
+                std::fs::remove_dir_all(history.join(&old.id))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        let (Some(dir), Some(record)) = (self.dir.take(), self.record.take()) else {
+            return;
+        };
+        warn!("rolling back unfinished transaction {}", record.id);
+        if let Err(err) = undo(&dir, &self.root, &record) {
+            error!("failed to roll back transaction {}: {err}", record.id);
+            return;
+        }
+        if let Err(err) = std::fs::remove_dir_all(&dir) {
+            warn!("can't remove transaction {}: {err}", dir.display());
+        }
+    }
+}
+
+/// Move a file, copying it if it can't be renamed because it goes to another file system.
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if std::fs::rename(from, to).is_err() {
+        std::fs::copy(from, to)?;
+        std::fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Undo the file operations of `record`, last first.
+fn undo(dir: &Path, root: &Path, record: &TransactionRecord) -> Result<()> {
+    for op in record.ops.iter().rev() {
+        match op {
+            TransactionOp::Removed { path } => {
+                move_file(&dir.join(FILES_DIR).join(path), &root.join(path))
+                    .with_context(|| format!("restore {}", path.display()))?;
+            }
+            TransactionOp::Created { path } => {
+                let path = root.join(path);
+                if path.exists() {
+                    std::fs::remove_file(&path)
+                        .with_context(|| format!("remove {}", path.display()))?;
+                }
+            }
+            TransactionOp::Renamed { from, to } => {
+                std::fs::rename(root.join(to), root.join(from))
+                    .with_context(|| format!("rename {} back", to.display()))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The transactions in `history`, newest first.
+pub fn list(history: &Path) -> Result<Vec<TransactionRecord>> {
+    if !history.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut records = vec![];
+    for entry in std::fs::read_dir(history)? {
+        let path = entry?.path().join(RECORD_FILE);
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        match toml::from_str::<TransactionRecord>(&contents) {
+            Ok(record) => records.push(record),
+            Err(err) => warn!("ignoring broken transaction {}: {err}", path.display()),
+        }
+    }
+    records.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| b.id.cmp(&a.id)));
+    Ok(records)
+}
+
+/// Roll back the transaction `id` in `history` and every transaction after it, newest first,
+/// restoring the files in `root` and the index of the instance config at `normal_config_path`.
+/// Returns the transactions that were rolled back.
+pub fn rollback(
+    history: &Path,
+    root: &Path,
+    normal_config_path: &Path,
+    id: &str,
+) -> Result<Vec<TransactionRecord>> {
+    let records = list(history)?;
+    let Some(position) = records.iter().position(|it| it.id == id) else {
+        bail!("there is no transaction {id}");
+    };
+    let records = records.into_iter().take(position + 1).collect::<Vec<_>>();
+    for record in &records {
+        let dir = history.join(&record.id);
+        undo(&dir, root, record).with_context(|| format!("roll back transaction {}", record.id))?;
+        let mut index: SyncIndex = toml::from_str(&std::fs::read_to_string(dir.join(INDEX_FILE))?)?;
+        index.write(normal_config_path)?;
+        std::fs::remove_dir_all(&dir)?;
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::fixtures::entry;
+
+    fn write(path: &Path, contents: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_rollback() {
+        let root = tempfile::tempdir().unwrap();
+        let history = tempfile::tempdir().unwrap();
+        let config_path = root.path().join("instance.toml");
+        let mods = root.path().join("mods");
+        write(&mods.join("sodium-0.5.3.jar"), "sodium 0.5.3");
+        write(&mods.join("lithium.jar"), "lithium");
+        let mut index = SyncIndex::default();
+        index
+            .packages
+            .push(entry("sodium", "0.5.3", "sodium-0.5.3.jar", true));
+        index
+            .packages
+            .push(entry("lithium", "0.11.2", "lithium.jar", true));
+        index.write(&config_path).unwrap();
+
+        let mut transaction =
+            Transaction::begin(history.path(), root.path(), "-Syu", &mut index).unwrap();
+        transaction.remove(&mods.join("sodium-0.5.3.jar")).unwrap();
+        transaction.create(&mods.join("sodium-0.5.4.jar")).unwrap();
+        write(&mods.join("sodium-0.5.4.jar"), "sodium 0.5.4");
+        transaction
+            .rename(
+                &mods.join("lithium.jar"),
+                &mods.join("lithium.jar.disabled"),
+            )
+            .unwrap();
+        let sodium = index.packages.iter_mut().find(|it| it.id == "sodium");
+        let sodium = sodium.unwrap();
+        sodium.file = "sodium-0.5.4.jar".to_string();
+        sodium.version = "0.5.4".to_string();
+        index.write(&config_path).unwrap();
+        transaction.commit(&index).unwrap();
+
+        // a transaction that changes nothing isn't kept
+        let mut transaction =
+            Transaction::begin(history.path(), root.path(), "-D --hold", &mut index).unwrap();
+        transaction.commit(&index).unwrap();
+
+        let records = list(history.path()).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].command, "-Syu");
+        assert!(records[0].complete);
+        assert_eq!(records[0].ops.len(), 3);
+        assert!(!mods.join("sodium-0.5.3.jar").exists());
+
+        rollback(history.path(), root.path(), &config_path, &records[0].id).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(mods.join("sodium-0.5.3.jar")).unwrap(),
+            "sodium 0.5.3"
+        );
+        assert!(!mods.join("sodium-0.5.4.jar").exists());
+        assert!(mods.join("lithium.jar").exists());
+        let index = SyncIndex::read(&config_path).unwrap();
+        let sodium = index.packages.iter().find(|it| it.id == "sodium").unwrap();
+        assert_eq!(sodium.version, "0.5.3");
+        assert!(list(history.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unfinished() {
+        let root = tempfile::tempdir().unwrap();
+        let history = tempfile::tempdir().unwrap();
+        let sodium = root.path().join("mods/sodium.jar");
+        write(&sodium, "sodium");
+
+        let mut transaction = Transaction::begin(
+            history.path(),
+            root.path(),
+            "-R sodium",
+            &mut SyncIndex::default(),
+        )
+        .unwrap();
+        transaction.remove(&sodium).unwrap();
+        assert!(!sodium.exists());
+        drop(transaction);
+        assert!(sodium.exists());
+        assert!(list(history.path()).unwrap().is_empty());
+
+        let mut transaction = Transaction::untracked(root.path());
+        transaction.remove(&sodium).unwrap();
+        drop(transaction);
+        assert!(!sodium.exists());
+    }
+}
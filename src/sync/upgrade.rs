@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use color_eyre::Result;
+use indicatif::{ProgressBar, ProgressFinish, ProgressStyle};
+use startmc_downloader::ProgressBarOpts;
+use version_compare::Cmp;
+
+use super::{ProjectVersion, ResolvedPackage, Sync, SyncIndexEntry};
+
+/// A version picked by [`pick_upgrade`].
+#[derive(Debug, Clone)]
+pub enum Pick {
+    Upgrade(ProjectVersion),
+    /// The installed version isn't compatible with the instance anymore, and every compatible
+    /// version is older than it.
+    Downgrade(ProjectVersion),
+}
+
+impl Pick {
+    pub fn version(&self) -> &ProjectVersion {
+        match self {
+            Pick::Upgrade(version) | Pick::Downgrade(version) => version,
+        }
+    }
+}
+
+/// A package that `-Syu` would upgrade, or downgrade.
+#[derive(Debug, Clone)]
+pub struct Upgrade {
+    pub package: ResolvedPackage,
+    pub downgrade: bool,
+}
+
+/// Pick the version to upgrade `entry` to out of its compatible `versions`, if any.
+///
+/// The installed version is kept if it's the newest one. If the installed version isn't
+/// compatible with the instance anymore, the newest compatible one is picked, and it's a
+/// downgrade unless its version number is higher than the installed one.
+pub fn pick_upgrade(entry: &SyncIndexEntry, versions: Vec<ProjectVersion>) -> Option<Pick> {
+    let installed = versions
+        .iter()
+        .find(|it| entry.version_id.as_deref() == Some(it.id.as_str()))
+        .map(|it| it.date_published);
+    let newest = super::newest_matching(versions, None)?;
+    match installed {
+        Some(installed) if installed >= newest.date_published => None,
+        Some(_) => Some(Pick::Upgrade(newest)),
+        None => match version_compare::compare(&newest.version_number, &entry.version) {
+            Ok(Cmp::Gt) => Some(Pick::Upgrade(newest)),
+            Ok(Cmp::Eq) => None,
+            _ => Some(Pick::Downgrade(newest)),
+        },
+    }
+}
+
+/// Upgrading content
+impl Sync {
+    /// Find the newest compatible versions of every package in the index that came from a provider.
+    pub async fn find_upgrades(&self) -> Result<Vec<Upgrade>> {
+        let entries = self
+            .index
+            .packages
+            .iter()
+            .filter(|it| it.provider.is_some())
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut projects = HashMap::new();
+        for kind in entries
+            .iter()
+            .filter_map(|it| it.provider)
+            .collect::<std::collections::BTreeSet<_>>()
+        {
+            let provider = match self.provider(kind) {
+                Ok(provider) => provider,
+                Err(err) => {
+                    warn!("{err}, skipping {kind} packages...");
+                    continue;
+                }
+            };
+            let ids = entries
+                .iter()
+                .filter(|it| it.provider == Some(kind))
+                .filter_map(|it| it.project_id.as_deref())
+                .collect::<Vec<_>>();
+            projects.extend(
+                provider
+                    .projects(&ids)
+                    .await?
+                    .into_iter()
+                    .map(|it| ((kind, it.id.clone()), it)),
+            );
+        }
+
+        let progress = ProgressBar::new(entries.len() as u64).with_finish(ProgressFinish::AndClear);
+        progress.set_style(
+            ProgressStyle::default_bar()
+                .template("{wide_msg} [{bar:69}] {percent}%")
+                .unwrap()
+                .progress_chars(ProgressBarOpts::CHARS_HASHTAG),
+        );
+        progress.set_message("Checking for upgrades...");
+
+        let mut upgrades = vec![];
+        for entry in entries {
+            progress.inc(1);
+            let provider = entry.provider.unwrap();
+            let project_id = entry.project_id.clone().unwrap_or_default();
+            let Some(project) = projects.remove(&(provider, project_id)) else {
+                warn!("{} not found on {provider}, skipping...", entry.id);
+                continue;
+            };
+            let versions = self
+                .compatible_versions(provider, &project.id, entry.kind)
+                .await?;
+            if let Some(pick) = pick_upgrade(entry, versions) {
+                let downgrade = matches!(pick, Pick::Downgrade(_));
+                let (Pick::Upgrade(version) | Pick::Downgrade(version)) = pick;
+                upgrades.push(Upgrade {
+                    package: ResolvedPackage {
+                        provider,
+                        project,
+                        version,
+                        kind: entry.kind,
+                    },
+                    downgrade,
+                });
+            }
+        }
+        progress.finish_and_clear();
+
+        Ok(upgrades)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{
+            IndexEntryKind, ProviderKind, SyncIndex,
+            fake::FakeProvider,
+            fixtures::{entry, project, sync, version},
+        },
+        *,
+    };
+
+    #[test]
+    fn test_pick_upgrade() {
+        let installed = entry("sodium", "0.5.0", "sodium-0.5.0.jar", true);
+        let versions = vec![
+            version("sodium-project", "0.5.0", "2023-06-01T00:00:00Z", &[]),
+            version("sodium-project", "0.5.3", "2023-09-01T00:00:00Z", &[]),
+        ];
+        assert_eq!(
+            pick_upgrade(&installed, versions.clone())
+                .unwrap()
+                .version()
+                .version_number,
+            "0.5.3"
+        );
+
+        let newest = entry("sodium", "0.5.3", "sodium-0.5.3.jar", true);
+        assert!(pick_upgrade(&newest, versions.clone()).is_none());
+
+        // installed version is not compatible with the instance anymore
+        let incompatible = entry("sodium", "0.6.0", "sodium-0.6.0.jar", true);
+        assert!(matches!(
+            pick_upgrade(&incompatible, versions.clone()),
+            Some(Pick::Downgrade(version)) if version.version_number == "0.5.3"
+        ));
+
+        // an older installed version that isn't compatible anymore is still upgraded
+        let old = entry("sodium", "0.4.0", "sodium-0.4.0.jar", true);
+        assert!(matches!(
+            pick_upgrade(&old, versions),
+            Some(Pick::Upgrade(version)) if version.version_number == "0.5.3"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_find_upgrades() {
+        let provider = FakeProvider::new(
+            vec![project("sodium-project", "sodium", IndexEntryKind::Mod)],
+            vec![
+                version("sodium-project", "0.5.0", "2023-06-01T00:00:00Z", &[]),
+                version("sodium-project", "0.5.3", "2023-09-01T00:00:00Z", &[]),
+            ],
+        );
+        let mut jei = entry("jei", "15.3.0.4", "jei.jar", true);
+        jei.provider = Some(ProviderKind::CurseForge);
+        let index = SyncIndex {
+            packages: vec![
+                entry("sodium", "0.5.0", "sodium-0.5.0.jar", true),
+                entry("custom", "1.0", "custom.jar", false),
+                // there's no CurseForge provider, so this is skipped
+                jei,
+            ],
+            ..Default::default()
+        };
+        let sync = sync(index, provider, "/nonexistent".into());
+
+        let upgrades = sync.find_upgrades().await.unwrap();
+        assert_eq!(upgrades.len(), 1);
+        assert_eq!(upgrades[0].package.to_string(), "sodium-0.5.3");
+        assert!(!upgrades[0].downgrade);
+    }
+}
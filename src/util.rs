@@ -4,5 +4,8 @@ pub use dialoguer::*;
 mod format;
 pub use format::*;
 
+mod fs;
+pub use fs::*;
+
 mod spin;
 pub use spin::*;
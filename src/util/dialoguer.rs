@@ -13,3 +13,14 @@ pub fn ask_yn(prompt: impl Into<String>) -> color_eyre::Result<bool> {
         .with_prompt(prompt)
         .interact()?)
 }
+
+/// Like [`ask_yn`], but answering no by default.
+pub fn ask_ny(prompt: impl Into<String>) -> color_eyre::Result<bool> {
+    let theme = dialoguer_theme();
+
+    Ok(dialoguer::Confirm::with_theme(&theme)
+        .default(false)
+        .show_default(true)
+        .with_prompt(prompt)
+        .interact()?)
+}
@@ -6,11 +6,11 @@ pub fn cols() -> String {
     "::".blue().bold().to_string()
 }
 
-pub fn arrow_error<'a>(text: impl Display) {
+pub fn arrow_error(text: impl Display) {
     println!("{} {}", "==> ERROR:".red().bold(), text.bold())
 }
 
-pub fn arrow_warn<'a>(text: impl Display) {
+pub fn arrow_warn(text: impl Display) {
     println!("{} {}", "==> WARNING:".yellow().bold(), text.bold())
 }
 
@@ -18,6 +18,10 @@ pub fn pacman_warn(text: impl Display) {
     println!("{} {text}", "warning:".yellow().bold())
 }
 
+pub fn pacman_error(text: impl Display) {
+    println!("{} {text}", "error:".red().bold())
+}
+
 pub fn green_arrow() -> String {
     "==>".green().bold().to_string()
 }
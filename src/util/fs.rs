@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+
+/// All files under `dir`, recursively.
+pub fn walk_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(walk_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// The size of all files under `dir` in bytes.
+pub fn dir_size(dir: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for file in walk_files(dir)? {
+        size += std::fs::metadata(file)?.len();
+    }
+    Ok(size)
+}
+
+/// Copy everything under `from` into `to`, except the paths `skip` returns `true` for, relative
+/// to `from`.
+pub fn copy_dir(from: &Path, to: &Path, skip: impl Fn(&Path) -> bool) -> std::io::Result<()> {
+    for file in walk_files(from)? {
+        let relative = file
+            .strip_prefix(from)
+            .expect("walked file is in the directory");
+        if skip(relative) {
+            continue;
+        }
+        let target = to.join(relative);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(&file, &target)?;
+    }
+    Ok(())
+}
+
+/// Create a symbolic link at `link` to the directory `target`.
+pub fn symlink_dir(target: &Path, link: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(target, link);
+    #[cfg(windows)]
+    return std::os::windows::fs::symlink_dir(target, link);
+}
+
+/// Move the directory `from` to `to`. If they're on different filesystems, it's copied and the
+/// original is removed, keeping links to directories as links.
+pub fn move_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    match std::fs::rename(from, to) {
+        Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+            copy_tree(from, to)?;
+            std::fs::remove_dir_all(from)
+        }
+        result => result,
+    }
+}
+
+fn copy_tree(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let path = entry.path();
+        let target = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() && path.is_dir() {
+            symlink_dir(&std::fs::read_link(&path)?, &target)?;
+        } else if file_type.is_dir() {
+            copy_tree(&path, &target)?;
+        } else {
+            std::fs::copy(&path, &target)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,197 @@
+//! The worlds of an instance, as the game describes them in their `level.dat`.
+
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use color_eyre::{Result, eyre::ContextCompat};
+
+use crate::nbt::{self, Tag};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameMode {
+    Survival,
+    Creative,
+    Adventure,
+    Spectator,
+}
+
+impl std::fmt::Display for GameMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            GameMode::Survival => "Survival",
+            GameMode::Creative => "Creative",
+            GameMode::Adventure => "Adventure",
+            GameMode::Spectator => "Spectator",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct World {
+    /// Name of the world's directory in `saves`
+    pub folder: String,
+    /// The name the game shows
+    pub name: String,
+    pub game_mode: GameMode,
+    pub hardcore: bool,
+    pub last_played: Option<DateTime<Utc>>,
+    /// The Minecraft version that last saved the world, which worlds from before 1.9 don't have
+    pub version: Option<String>,
+}
+
+impl World {
+    /// Read the world in the directory `dir`.
+    pub fn read(dir: &Path) -> Result<Self> {
+        let root = Tag::Compound(nbt::read_file(&dir.join("level.dat"))?);
+        let data = root.get("Data").context("level.dat has no Data")?;
+        let folder = dir
+            .file_name()
+            .map(|it| it.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(Self {
+            name: data
+                .get("LevelName")
+                .and_then(Tag::as_str)
+                .unwrap_or(&folder)
+                .to_string(),
+            game_mode: match data.get("GameType").and_then(Tag::as_i64) {
+                Some(1) => GameMode::Creative,
+                Some(2) => GameMode::Adventure,
+                Some(3) => GameMode::Spectator,
+                _ => GameMode::Survival,
+            },
+            hardcore: data
+                .get("hardcore")
+                .and_then(Tag::as_i64)
+                .is_some_and(|it| it != 0),
+            last_played: data
+                .get("LastPlayed")
+                .and_then(Tag::as_i64)
+                .and_then(DateTime::from_timestamp_millis),
+            version: data
+                .get("Version")
+                .and_then(|it| it.get("Name"))
+                .and_then(Tag::as_str)
+                .map(|it| it.to_string()),
+            folder,
+        })
+    }
+
+    /// Whether the world was last saved by a newer version of the game than `game_version`,
+    /// going by the order of `versions`, newest first, like in Mojang's version manifest.
+    /// Versions that aren't in `versions` can't be compared, and aren't newer.
+    pub fn is_newer_than(&self, game_version: &str, versions: &[&str]) -> bool {
+        let position = |version: &str| versions.iter().position(|it| *it == version);
+        match (
+            self.version.as_deref().and_then(position),
+            position(game_version),
+        ) {
+            (Some(world), Some(instance)) => world < instance,
+            _ => false,
+        }
+    }
+}
+
+/// Names of the world directories of the instance in `minecraft_directory`, sorted.
+pub fn folders(minecraft_directory: &Path) -> Result<Vec<String>> {
+    let saves = minecraft_directory.join("saves");
+    if !saves.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut worlds = vec![];
+    for entry in std::fs::read_dir(saves)? {
+        let entry = entry?;
+        if entry.path().is_dir() {
+            worlds.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    worlds.sort();
+    Ok(worlds)
+}
+
+/// Read every world of the instance in `minecraft_directory`, the last played first like the
+/// game lists them. Directories without a readable `level.dat` are skipped.
+pub fn read_all(minecraft_directory: &Path) -> Result<Vec<World>> {
+    let mut worlds = vec![];
+    for folder in folders(minecraft_directory)? {
+        match World::read(&minecraft_directory.join("saves").join(&folder)) {
+            Ok(world) => worlds.push(world),
+            Err(err) => warn!("can't read world {folder}: {err}"),
+        }
+    }
+    worlds.sort_by_key(|it| std::cmp::Reverse(it.last_played));
+    Ok(worlds)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{Compression, write::GzEncoder};
+
+    use super::*;
+
+    /// A gzipped `level.dat` with a `Data` compound, written by hand.
+    fn level_dat(name: &str, game_type: i32, hardcore: bool, version: &str) -> Vec<u8> {
+        let string = |bytes: &mut Vec<u8>, it: &str| {
+            bytes.extend((it.len() as u16).to_be_bytes());
+            bytes.extend(it.as_bytes());
+        };
+        let mut bytes = vec![10, 0, 0, 10];
+        string(&mut bytes, "Data");
+        bytes.push(8);
+        string(&mut bytes, "LevelName");
+        string(&mut bytes, name);
+        bytes.push(3);
+        string(&mut bytes, "GameType");
+        bytes.extend(game_type.to_be_bytes());
+        bytes.push(1);
+        string(&mut bytes, "hardcore");
+        bytes.push(hardcore as u8);
+        bytes.push(4);
+        string(&mut bytes, "LastPlayed");
+        bytes.extend(1_700_000_000_000i64.to_be_bytes());
+        bytes.push(10);
+        string(&mut bytes, "Version");
+        bytes.push(8);
+        string(&mut bytes, "Name");
+        string(&mut bytes, version);
+        bytes.extend([0, 0, 0]);
+
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_read_all() {
+        let dir = tempfile::tempdir().unwrap();
+        let saves = dir.path().join("saves");
+        std::fs::create_dir_all(saves.join("New World")).unwrap();
+        std::fs::write(
+            saves.join("New World/level.dat"),
+            level_dat("Hardcore Run", 0, true, "1.21"),
+        )
+        .unwrap();
+        std::fs::create_dir_all(saves.join("broken")).unwrap();
+
+        assert_eq!(folders(dir.path()).unwrap(), vec!["New World", "broken"]);
+        let worlds = read_all(dir.path()).unwrap();
+        assert_eq!(worlds.len(), 1);
+        let world = &worlds[0];
+        assert_eq!(world.folder, "New World");
+        assert_eq!(world.name, "Hardcore Run");
+        assert_eq!(world.game_mode, GameMode::Survival);
+        assert!(world.hardcore);
+        assert_eq!(
+            world.last_played.unwrap().to_rfc3339(),
+            "2023-11-14T22:13:20+00:00"
+        );
+        assert_eq!(world.version.as_deref(), Some("1.21"));
+
+        let versions = ["1.21", "24w14a", "1.20.1", "1.20"];
+        assert!(world.is_newer_than("1.20.1", &versions));
+        assert!(!world.is_newer_than("1.21", &versions));
+        assert!(!world.is_newer_than("b1.7.3", &versions));
+    }
+}